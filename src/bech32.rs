@@ -0,0 +1,145 @@
+const CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc830a3;
+
+fn polymod(values: &[u8]) -> u32 {
+    let generator = [0x3b6a57b2u32, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut chk = 1u32;
+    for v in values {
+        let b = chk >> 25;
+        chk = ((chk & 0x1ffffff) << 5) ^ (*v as u32);
+        for (i, g) in generator.iter().enumerate() {
+            if (b >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+
+    chk
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut result: Vec<u8> = hrp.bytes().map(|c| c >> 5).collect();
+    result.push(0);
+    result.extend(hrp.bytes().map(|c| c & 31));
+
+    result
+}
+
+/// regroup bits, e.g. from 8 bits per item to 5 bits per item
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc = 0u32;
+    let mut bits = 0u32;
+    let max_v = (1u32 << to) - 1;
+    let mut result = vec![];
+
+    for value in data {
+        if (*value as u32) >> from != 0 {
+            return None;
+        }
+        acc = (acc << from) | (*value as u32);
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            result.push(((acc >> bits) & max_v) as u8);
+        }
+    }
+
+    if pad {
+        if bits > 0 {
+            result.push(((acc << (to - bits)) & max_v) as u8);
+        }
+    } else if bits >= from || ((acc << (to - bits)) & max_v) != 0 {
+        return None;
+    }
+
+    Some(result)
+}
+
+/// encode a segwit address, bech32 for version 0 and bech32m for later versions (BIP173, BIP350)
+pub fn encode_segwit_address(hrp: &str, version: u8, program: &[u8]) -> String {
+    let mut data = vec![version];
+    data.extend_from_slice(&convert_bits(program, 8, 5, true).unwrap());
+
+    let constant = if version == 0 { BECH32_CONST } else { BECH32M_CONST };
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(&data);
+    values.extend_from_slice(&[0u8; 6]);
+    let checksum = polymod(&values) ^ constant;
+
+    let mut result = format!("{}1", hrp);
+    for d in &data {
+        result.push(CHARSET[*d as usize] as char);
+    }
+    for i in 0..6 {
+        result.push(CHARSET[((checksum >> (5 * (5 - i))) & 31) as usize] as char);
+    }
+
+    result
+}
+
+/// decode a segwit address, returns the hrp, witness version and witness program
+pub fn decode_segwit_address(address: &str) -> Option<(String, u8, Vec<u8>)> {
+    if address.to_lowercase() != address && address.to_uppercase() != address {
+        return None;
+    }
+    let address = address.to_lowercase();
+
+    let pos = address.rfind('1')?;
+    if pos < 1 || pos + 7 > address.len() || address.len() > 90 {
+        return None;
+    }
+
+    let hrp = &address[..pos];
+    let mut data = vec![];
+    for c in address[(pos + 1)..].bytes() {
+        data.push(CHARSET.iter().position(|x| *x == c)? as u8);
+    }
+
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(&data);
+    let constant = polymod(&values);
+
+    let data = &data[..(data.len() - 6)];
+    let version = *data.first()?;
+    if version > 16 {
+        return None;
+    }
+    if (version == 0 && constant != BECH32_CONST) || (version != 0 && constant != BECH32M_CONST) {
+        return None;
+    }
+
+    let program = convert_bits(&data[1..], 5, 8, false)?;
+    if program.len() < 2 || program.len() > 40 || (version == 0 && program.len() != 20 && program.len() != 32) {
+        return None;
+    }
+
+    Some((hrp.to_string(), version, program))
+}
+
+
+#[cfg(test)]
+mod tests_bech32 {
+    use hex::ToHex;
+
+    use super::{encode_segwit_address, decode_segwit_address};
+
+    #[test]
+    fn test_segwit_address() {
+        let address = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+        let (hrp, version, program) = decode_segwit_address(address).unwrap();
+        assert_eq!("bc", hrp);
+        assert_eq!(0, version);
+        assert_eq!("751e76e8199196d454941c45d1b3a323f1433bd6", program.encode_hex::<String>());
+        assert_eq!(address, encode_segwit_address(&hrp, version, &program));
+
+        let address = "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0";
+        let (hrp, version, program) = decode_segwit_address(address).unwrap();
+        assert_eq!(1, version);
+        assert_eq!(address, encode_segwit_address(&hrp, version, &program));
+
+        // version 1 encoded with the bech32 constant
+        assert!(decode_segwit_address("bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd").is_none());
+    }
+}
@@ -0,0 +1,799 @@
+use std::fmt::Display;
+
+use hex::ToHex;
+use num_bigint::BigInt;
+
+use crate::{
+    bech32::{encode_segwit_address, decode_segwit_address},
+    extended_key::{ExtendedPrivKey, ExtendedPubKey, DerivationPath, Bip32Error, HARDENED, curve_order},
+    network::Network,
+    private_key::PrivateKey,
+    s256point::S256Point,
+    script::{Script, ScriptCmd},
+    utils::{hash160, sha256, tagged_hash, encode_base58_checksum, decode_base58_checksum, u8_slice_base58_to_string},
+};
+
+
+const INPUT_CHARSET: &[u8] = b"0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// leaf version of tapscript leaves
+const TAPROOT_LEAF_TAPSCRIPT: u8 = 0xc0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DescriptorError {
+    InvalidChecksum(String),
+    Syntax(String),
+    UnknownFunction(String),
+    /// the function is not allowed inside its parent, e.g. `wpkh()` inside `wsh()`
+    InvalidContext(String),
+    InvalidKey(String),
+    InvalidThreshold(usize, usize),
+    InvalidAddress(String),
+    Derivation(Bip32Error),
+    /// the descriptor has no address form, e.g. `raw()` or bare `multi()`
+    NoAddress,
+}
+
+impl Display for DescriptorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DescriptorError::InvalidChecksum(c) => write!(f, "invalid checksum: {}", c),
+            DescriptorError::Syntax(s) => write!(f, "syntax error: {}", s),
+            DescriptorError::UnknownFunction(name) => write!(f, "unknown function: {}", name),
+            DescriptorError::InvalidContext(name) => write!(f, "{}() not allowed here", name),
+            DescriptorError::InvalidKey(k) => write!(f, "invalid key: {}", k),
+            DescriptorError::InvalidThreshold(k, n) => write!(f, "invalid threshold {} of {}", k, n),
+            DescriptorError::InvalidAddress(a) => write!(f, "invalid address: {}", a),
+            DescriptorError::Derivation(e) => write!(f, "derivation failed: {}", e),
+            DescriptorError::NoAddress => write!(f, "descriptor has no address"),
+        }
+    }
+}
+
+impl From<Bip32Error> for DescriptorError {
+    fn from(e: Bip32Error) -> Self {
+        DescriptorError::Derivation(e)
+    }
+}
+
+/// where an expression appears, decides which functions and key formats are allowed
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Context {
+    Top,
+    Sh,
+    Wsh,
+    Tap,
+}
+
+fn polymod(symbols: &[u64]) -> u64 {
+    let generator = [0xf5dee51989u64, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd];
+    let mut chk = 1u64;
+    for value in symbols {
+        let top = chk >> 35;
+        chk = ((chk & 0x7ffffffff) << 5) ^ value;
+        for (i, g) in generator.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+
+    chk
+}
+
+/// the 8 characters descriptor checksum (BIP380), None if `s` has characters outside the input charset
+pub fn descriptor_checksum(s: &str) -> Option<String> {
+    let mut symbols: Vec<u64> = vec![];
+    let mut groups: Vec<u64> = vec![];
+    for c in s.bytes() {
+        let v = INPUT_CHARSET.iter().position(|x| *x == c)? as u64;
+        symbols.push(v & 31);
+        groups.push(v >> 5);
+        if groups.len() == 3 {
+            symbols.push(groups[0] * 9 + groups[1] * 3 + groups[2]);
+            groups.clear();
+        }
+    }
+    if groups.len() == 1 {
+        symbols.push(groups[0]);
+    } else if groups.len() == 2 {
+        symbols.push(groups[0] * 3 + groups[1]);
+    }
+    symbols.extend_from_slice(&[0u64; 8]);
+
+    let checksum = polymod(&symbols) ^ 1;
+    let mut result = String::new();
+    for i in 0..8 {
+        result.push(CHECKSUM_CHARSET[((checksum >> (5 * (7 - i))) & 31) as usize] as char);
+    }
+
+    Some(result)
+}
+
+/// split `name(arg, arg)` into the name and its top-level arguments
+pub(crate) fn split_call(s: &str) -> Result<(&str, Vec<&str>), DescriptorError> {
+    let open = s.find('(').ok_or_else(|| DescriptorError::Syntax(s.to_string()))?;
+    if !s.ends_with(')') {
+        return Err(DescriptorError::Syntax(s.to_string()));
+    }
+
+    let name = &s[..open];
+    let args = split_args(&s[(open + 1)..(s.len() - 1)])?;
+
+    Ok((name, args))
+}
+
+/// split on commas which are not nested inside (), [] or {}
+fn split_args(s: &str) -> Result<Vec<&str>, DescriptorError> {
+    let mut args = vec![];
+    let mut depth = 0i32;
+    let mut start = 0usize;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(&s[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+        if depth < 0 {
+            return Err(DescriptorError::Syntax(s.to_string()));
+        }
+    }
+    if depth != 0 {
+        return Err(DescriptorError::Syntax(s.to_string()));
+    }
+    if !s.is_empty() {
+        args.push(&s[start..]);
+    }
+
+    Ok(args)
+}
+
+fn path_suffix(path: &DerivationPath) -> String {
+    path.to_string()[1..].to_string()
+}
+
+
+// -- DescriptorKey --
+
+/// key origin `[fingerprint/path]` describing where a key was derived from
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyOrigin {
+    pub fingerprint: Vec<u8>,
+    pub path: DerivationPath,
+}
+
+/// trailing `/*` of a ranged key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wildcard {
+    None,
+    Unhardened,
+    Hardened,
+}
+
+#[derive(Clone)]
+pub enum KeySource {
+    /// hex encoded SEC key, or a 32 bytes x-only key inside `tr()`
+    Public { key: S256Point, compressed: bool, xonly: bool },
+    /// WIF encoded private key
+    Wif { key: PrivateKey, compressed: bool, network: Network },
+    XPub { xkey: ExtendedPubKey, path: DerivationPath, wildcard: Wildcard },
+    XPrv { xkey: ExtendedPrivKey, path: DerivationPath, wildcard: Wildcard },
+}
+
+#[derive(Clone)]
+pub struct DescriptorKey {
+    pub origin: Option<KeyOrigin>,
+    pub source: KeySource,
+}
+
+impl DescriptorKey {
+    fn parse(s: &str, ctx: Context) -> Result<Self, DescriptorError> {
+        let invalid = || DescriptorError::InvalidKey(s.to_string());
+
+        let mut rest = s;
+        let mut origin = None;
+        if let Some(stripped) = s.strip_prefix('[') {
+            let end = stripped.find(']').ok_or_else(invalid)?;
+            let inner = &stripped[..end];
+            let (fingerprint, path) = match inner.find('/') {
+                Some(i) => (&inner[..i], &inner[i..]),
+                None => (inner, ""),
+            };
+            if fingerprint.len() != 8 {
+                return Err(invalid());
+            }
+
+            origin = Some(KeyOrigin {
+                fingerprint: hex::decode(fingerprint).map_err(|_| invalid())?,
+                path: DerivationPath::parse(&format!("m{}", path))?,
+            });
+            rest = &stripped[(end + 1)..];
+        }
+
+        let mut parts = rest.split('/');
+        let key = parts.next().ok_or_else(invalid)?;
+        let mut steps: Vec<&str> = parts.collect();
+
+        let mut wildcard = Wildcard::None;
+        match steps.last() {
+            Some(&"*") => wildcard = Wildcard::Unhardened,
+            Some(&"*'") | Some(&"*h") => wildcard = Wildcard::Hardened,
+            _ => {},
+        }
+        if wildcard != Wildcard::None {
+            steps.pop();
+        }
+        let path = if steps.is_empty() {
+            DerivationPath::default()
+        } else {
+            DerivationPath::parse(&format!("m/{}", steps.join("/")))?
+        };
+
+        let is_extended = key.starts_with("xpub") || key.starts_with("tpub") || key.starts_with("xprv") || key.starts_with("tprv");
+        if !is_extended && (!steps.is_empty() || wildcard != Wildcard::None) {
+            return Err(invalid());
+        }
+
+        let source = if key.starts_with("xpub") || key.starts_with("tpub") {
+            KeySource::XPub { xkey: ExtendedPubKey::parse(key)?, path, wildcard }
+        } else if key.starts_with("xprv") || key.starts_with("tprv") {
+            KeySource::XPrv { xkey: ExtendedPrivKey::parse(key)?, path, wildcard }
+        } else if let Ok(sec) = hex::decode(key) {
+            // the point constructors panic off the curve
+            let xonly_sec = [&[2u8][..], &sec].concat();
+            match (sec.len(), sec.first()) {
+                (32, _) if ctx == Context::Tap && S256Point::is_valid_sec(&xonly_sec) => {
+                    KeySource::Public { key: S256Point::lift_x(&sec), compressed: true, xonly: true }
+                },
+                (33, _) if S256Point::is_valid_sec(&sec) => KeySource::Public { key: S256Point::parse(sec), compressed: true, xonly: false },
+                (65, _) if S256Point::is_valid_sec(&sec) => KeySource::Public { key: S256Point::parse(sec), compressed: false, xonly: false },
+                _ => return Err(invalid()),
+            }
+        } else {
+            let data = decode_base58_checksum(key.as_bytes()).ok_or_else(invalid)?;
+            let compressed = match data.len() {
+                33 => false,
+                34 if data[33] == 1 => true,
+                _ => return Err(invalid()),
+            };
+            let network = if data[0] == Network::Mainnet.wif_prefix() {
+                Network::Mainnet
+            } else if data[0] == Network::Testnet.wif_prefix() {
+                Network::Testnet
+            } else {
+                return Err(invalid());
+            };
+            let secret = BigInt::from_bytes_be(num_bigint::Sign::Plus, &data[1..33]);
+            if secret == BigInt::from(0i32) || secret >= curve_order() {
+                return Err(invalid());
+            }
+            KeySource::Wif { key: PrivateKey::new(secret), compressed, network }
+        };
+
+        let key = Self {
+            origin,
+            source,
+        };
+        if !key.is_compressed() && ctx != Context::Top && ctx != Context::Sh {
+            return Err(invalid());
+        }
+
+        Ok(key)
+    }
+
+    pub fn is_ranged(&self) -> bool {
+        match &self.source {
+            KeySource::XPub { wildcard, .. } | KeySource::XPrv { wildcard, .. } => *wildcard != Wildcard::None,
+            _ => false,
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        match &self.source {
+            KeySource::Public { compressed, .. } | KeySource::Wif { compressed, .. } => *compressed,
+            _ => true,
+        }
+    }
+
+    /// the public key at `index`, which is ignored for keys without a wildcard
+    pub fn derive(&self, index: u32) -> Result<S256Point, DescriptorError> {
+        let child = |path: &DerivationPath, wildcard: &Wildcard| match wildcard {
+            Wildcard::None => path.clone(),
+            Wildcard::Unhardened => path.child(index),
+            Wildcard::Hardened => path.child(index | HARDENED),
+        };
+
+        let key = match &self.source {
+            KeySource::Public { key, .. } => key.clone(),
+            KeySource::Wif { key, .. } => key.get_pub_key(),
+            KeySource::XPub { xkey, path, wildcard } => xkey.derive_pub(&child(path, wildcard))?.public_key,
+            KeySource::XPrv { xkey, path, wildcard } => xkey.derive_priv(&child(path, wildcard))?.private_key.get_pub_key(),
+        };
+
+        Ok(key)
+    }
+
+    /// serialized key at `index` as it appears in scripts
+    fn derive_bytes(&self, index: u32, ctx: Context) -> Result<Vec<u8>, DescriptorError> {
+        let key = self.derive(index)?;
+        if ctx == Context::Tap {
+            return Ok(key.xonly());
+        }
+
+        Ok(key.sec(self.is_compressed()))
+    }
+}
+
+impl Display for DescriptorKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(origin) = &self.origin {
+            write!(f, "[{}{}]", origin.fingerprint.encode_hex::<String>(), path_suffix(&origin.path))?;
+        }
+
+        let wildcard_suffix = |wildcard: &Wildcard| match wildcard {
+            Wildcard::None => "",
+            Wildcard::Unhardened => "/*",
+            Wildcard::Hardened => "/*'",
+        };
+
+        match &self.source {
+            KeySource::Public { key, compressed, xonly } => {
+                if *xonly {
+                    write!(f, "{}", key.xonly().encode_hex::<String>())
+                } else {
+                    write!(f, "{}", key.sec(*compressed).encode_hex::<String>())
+                }
+            },
+            KeySource::Wif { key, compressed, network } => {
+                write!(f, "{}", u8_slice_base58_to_string(&key.wif(*compressed, *network)))
+            },
+            KeySource::XPub { xkey, path, wildcard } => {
+                write!(f, "{}{}{}", xkey, path_suffix(path), wildcard_suffix(wildcard))
+            },
+            KeySource::XPrv { xkey, path, wildcard } => {
+                write!(f, "{}{}{}", xkey, path_suffix(path), wildcard_suffix(wildcard))
+            },
+        }
+    }
+}
+
+
+// -- TapTree --
+
+#[derive(Clone)]
+pub enum TapTree {
+    Leaf(Box<Descriptor>),
+    Branch(Box<TapTree>, Box<TapTree>),
+}
+
+impl TapTree {
+    fn parse(s: &str) -> Result<Self, DescriptorError> {
+        if let Some(inner) = s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+            let args = split_args(inner)?;
+            if args.len() != 2 {
+                return Err(DescriptorError::Syntax(s.to_string()));
+            }
+
+            return Ok(TapTree::Branch(Box::new(Self::parse(args[0])?), Box::new(Self::parse(args[1])?)));
+        }
+
+        Ok(TapTree::Leaf(Box::new(Descriptor::parse_expr(s, Context::Tap)?)))
+    }
+
+    /// merkle root of the tree at `index` (BIP341)
+    pub fn merkle_root(&self, index: u32) -> Result<Vec<u8>, DescriptorError> {
+        match self {
+            TapTree::Leaf(desc) => {
+                let mut msg = vec![TAPROOT_LEAF_TAPSCRIPT];
+                msg.extend_from_slice(&desc.script(index, Context::Tap)?.serialize());
+                Ok(tagged_hash("TapLeaf", &msg))
+            },
+            TapTree::Branch(left, right) => {
+                let mut left = left.merkle_root(index)?;
+                let mut right = right.merkle_root(index)?;
+                if right < left {
+                    std::mem::swap(&mut left, &mut right);
+                }
+                left.extend_from_slice(&right);
+                Ok(tagged_hash("TapBranch", &left))
+            },
+        }
+    }
+
+    fn is_ranged(&self) -> bool {
+        match self {
+            TapTree::Leaf(desc) => desc.is_ranged(),
+            TapTree::Branch(left, right) => left.is_ranged() || right.is_ranged(),
+        }
+    }
+}
+
+impl Display for TapTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TapTree::Leaf(desc) => desc.fmt_inner(f),
+            TapTree::Branch(left, right) => write!(f, "{{{},{}}}", left, right),
+        }
+    }
+}
+
+
+// -- Descriptor --
+
+/// output script descriptor (BIP380-386)
+#[derive(Clone)]
+pub enum Descriptor {
+    Pk(DescriptorKey),
+    Pkh(DescriptorKey),
+    Wpkh(DescriptorKey),
+    Sh(Box<Descriptor>),
+    Wsh(Box<Descriptor>),
+    Multi(usize, Vec<DescriptorKey>),
+    SortedMulti(usize, Vec<DescriptorKey>),
+    Tr(DescriptorKey, Option<TapTree>),
+    Addr(String, Script),
+    Raw(Script),
+}
+
+impl Descriptor {
+    /// parse a descriptor, the `#checksum` suffix is verified when present
+    pub fn parse(s: &str) -> Result<Self, DescriptorError> {
+        let s = s.trim();
+        let s = match s.split_once('#') {
+            Some((desc, checksum)) => {
+                if descriptor_checksum(desc).as_deref() != Some(checksum) {
+                    return Err(DescriptorError::InvalidChecksum(checksum.to_string()));
+                }
+                desc
+            },
+            None => s,
+        };
+
+        if descriptor_checksum(s).is_none() {
+            return Err(DescriptorError::Syntax(s.to_string()));
+        }
+
+        Self::parse_expr(s, Context::Top)
+    }
+
+    fn parse_expr(s: &str, ctx: Context) -> Result<Self, DescriptorError> {
+        let (name, args) = split_call(s)?;
+        let expect_args = |n: usize| {
+            if args.len() != n {
+                return Err(DescriptorError::Syntax(s.to_string()));
+            }
+            Ok(())
+        };
+        let allowed = |contexts: &[Context]| {
+            if !contexts.contains(&ctx) {
+                return Err(DescriptorError::InvalidContext(name.to_string()));
+            }
+            Ok(())
+        };
+
+        match name {
+            "pk" => {
+                expect_args(1)?;
+                Ok(Descriptor::Pk(DescriptorKey::parse(args[0], ctx)?))
+            },
+            "pkh" => {
+                expect_args(1)?;
+                allowed(&[Context::Top, Context::Sh, Context::Wsh])?;
+                Ok(Descriptor::Pkh(DescriptorKey::parse(args[0], ctx)?))
+            },
+            "wpkh" => {
+                expect_args(1)?;
+                allowed(&[Context::Top, Context::Sh])?;
+                // parsed as a segwit key, which must be compressed
+                Ok(Descriptor::Wpkh(DescriptorKey::parse(args[0], Context::Wsh)?))
+            },
+            "sh" => {
+                expect_args(1)?;
+                allowed(&[Context::Top])?;
+                Ok(Descriptor::Sh(Box::new(Self::parse_expr(args[0], Context::Sh)?)))
+            },
+            "wsh" => {
+                expect_args(1)?;
+                allowed(&[Context::Top, Context::Sh])?;
+                Ok(Descriptor::Wsh(Box::new(Self::parse_expr(args[0], Context::Wsh)?)))
+            },
+            "multi" | "sortedmulti" => {
+                allowed(&[Context::Top, Context::Sh, Context::Wsh])?;
+                if args.len() < 2 {
+                    return Err(DescriptorError::Syntax(s.to_string()));
+                }
+
+                let k = args[0].parse::<usize>().map_err(|_| DescriptorError::Syntax(s.to_string()))?;
+                let keys = args[1..].iter().map(|k| DescriptorKey::parse(k, ctx)).collect::<Result<Vec<_>, _>>()?;
+                // p2sh redeem scripts are limited to 520 bytes, 15 compressed keys
+                let max_keys = if ctx == Context::Sh { 15 } else { 20 };
+                if k == 0 || k > keys.len() || keys.len() > max_keys {
+                    return Err(DescriptorError::InvalidThreshold(k, keys.len()));
+                }
+
+                if name == "multi" {
+                    Ok(Descriptor::Multi(k, keys))
+                } else {
+                    Ok(Descriptor::SortedMulti(k, keys))
+                }
+            },
+            "tr" => {
+                allowed(&[Context::Top])?;
+                if args.is_empty() || args.len() > 2 {
+                    return Err(DescriptorError::Syntax(s.to_string()));
+                }
+
+                let key = DescriptorKey::parse(args[0], Context::Tap)?;
+                let tree = match args.get(1) {
+                    Some(tree) => Some(TapTree::parse(tree)?),
+                    None => None,
+                };
+                Ok(Descriptor::Tr(key, tree))
+            },
+            "addr" => {
+                expect_args(1)?;
+                allowed(&[Context::Top])?;
+                let script = address_to_script(args[0])?;
+                Ok(Descriptor::Addr(args[0].to_string(), script))
+            },
+            "raw" => {
+                expect_args(1)?;
+                allowed(&[Context::Top])?;
+                let raw = hex::decode(args[0]).map_err(|_| DescriptorError::Syntax(s.to_string()))?;
+                let script = Script::parse_raw(&raw).ok_or_else(|| DescriptorError::Syntax(s.to_string()))?;
+                Ok(Descriptor::Raw(script))
+            },
+            _ => Err(DescriptorError::UnknownFunction(name.to_string())),
+        }
+    }
+
+    pub fn is_ranged(&self) -> bool {
+        match self {
+            Descriptor::Pk(key) | Descriptor::Pkh(key) | Descriptor::Wpkh(key) => key.is_ranged(),
+            Descriptor::Sh(inner) | Descriptor::Wsh(inner) => inner.is_ranged(),
+            Descriptor::Multi(_, keys) | Descriptor::SortedMulti(_, keys) => keys.iter().any(|k| k.is_ranged()),
+            Descriptor::Tr(key, tree) => key.is_ranged() || tree.as_ref().is_some_and(|t| t.is_ranged()),
+            Descriptor::Addr(_, _) | Descriptor::Raw(_) => false,
+        }
+    }
+
+    /// the scriptPubKey at `index`
+    pub fn script_pubkey(&self, index: u32) -> Result<Script, DescriptorError> {
+        self.script(index, Context::Top)
+    }
+
+    fn script(&self, index: u32, ctx: Context) -> Result<Script, DescriptorError> {
+        let script = match self {
+            Descriptor::Pk(key) => Script::p2pk(&key.derive_bytes(index, ctx)?),
+            Descriptor::Pkh(key) => Script::p2pkh(&hash160(&key.derive_bytes(index, ctx)?)),
+            Descriptor::Wpkh(key) => Script::p2wpkh(&hash160(&key.derive_bytes(index, ctx)?)),
+            Descriptor::Sh(inner) => Script::p2sh(&hash160(&inner.script(index, Context::Sh)?.raw_serialize())),
+            Descriptor::Wsh(inner) => Script::p2wsh(&sha256(&inner.script(index, Context::Wsh)?.raw_serialize())),
+            Descriptor::Multi(k, keys) | Descriptor::SortedMulti(k, keys) => {
+                let mut pub_keys = keys.iter().map(|key| key.derive_bytes(index, ctx)).collect::<Result<Vec<_>, _>>()?;
+                if let Descriptor::SortedMulti(_, _) = self {
+                    pub_keys.sort();
+                }
+
+                Script::multisig(*k, &pub_keys)
+            },
+            Descriptor::Tr(key, tree) => {
+                let merkle_root = match tree {
+                    Some(tree) => Some(tree.merkle_root(index)?),
+                    None => None,
+                };
+                Script::p2tr(&key.derive(index)?.tap_tweak(merkle_root.as_deref()).xonly())
+            },
+            Descriptor::Addr(_, script) | Descriptor::Raw(script) => script.clone(),
+        };
+
+        Ok(script)
+    }
+
+    /// the address at `index`
+    pub fn address(&self, index: u32, network: Network) -> Result<String, DescriptorError> {
+        let script = self.script_pubkey(index)?;
+        let program = || match &script.cmds[1] {
+            ScriptCmd::Cmd(program) => program.clone(),
+            _ => unreachable!(),
+        };
+
+        match self {
+            Descriptor::Pkh(_) => {
+                let h160 = match &script.cmds[2] {
+                    ScriptCmd::Cmd(h) => h.clone(),
+                    _ => unreachable!(),
+                };
+                let mut s = vec![network.p2pkh_prefix()];
+                s.extend_from_slice(&h160);
+                Ok(u8_slice_base58_to_string(&encode_base58_checksum(&s)))
+            },
+            Descriptor::Sh(_) => {
+                let mut s = vec![network.p2sh_prefix()];
+                s.extend_from_slice(&program());
+                Ok(u8_slice_base58_to_string(&encode_base58_checksum(&s)))
+            },
+            Descriptor::Wpkh(_) | Descriptor::Wsh(_) => Ok(encode_segwit_address(network.bech32_hrp(), 0, &program())),
+            Descriptor::Tr(_, _) => Ok(encode_segwit_address(network.bech32_hrp(), 1, &program())),
+            Descriptor::Addr(address, _) => Ok(address.clone()),
+            _ => Err(DescriptorError::NoAddress),
+        }
+    }
+
+    fn fmt_inner(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join_keys = |keys: &[DescriptorKey]| keys.iter().map(|k| k.to_string()).collect::<Vec<_>>().join(",");
+
+        match self {
+            Descriptor::Pk(key) => write!(f, "pk({})", key),
+            Descriptor::Pkh(key) => write!(f, "pkh({})", key),
+            Descriptor::Wpkh(key) => write!(f, "wpkh({})", key),
+            Descriptor::Sh(inner) => {
+                write!(f, "sh(")?;
+                inner.fmt_inner(f)?;
+                write!(f, ")")
+            },
+            Descriptor::Wsh(inner) => {
+                write!(f, "wsh(")?;
+                inner.fmt_inner(f)?;
+                write!(f, ")")
+            },
+            Descriptor::Multi(k, keys) => write!(f, "multi({},{})", k, join_keys(keys)),
+            Descriptor::SortedMulti(k, keys) => write!(f, "sortedmulti({},{})", k, join_keys(keys)),
+            Descriptor::Tr(key, None) => write!(f, "tr({})", key),
+            Descriptor::Tr(key, Some(tree)) => write!(f, "tr({},{})", key, tree),
+            Descriptor::Addr(address, _) => write!(f, "addr({})", address),
+            Descriptor::Raw(script) => write!(f, "raw({})", script.raw_serialize().encode_hex::<String>()),
+        }
+    }
+}
+
+impl Display for Descriptor {
+    /// the descriptor with its checksum appended
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let desc = DescriptorWithoutChecksum(self).to_string();
+        write!(f, "{}#{}", desc, descriptor_checksum(&desc).unwrap())
+    }
+}
+
+struct DescriptorWithoutChecksum<'a>(&'a Descriptor);
+
+impl Display for DescriptorWithoutChecksum<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt_inner(f)
+    }
+}
+
+/// scriptPubKey paying to a base58 or bech32 address
+fn address_to_script(address: &str) -> Result<Script, DescriptorError> {
+    if let Some((_, version, program)) = decode_segwit_address(address) {
+        return Ok(Script::segwit(version, &program));
+    }
+
+    let data = decode_base58_checksum(address.as_bytes())
+        .filter(|d| d.len() == 21)
+        .ok_or_else(|| DescriptorError::InvalidAddress(address.to_string()))?;
+    let h160 = data[1..].to_owned();
+
+    if data[0] == Network::Mainnet.p2pkh_prefix() || data[0] == Network::Testnet.p2pkh_prefix() {
+        Ok(Script::p2pkh(&h160))
+    } else if data[0] == Network::Mainnet.p2sh_prefix() || data[0] == Network::Testnet.p2sh_prefix() {
+        Ok(Script::p2sh(&h160))
+    } else {
+        Err(DescriptorError::InvalidAddress(address.to_string()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests_descriptor {
+    use hex::ToHex;
+
+    use crate::{network::Network, utils::{encode_base58_checksum, u8_slice_base58_to_string}};
+
+    use super::{Descriptor, DescriptorError, descriptor_checksum};
+
+    const ABANDON_XPRV: &str = "xprv9s21ZrQH143K3GJpoapnV8SFfukcVBSfeCficPSGfubmSFDxo1kuHnLisriDvSnRRuL2Qrg5ggqHKNVpxR86QEC8w35uxmGoggxtQTPvfUu";
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!("89f8spxm", descriptor_checksum("raw(deadbeef)").unwrap());
+        assert_eq!("02wpgw69", descriptor_checksum("addr(mkmZxiEcEd8ZqjQWVZuC6so5dFMKEFpN2j)").unwrap());
+
+        assert!(Descriptor::parse("raw(deadbeef)#89f8spxm").is_ok());
+        assert_eq!(
+            Err(DescriptorError::InvalidChecksum("89f8spxx".to_string())),
+            Descriptor::parse("raw(deadbeef)#89f8spxx").map(|_| ()),
+        );
+    }
+
+    #[test]
+    fn test_single_key() {
+        let desc = Descriptor::parse("pkh(02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5)").unwrap();
+        assert_eq!(
+            "76a91406afd46bcdfd22ef94ac122aa11f241244a37ecc88ac",
+            desc.script_pubkey(0).unwrap().raw_serialize().encode_hex::<String>(),
+        );
+
+        let desc = Descriptor::parse("wpkh(02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9)").unwrap();
+        assert_eq!(
+            "00147dd65592d0ab2fe0d0257d571abf032cd9db93dc",
+            desc.script_pubkey(0).unwrap().raw_serialize().encode_hex::<String>(),
+        );
+
+        // uncompressed keys are not allowed in segwit
+        assert!(Descriptor::parse("wpkh(04a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd5b8dec5235a0fa8722476c7709c02559e3aa73aa03918ba2d492eea75abea235)").is_err());
+        assert!(Descriptor::parse("wsh(wpkh(02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9))").is_err());
+
+        // keys off the curve, and WIF payloads that aren't a valid secret
+        let invalid_key = |desc: &str, key: &str| {
+            assert_eq!(Err(DescriptorError::InvalidKey(key.to_string())), Descriptor::parse(&format!("{}({})", desc, key)).map(|_| ()));
+        };
+        let x = "00".repeat(32);
+        invalid_key("pkh", &format!("02{}", x));
+        invalid_key("pkh", &format!("04{}{}", x, x));
+        invalid_key("tr", &x);
+        for payload in [vec![], [vec![0x80], vec![0; 32], vec![1]].concat(), [vec![0x80], vec![0xff; 32]].concat()] {
+            invalid_key("pkh", &u8_slice_base58_to_string(&encode_base58_checksum(&payload)));
+        }
+    }
+
+    #[test]
+    fn test_multi() {
+        let desc = Descriptor::parse("sh(sortedmulti(1,03fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556,022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01))").unwrap();
+        let script = desc.script_pubkey(0).unwrap();
+        assert_eq!(23, script.raw_serialize().len());
+        assert!(desc.address(0, Network::Mainnet).unwrap().starts_with('3'));
+
+        assert_eq!(
+            Err(DescriptorError::InvalidThreshold(3, 2)),
+            Descriptor::parse("multi(3,03fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556,022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01)").map(|_| ()),
+        );
+        assert_eq!(
+            Err(DescriptorError::NoAddress),
+            Descriptor::parse("raw(deadbeef)").unwrap().address(0, Network::Mainnet),
+        );
+        // a push running past the end of the script
+        assert_eq!(Err(DescriptorError::Syntax(String::from("raw(4c)"))), Descriptor::parse("raw(4c)").map(|_| ()));
+    }
+
+    #[test]
+    fn test_ranged() {
+        // BIP84 first receive address of the "abandon ... about" mnemonic
+        let s = format!("wpkh([73c5da0a/84'/0'/0']{}/84'/0'/0'/0/*)", ABANDON_XPRV);
+        let desc = Descriptor::parse(&s).unwrap();
+        assert!(desc.is_ranged());
+        assert_eq!("bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu", desc.address(0, Network::Mainnet).unwrap());
+
+        // the serialization round trips with a checksum
+        let serialized = desc.to_string();
+        assert!(serialized.starts_with(&format!("wpkh([73c5da0a/84'/0'/0']{}/84'/0'/0'/0/*)#", ABANDON_XPRV)));
+        assert_eq!(serialized, Descriptor::parse(&serialized).unwrap().to_string());
+    }
+
+    #[test]
+    fn test_tr() {
+        // BIP86 first receive output of the "abandon ... about" mnemonic
+        let s = format!("tr({}/86'/0'/0'/0/*)", ABANDON_XPRV);
+        let desc = Descriptor::parse(&s).unwrap();
+        assert_eq!(
+            "5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c",
+            desc.script_pubkey(0).unwrap().raw_serialize().encode_hex::<String>(),
+        );
+
+        let address = desc.address(0, Network::Mainnet).unwrap();
+        let addr = Descriptor::parse(&format!("addr({})", address)).unwrap();
+        assert_eq!(
+            desc.script_pubkey(0).unwrap().raw_serialize(),
+            addr.script_pubkey(0).unwrap().raw_serialize(),
+        );
+
+        let desc = Descriptor::parse("tr(c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5,{pk(fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556),pk(e493dbf1c10d80f3581e4904930b1404cc6c13900ee0758474fa94abe8c4cd13)})").unwrap();
+        assert_eq!(34, desc.script_pubkey(0).unwrap().raw_serialize().len());
+        assert!(Descriptor::parse(&desc.to_string()).is_ok());
+    }
+}
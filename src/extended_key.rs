@@ -0,0 +1,519 @@
+use std::{fmt::Display, str::FromStr};
+
+use hmac::{Hmac, Mac};
+use num_bigint::BigInt;
+use sha2::Sha512;
+
+use crate::{network::Network, private_key::PrivateKey, s256point::S256Point, utils::{hash160, encode_base58_checksum, decode_base58_checksum, u8_slice_base58_to_string}};
+
+
+type HmacSha512 = Hmac<Sha512>;
+
+/// child indexes at or above this are hardened
+pub const HARDENED: u32 = 0x80000000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bip32Error {
+    /// public derivation was asked for a hardened index
+    CannotDeriveHardenedFromPublic,
+    /// IL >= n or the derived key is zero/infinity, the next index should be used
+    InvalidChild(u32),
+    InvalidSeedLength(usize),
+    InvalidBase58,
+    InvalidLength(usize),
+    UnknownVersion(Vec<u8>),
+    InvalidKey,
+    InvalidPath(String),
+    /// the key is already at depth 255, the most a serialized key can hold
+    MaxDepth,
+    /// a depth 0 key with a parent fingerprint or child number
+    InvalidMaster,
+}
+
+impl Display for Bip32Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Bip32Error::CannotDeriveHardenedFromPublic => write!(f, "cannot derive hardened child from public key"),
+            Bip32Error::InvalidChild(i) => write!(f, "invalid child at index {}", i),
+            Bip32Error::InvalidSeedLength(len) => write!(f, "seed length {} not in 16..=64 bytes", len),
+            Bip32Error::InvalidBase58 => write!(f, "invalid base58 or checksum"),
+            Bip32Error::InvalidLength(len) => write!(f, "extended key must be 78 bytes, got {}", len),
+            Bip32Error::UnknownVersion(v) => write!(f, "unknown version bytes {}", hex::encode(v)),
+            Bip32Error::InvalidKey => write!(f, "invalid key data"),
+            Bip32Error::InvalidPath(p) => write!(f, "invalid derivation path: {}", p),
+            Bip32Error::MaxDepth => write!(f, "cannot derive past depth 255"),
+            Bip32Error::InvalidMaster => write!(f, "master key with a nonzero parent fingerprint or child number"),
+        }
+    }
+}
+
+pub(crate) fn curve_order() -> BigInt {
+    BigInt::parse_bytes(b"fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141", 16).unwrap()
+}
+
+/// a serialized key at depth 0 has no parent, so its fingerprint and child number are zero
+fn check_master(data: &[u8]) -> Result<(), Bip32Error> {
+    if data[4] == 0 && data[5..13].iter().any(|b| *b != 0) {
+        return Err(Bip32Error::InvalidMaster);
+    }
+    Ok(())
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha512::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+
+// -- DerivationPath --
+
+/// a BIP32 derivation path like `m/84'/0'/0'/0/5`, hardened steps have `HARDENED` set
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DerivationPath {
+    pub steps: Vec<u32>,
+}
+
+impl DerivationPath {
+    pub fn new(steps: &[u32]) -> Self {
+        Self {
+            steps: steps.to_owned(),
+        }
+    }
+
+    /// parse a path, the leading `m` is optional and hardened steps are marked with `'` or `h`
+    pub fn parse(s: &str) -> Result<Self, Bip32Error> {
+        let mut steps = vec![];
+
+        for (i, part) in s.split('/').enumerate() {
+            if i == 0 && (part == "m" || part.is_empty()) {
+                continue;
+            }
+
+            let (num, hardened) = match part.strip_suffix(|c| c == '\'' || c == 'h' || c == 'H') {
+                Some(num) => (num, true),
+                None => (part, false),
+            };
+
+            let index = num.parse::<u32>().map_err(|_| Bip32Error::InvalidPath(s.to_string()))?;
+            if index >= HARDENED {
+                return Err(Bip32Error::InvalidPath(s.to_string()));
+            }
+
+            steps.push(if hardened { index + HARDENED } else { index });
+        }
+
+        Ok(Self {
+            steps,
+        })
+    }
+
+    /// returns a new path with `index` appended
+    pub fn child(&self, index: u32) -> Self {
+        let mut steps = self.steps.clone();
+        steps.push(index);
+
+        Self {
+            steps,
+        }
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = Bip32Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl Display for DerivationPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "m")?;
+        for step in &self.steps {
+            if *step >= HARDENED {
+                write!(f, "/{}'", step - HARDENED)?;
+            } else {
+                write!(f, "/{}", step)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+// -- ExtendedPrivKey --
+
+#[derive(Clone)]
+pub struct ExtendedPrivKey {
+    pub network: Network,
+    /// 0 for the master key
+    pub depth: u8,
+    /// first 4 bytes of the parent's key identifier, zeros for the master key
+    pub parent_fingerprint: Vec<u8>,
+    pub child_number: u32,
+    /// 32 bytes
+    pub chain_code: Vec<u8>,
+    pub private_key: PrivateKey,
+}
+
+impl ExtendedPrivKey {
+    /// master key from a 16 to 64 bytes seed
+    pub fn new_master(seed: &[u8], network: Network) -> Result<Self, Bip32Error> {
+        if seed.len() < 16 || seed.len() > 64 {
+            return Err(Bip32Error::InvalidSeedLength(seed.len()));
+        }
+
+        let i = hmac_sha512(b"Bitcoin seed", seed);
+        let secret = BigInt::from_bytes_be(num_bigint::Sign::Plus, &i[..32]);
+        if secret == BigInt::from(0i32) || secret >= curve_order() {
+            return Err(Bip32Error::InvalidKey);
+        }
+
+        Ok(Self {
+            network,
+            depth: 0,
+            parent_fingerprint: vec![0u8; 4],
+            child_number: 0,
+            chain_code: i[32..].to_owned(),
+            private_key: PrivateKey::new(secret),
+        })
+    }
+
+    /// CKDpriv, derive the child private key at `index`
+    pub fn ckd_priv(&self, index: u32) -> Result<Self, Bip32Error> {
+        let depth = self.depth.checked_add(1).ok_or(Bip32Error::MaxDepth)?;
+        let mut data: Vec<u8> = vec![];
+        if index >= HARDENED {
+            data.push(0u8);
+            data.extend_from_slice(&self.private_key.secret_bytes());
+        } else {
+            data.extend_from_slice(&self.private_key.get_pub_key().sec(true));
+        }
+        data.extend_from_slice(&index.to_be_bytes());
+
+        let i = hmac_sha512(&self.chain_code, &data);
+        let n = curve_order();
+        let il = BigInt::from_bytes_be(num_bigint::Sign::Plus, &i[..32]);
+        if il >= n {
+            return Err(Bip32Error::InvalidChild(index));
+        }
+
+        let secret = (il + &self.private_key.secret) % n;
+        if secret == BigInt::from(0i32) {
+            return Err(Bip32Error::InvalidChild(index));
+        }
+
+        Ok(Self {
+            network: self.network,
+            depth,
+            parent_fingerprint: self.fingerprint(),
+            child_number: index,
+            chain_code: i[32..].to_owned(),
+            private_key: PrivateKey::new(secret),
+        })
+    }
+
+    /// derive along every step of `path`
+    pub fn derive_priv(&self, path: &DerivationPath) -> Result<Self, Bip32Error> {
+        let mut key = self.clone();
+        for index in &path.steps {
+            key = key.ckd_priv(*index)?;
+        }
+
+        Ok(key)
+    }
+
+    pub fn extended_pub_key(&self) -> ExtendedPubKey {
+        ExtendedPubKey {
+            network: self.network,
+            depth: self.depth,
+            parent_fingerprint: self.parent_fingerprint.clone(),
+            child_number: self.child_number,
+            chain_code: self.chain_code.clone(),
+            public_key: self.private_key.get_pub_key(),
+        }
+    }
+
+    /// hash160 of the compressed public key
+    pub fn identifier(&self) -> Vec<u8> {
+        hash160(&self.private_key.get_pub_key().sec(true))
+    }
+
+    pub fn fingerprint(&self) -> Vec<u8> {
+        self.identifier()[..4].to_owned()
+    }
+
+    /// 78 bytes serialization before base58 encoding
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = self.network.xprv_version().to_vec();
+        result.push(self.depth);
+        result.extend_from_slice(&self.parent_fingerprint);
+        result.extend_from_slice(&self.child_number.to_be_bytes());
+        result.extend_from_slice(&self.chain_code);
+        result.push(0u8);
+        result.extend_from_slice(&self.private_key.secret_bytes());
+
+        result
+    }
+
+    /// parse xprv/tprv, tprv keys are assigned to `Network::Testnet`
+    pub fn parse(s: &str) -> Result<Self, Bip32Error> {
+        let data = decode_base58_checksum(s.as_bytes()).ok_or(Bip32Error::InvalidBase58)?;
+        if data.len() != 78 {
+            return Err(Bip32Error::InvalidLength(data.len()));
+        }
+
+        let network = if data[0..4] == Network::Mainnet.xprv_version() {
+            Network::Mainnet
+        } else if data[0..4] == Network::Testnet.xprv_version() {
+            Network::Testnet
+        } else {
+            return Err(Bip32Error::UnknownVersion(data[0..4].to_owned()));
+        };
+        check_master(&data)?;
+
+        if data[45] != 0 {
+            return Err(Bip32Error::InvalidKey);
+        }
+        let secret = BigInt::from_bytes_be(num_bigint::Sign::Plus, &data[46..78]);
+        if secret == BigInt::from(0i32) || secret >= curve_order() {
+            return Err(Bip32Error::InvalidKey);
+        }
+
+        Ok(Self {
+            network,
+            depth: data[4],
+            parent_fingerprint: data[5..9].to_owned(),
+            child_number: u32::from_be_bytes([data[9], data[10], data[11], data[12]]),
+            chain_code: data[13..45].to_owned(),
+            private_key: PrivateKey::new(secret),
+        })
+    }
+}
+
+impl Display for ExtendedPrivKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", u8_slice_base58_to_string(&encode_base58_checksum(&self.serialize())))
+    }
+}
+
+
+// -- ExtendedPubKey --
+
+#[derive(Clone)]
+pub struct ExtendedPubKey {
+    pub network: Network,
+    pub depth: u8,
+    pub parent_fingerprint: Vec<u8>,
+    pub child_number: u32,
+    pub chain_code: Vec<u8>,
+    pub public_key: S256Point,
+}
+
+impl ExtendedPubKey {
+    /// CKDpub, derive the child public key at a non-hardened `index`
+    pub fn ckd_pub(&self, index: u32) -> Result<Self, Bip32Error> {
+        if index >= HARDENED {
+            return Err(Bip32Error::CannotDeriveHardenedFromPublic);
+        }
+        let depth = self.depth.checked_add(1).ok_or(Bip32Error::MaxDepth)?;
+
+        let mut data = self.public_key.sec(true);
+        data.extend_from_slice(&index.to_be_bytes());
+
+        let i = hmac_sha512(&self.chain_code, &data);
+        let il = BigInt::from_bytes_be(num_bigint::Sign::Plus, &i[..32]);
+        if il >= curve_order() {
+            return Err(Bip32Error::InvalidChild(index));
+        }
+
+        let public_key = PrivateKey::new(il).get_pub_key() + self.public_key.clone();
+        if public_key.x.is_none() {
+            return Err(Bip32Error::InvalidChild(index));
+        }
+
+        Ok(Self {
+            network: self.network,
+            depth,
+            parent_fingerprint: self.fingerprint(),
+            child_number: index,
+            chain_code: i[32..].to_owned(),
+            public_key,
+        })
+    }
+
+    /// derive along every step of `path`, fails on hardened steps
+    pub fn derive_pub(&self, path: &DerivationPath) -> Result<Self, Bip32Error> {
+        let mut key = self.clone();
+        for index in &path.steps {
+            key = key.ckd_pub(*index)?;
+        }
+
+        Ok(key)
+    }
+
+    /// hash160 of the compressed public key
+    pub fn identifier(&self) -> Vec<u8> {
+        self.public_key.hash160(true)
+    }
+
+    pub fn fingerprint(&self) -> Vec<u8> {
+        self.identifier()[..4].to_owned()
+    }
+
+    /// 78 bytes serialization before base58 encoding
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = self.network.xpub_version().to_vec();
+        result.push(self.depth);
+        result.extend_from_slice(&self.parent_fingerprint);
+        result.extend_from_slice(&self.child_number.to_be_bytes());
+        result.extend_from_slice(&self.chain_code);
+        result.extend_from_slice(&self.public_key.sec(true));
+
+        result
+    }
+
+    /// parse xpub/tpub, tpub keys are assigned to `Network::Testnet`
+    pub fn parse(s: &str) -> Result<Self, Bip32Error> {
+        let data = decode_base58_checksum(s.as_bytes()).ok_or(Bip32Error::InvalidBase58)?;
+        if data.len() != 78 {
+            return Err(Bip32Error::InvalidLength(data.len()));
+        }
+
+        let network = if data[0..4] == Network::Mainnet.xpub_version() {
+            Network::Mainnet
+        } else if data[0..4] == Network::Testnet.xpub_version() {
+            Network::Testnet
+        } else {
+            return Err(Bip32Error::UnknownVersion(data[0..4].to_owned()));
+        };
+        check_master(&data)?;
+
+        if !S256Point::is_valid_sec(&data[45..78]) {
+            return Err(Bip32Error::InvalidKey);
+        }
+
+        Ok(Self {
+            network,
+            depth: data[4],
+            parent_fingerprint: data[5..9].to_owned(),
+            child_number: u32::from_be_bytes([data[9], data[10], data[11], data[12]]),
+            chain_code: data[13..45].to_owned(),
+            public_key: S256Point::parse(data[45..78].to_owned()),
+        })
+    }
+}
+
+impl Display for ExtendedPubKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", u8_slice_base58_to_string(&encode_base58_checksum(&self.serialize())))
+    }
+}
+
+
+#[cfg(test)]
+mod tests_extended_key {
+    use crate::{
+        network::Network,
+        s256point::S256Point,
+        utils::{encode_base58_checksum, u8_slice_base58_to_string},
+    };
+
+    use super::{ExtendedPrivKey, ExtendedPubKey, DerivationPath, HARDENED, Bip32Error};
+
+    #[test]
+    fn test_derivation_path() {
+        let path = DerivationPath::parse("m/84'/0h/0'/0/5").unwrap();
+        assert_eq!(vec![84 + HARDENED, HARDENED, HARDENED, 0, 5], path.steps);
+        assert_eq!("m/84'/0'/0'/0/5", path.to_string());
+
+        assert!(DerivationPath::parse("m/x/1").is_err());
+        assert_eq!(0, DerivationPath::parse("m").unwrap().steps.len());
+    }
+
+    #[test]
+    fn test_vector_1() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedPrivKey::new_master(&seed, Network::Mainnet).unwrap();
+
+        assert_eq!(
+            "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi",
+            master.to_string(),
+        );
+        assert_eq!(
+            "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8",
+            master.extended_pub_key().to_string(),
+        );
+
+        let child = master.derive_priv(&DerivationPath::parse("m/0'/1").unwrap()).unwrap();
+        assert_eq!(
+            "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs",
+            child.to_string(),
+        );
+        assert_eq!(2, child.depth);
+
+        // public derivation of the last, non-hardened step gives the same key
+        let hardened = master.ckd_priv(HARDENED).unwrap().extended_pub_key();
+        assert_eq!(
+            "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw",
+            hardened.to_string(),
+        );
+        assert_eq!(
+            "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ",
+            hardened.ckd_pub(1).unwrap().to_string(),
+        );
+        assert_eq!(Err(Bip32Error::CannotDeriveHardenedFromPublic), hardened.ckd_pub(HARDENED).map(|_| ()));
+    }
+
+    #[test]
+    fn test_parse() {
+        let s = "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs";
+        let xprv = ExtendedPrivKey::parse(s).unwrap();
+        assert_eq!(s, xprv.to_string());
+        assert_eq!(2, xprv.depth);
+
+        let s = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+        let xpub = ExtendedPubKey::parse(s).unwrap();
+        assert_eq!(s, xpub.to_string());
+        assert_eq!(1, xpub.child_number);
+
+        assert!(ExtendedPubKey::parse(&s[..s.len() - 1]).is_err());
+
+        // a compressed key prefix with an x that isn't on the curve
+        let mut data = xpub.serialize();
+        data[46..78].copy_from_slice(&[0u8; 32]);
+        while S256Point::is_valid_sec(&data[45..78]) {
+            data[77] += 1;
+        }
+        let s = u8_slice_base58_to_string(&encode_base58_checksum(&data));
+        assert_eq!(Err(Bip32Error::InvalidKey), ExtendedPubKey::parse(&s).map(|_| ()));
+
+        // depth 0 with a parent fingerprint or a child number
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedPrivKey::new_master(&seed, Network::Mainnet).unwrap();
+        for i in [5, 12] {
+            let mut data = master.serialize();
+            data[i] = 1;
+            let s = u8_slice_base58_to_string(&encode_base58_checksum(&data));
+            assert_eq!(Err(Bip32Error::InvalidMaster), ExtendedPrivKey::parse(&s).map(|_| ()));
+
+            let mut data = master.extended_pub_key().serialize();
+            data[i] = 1;
+            let s = u8_slice_base58_to_string(&encode_base58_checksum(&data));
+            assert_eq!(Err(Bip32Error::InvalidMaster), ExtendedPubKey::parse(&s).map(|_| ()));
+        }
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let mut xprv = ExtendedPrivKey::new_master(&seed, Network::Mainnet).unwrap();
+        xprv.depth = 254;
+        let child = xprv.ckd_priv(0).unwrap();
+        assert_eq!(255, child.depth);
+        assert_eq!(Err(Bip32Error::MaxDepth), child.ckd_priv(0).map(|_| ()));
+        assert_eq!(Err(Bip32Error::MaxDepth), child.extended_pub_key().ckd_pub(0).map(|_| ()));
+    }
+}
@@ -0,0 +1,1489 @@
+use std::{fmt::Display, ops::BitOr};
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+use crate::{
+    op::*,
+    s256point::S256Point,
+    script::{Script, ScriptCmd, ScriptType},
+    transaction::SIGHASH_DEFAULT,
+    utils::{sha256, tagged_hash, encode_varint, little_endian_to_int},
+};
+
+
+/// why a script failed to evaluate
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptError {
+    /// the script finished with an empty stack or a false value on top
+    EvalFalse,
+    /// an opcode needed more stack elements than there are
+    InvalidStackOperation,
+    /// OP_VERIFY on a false value
+    Verify,
+    OpReturn,
+    /// IF without ENDIF, or ELSE/ENDIF without IF
+    UnbalancedConditional,
+    /// OP_FROMALTSTACK with an empty alt stack
+    InvalidAltstackOperation,
+    /// IF/NOTIF argument other than empty or 0x01 under MINIMALIF
+    MinimalIf,
+    /// OP_CODESEPARATOR in a legacy script under CONST_SCRIPTCODE
+    OpCodeSeparator,
+    /// FindAndDelete removed a signature from a legacy script under CONST_SCRIPTCODE
+    SigFindAndDelete,
+    /// push without the shortest encoding under MINIMALDATA
+    MinimalData,
+    NegativeLocktime,
+    /// the spending transaction doesn't meet a CLTV or CSV lock
+    UnsatisfiedLocktime,
+    EqualVerify,
+    NumEqualVerify,
+    CheckSigVerify,
+    CheckMultiSigVerify,
+    /// CHECKMULTISIG with more than 20 keys or a negative count
+    PubKeyCount,
+    /// CHECKMULTISIG with more signatures than keys or a negative count
+    SigCount,
+    /// nonempty CHECKMULTISIG dummy element under NULLDUMMY
+    SigNullDummy,
+    /// signature not strict DER under DERSIG, LOW_S or STRICTENC (BIP66)
+    SigDer,
+    /// signature s above n/2 under LOW_S
+    SigHighS,
+    /// undefined sighash type under STRICTENC
+    SigHashType,
+    /// public key neither compressed nor uncompressed SEC under STRICTENC
+    PubKeyType,
+    /// failed signature check with a nonempty signature under NULLFAIL (BIP146)
+    NullFail,
+    /// more than one element left on the stack under CLEANSTACK
+    CleanStack,
+    /// reserved NOP under DISCOURAGE_UPGRADABLE_NOPS
+    DiscourageUpgradableNops,
+    /// script longer than MAX_SCRIPT_SIZE
+    ScriptSize,
+    /// push longer than MAX_SCRIPT_ELEMENT_SIZE
+    PushSize,
+    /// more than MAX_OPS_PER_SCRIPT opcodes
+    OpCount,
+    /// more than MAX_STACK_SIZE elements on the stacks
+    StackSize,
+    /// scriptSig with other opcodes than pushes, under P2SH for P2SH outputs
+    SigPushOnly,
+    /// a push runs past the end of a redeem script
+    BadRedeemScript,
+    /// witness for an output that isn't a witness program
+    WitnessUnexpected,
+    /// nonempty scriptSig spending a native witness program
+    WitnessMalleated,
+    /// scriptSig other than a single push of the witness program redeem script
+    WitnessMalleatedP2sh,
+    /// witness program of an unknown version under DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM
+    DiscourageUpgradableWitnessProgram,
+    /// taproot control block not 33 bytes plus a multiple of 32 up to 128 of them
+    TaprootWrongControlSize,
+    /// invalid Schnorr signature
+    SchnorrSig,
+    /// Schnorr signature neither 64 nor 65 bytes
+    SchnorrSigSize,
+    /// undefined sighash type, or an explicit SIGHASH_DEFAULT byte
+    SchnorrSigHashType,
+    /// tapscript checked more signatures than its witness size pays for
+    TapscriptValidationWeight,
+    /// OP_CHECKMULTISIG(VERIFY) in a tapscript
+    TapscriptCheckMultiSig,
+    /// IF/NOTIF argument other than empty or 0x01 in a tapscript
+    TapscriptMinimalIf,
+    /// OP_SUCCESSx under DISCOURAGE_OP_SUCCESS
+    DiscourageOpSuccess,
+    /// unknown tapleaf version under DISCOURAGE_UPGRADABLE_TAPROOT_VERSION
+    DiscourageUpgradableTaprootVersion,
+    /// tapscript key neither empty nor 32 bytes under DISCOURAGE_UPGRADABLE_PUBKEYTYPE
+    DiscourageUpgradablePubKeyType,
+    /// version 0 witness program neither 20 nor 32 bytes
+    WitnessProgramWrongLength,
+    /// empty witness spending a P2WSH output
+    WitnessProgramWitnessEmpty,
+    /// witness script not matching the P2WSH hash, or a P2WPKH witness of other than 2 items
+    WitnessProgramMismatch,
+    /// uncompressed public key in a witness script under WITNESS_PUBKEYTYPE
+    WitnessPubKeyType,
+    /// a number operand longer than allowed, with its length
+    NumOverflow(usize),
+    /// a number operand that isn't minimally encoded
+    NonMinimalNum,
+    /// OP_CAT and friends, disabled since 2010
+    DisabledOpcode(u8),
+    /// reserved or undefined opcode
+    BadOpcode(u8),
+    /// defined opcode the interpreter can't run yet
+    UnimplementedOpcode(u8),
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScriptError::EvalFalse => write!(f, "script evaluated without error but finished with a false/empty top stack element"),
+            ScriptError::InvalidStackOperation => write!(f, "operation not valid with the current stack size"),
+            ScriptError::Verify => write!(f, "script failed an OP_VERIFY operation"),
+            ScriptError::OpReturn => write!(f, "OP_RETURN was encountered"),
+            ScriptError::UnbalancedConditional => write!(f, "invalid OP_IF construction"),
+            ScriptError::InvalidAltstackOperation => write!(f, "operation not valid with the current altstack size"),
+            ScriptError::MinimalIf => write!(f, "OP_IF/NOTIF argument must be minimal"),
+            ScriptError::OpCodeSeparator => write!(f, "using OP_CODESEPARATOR in non-witness script"),
+            ScriptError::SigFindAndDelete => write!(f, "signature is found in scriptCode"),
+            ScriptError::MinimalData => write!(f, "data push larger than necessary"),
+            ScriptError::NegativeLocktime => write!(f, "negative locktime"),
+            ScriptError::UnsatisfiedLocktime => write!(f, "locktime requirement not satisfied"),
+            ScriptError::EqualVerify => write!(f, "script failed an OP_EQUALVERIFY operation"),
+            ScriptError::NumEqualVerify => write!(f, "script failed an OP_NUMEQUALVERIFY operation"),
+            ScriptError::CheckSigVerify => write!(f, "script failed an OP_CHECKSIGVERIFY operation"),
+            ScriptError::CheckMultiSigVerify => write!(f, "script failed an OP_CHECKMULTISIGVERIFY operation"),
+            ScriptError::PubKeyCount => write!(f, "pubkey count negative or limit exceeded"),
+            ScriptError::SigCount => write!(f, "signature count negative or greater than pubkey count"),
+            ScriptError::SigNullDummy => write!(f, "dummy CHECKMULTISIG argument must be zero"),
+            ScriptError::SigDer => write!(f, "non-canonical DER signature"),
+            ScriptError::SigHighS => write!(f, "non-canonical signature: S value is unnecessarily high"),
+            ScriptError::SigHashType => write!(f, "signature hash type missing or not understood"),
+            ScriptError::PubKeyType => write!(f, "public key is neither compressed or uncompressed"),
+            ScriptError::NullFail => write!(f, "signature must be zero for failed CHECK(MULTI)SIG operation"),
+            ScriptError::CleanStack => write!(f, "stack size must be exactly one after execution"),
+            ScriptError::DiscourageUpgradableNops => write!(f, "NOPx reserved for soft-fork upgrades"),
+            ScriptError::ScriptSize => write!(f, "script is too big"),
+            ScriptError::PushSize => write!(f, "push value size limit exceeded"),
+            ScriptError::OpCount => write!(f, "operation limit exceeded"),
+            ScriptError::StackSize => write!(f, "stack size limit exceeded"),
+            ScriptError::SigPushOnly => write!(f, "only push operators allowed in signatures"),
+            ScriptError::BadRedeemScript => write!(f, "redeem script ends in the middle of a push"),
+            ScriptError::WitnessUnexpected => write!(f, "witness provided for non-witness script"),
+            ScriptError::WitnessMalleated => write!(f, "witness requires empty scriptSig"),
+            ScriptError::WitnessMalleatedP2sh => write!(f, "witness requires only-redeemscript scriptSig"),
+            ScriptError::DiscourageUpgradableWitnessProgram => write!(f, "witness version reserved for soft-fork upgrades"),
+            ScriptError::TaprootWrongControlSize => write!(f, "invalid taproot control block size"),
+            ScriptError::SchnorrSig => write!(f, "invalid Schnorr signature"),
+            ScriptError::SchnorrSigSize => write!(f, "invalid Schnorr signature size"),
+            ScriptError::SchnorrSigHashType => write!(f, "invalid Schnorr signature hash type"),
+            ScriptError::TapscriptValidationWeight => write!(f, "too much signature validation relative to witness weight"),
+            ScriptError::TapscriptCheckMultiSig => write!(f, "OP_CHECKMULTISIG(VERIFY) is not available in tapscript"),
+            ScriptError::TapscriptMinimalIf => write!(f, "OP_IF/NOTIF argument must be minimal in tapscript"),
+            ScriptError::DiscourageOpSuccess => write!(f, "OP_SUCCESSx reserved for soft-fork upgrades"),
+            ScriptError::DiscourageUpgradableTaprootVersion => write!(f, "taproot version reserved for soft-fork upgrades"),
+            ScriptError::DiscourageUpgradablePubKeyType => write!(f, "public key version reserved for soft-fork upgrades"),
+            ScriptError::WitnessProgramWrongLength => write!(f, "witness program has incorrect length"),
+            ScriptError::WitnessProgramWitnessEmpty => write!(f, "witness program was passed an empty witness"),
+            ScriptError::WitnessProgramMismatch => write!(f, "witness program hash mismatch"),
+            ScriptError::WitnessPubKeyType => write!(f, "using non-compressed keys in segwit"),
+            ScriptError::NumOverflow(len) => write!(f, "script number of {} bytes overflows", len),
+            ScriptError::NonMinimalNum => write!(f, "script number is not minimally encoded"),
+            ScriptError::DisabledOpcode(op) => write!(f, "attempted to use a disabled opcode 0x{:02x}", op),
+            ScriptError::BadOpcode(op) => write!(f, "opcode 0x{:02x} missing or not understood", op),
+            ScriptError::UnimplementedOpcode(op) => write!(f, "opcode 0x{:02x} is not supported", op),
+        }
+    }
+}
+
+
+/// script verification rules on top of the consensus rules every script follows, bit positions
+/// match Bitcoin Core's SCRIPT_VERIFY_* flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VerifyFlags(pub u32);
+
+impl VerifyFlags {
+    pub const NONE: Self = Self(0);
+    /// run the redeem script of P2SH outputs (BIP16)
+    pub const P2SH: Self = Self(1 << 0);
+    /// signatures and public keys must be strictly encoded with a defined sighash type
+    pub const STRICTENC: Self = Self(1 << 1);
+    /// signatures must be strict DER (BIP66)
+    pub const DERSIG: Self = Self(1 << 2);
+    /// signatures must have s at most n/2
+    pub const LOW_S: Self = Self(1 << 3);
+    /// the dummy element CHECKMULTISIG pops must be empty (BIP147)
+    pub const NULLDUMMY: Self = Self(1 << 4);
+    /// scriptSig may only push data
+    pub const SIGPUSHONLY: Self = Self(1 << 5);
+    /// data and numbers must use the shortest encoding
+    pub const MINIMALDATA: Self = Self(1 << 6);
+    /// NOP1 and NOP4 to NOP10 fail instead of doing nothing
+    pub const DISCOURAGE_UPGRADABLE_NOPS: Self = Self(1 << 7);
+    /// exactly one element must be left on the stack
+    pub const CLEANSTACK: Self = Self(1 << 8);
+    /// run OP_CHECKLOCKTIMEVERIFY instead of treating it as OP_NOP2 (BIP65)
+    pub const CHECKLOCKTIMEVERIFY: Self = Self(1 << 9);
+    /// run OP_CHECKSEQUENCEVERIFY instead of treating it as OP_NOP3 (BIP112)
+    pub const CHECKSEQUENCEVERIFY: Self = Self(1 << 10);
+    /// verify segwit outputs against the input's witness (BIP141)
+    pub const WITNESS: Self = Self(1 << 11);
+    /// witness programs of unknown versions fail instead of passing
+    pub const DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM: Self = Self(1 << 12);
+    /// IF/NOTIF arguments in witness scripts must be empty or exactly 0x01
+    pub const MINIMALIF: Self = Self(1 << 13);
+    /// failed signature checks must have empty signatures (BIP146)
+    pub const NULLFAIL: Self = Self(1 << 14);
+    /// witness scripts may only use compressed public keys
+    pub const WITNESS_PUBKEYTYPE: Self = Self(1 << 15);
+    /// legacy scripts may not use OP_CODESEPARATOR or have signatures FindAndDelete removes
+    pub const CONST_SCRIPTCODE: Self = Self(1 << 16);
+    /// verify taproot outputs and tapscripts (BIP341, BIP342)
+    pub const TAPROOT: Self = Self(1 << 17);
+    /// tapleaf versions other than 0xc0 fail instead of passing
+    pub const DISCOURAGE_UPGRADABLE_TAPROOT_VERSION: Self = Self(1 << 18);
+    /// OP_SUCCESSx fail instead of passing
+    pub const DISCOURAGE_OP_SUCCESS: Self = Self(1 << 19);
+    /// tapscript keys other than 32 bytes fail instead of passing
+    pub const DISCOURAGE_UPGRADABLE_PUBKEYTYPE: Self = Self(1 << 20);
+
+    /// the rules blocks have to follow today
+    pub const CONSENSUS: Self = Self(
+        Self::P2SH.0 | Self::DERSIG.0 | Self::NULLDUMMY.0 | Self::CHECKLOCKTIMEVERIFY.0 |
+        Self::CHECKSEQUENCEVERIFY.0 | Self::WITNESS.0 | Self::TAPROOT.0
+    );
+    /// the rules Bitcoin Core relays transactions under
+    pub const STANDARD: Self = Self(
+        Self::CONSENSUS.0 | Self::STRICTENC.0 | Self::LOW_S.0 | Self::MINIMALDATA.0 |
+        Self::DISCOURAGE_UPGRADABLE_NOPS.0 | Self::CLEANSTACK.0 | Self::MINIMALIF.0 | Self::NULLFAIL.0 |
+        Self::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM.0 | Self::WITNESS_PUBKEYTYPE.0 | Self::CONST_SCRIPTCODE.0 |
+        Self::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION.0 | Self::DISCOURAGE_OP_SUCCESS.0 |
+        Self::DISCOURAGE_UPGRADABLE_PUBKEYTYPE.0
+    );
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for VerifyFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+
+/// which rules a script runs under, it decides how signatures hash the transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigVersion {
+    /// scriptSig, scriptPubKey and P2SH redeem scripts
+    Base,
+    /// P2WPKH and P2WSH scripts (BIP143)
+    WitnessV0,
+    /// taproot script path spends (BIP342)
+    Tapscript,
+}
+
+
+/// what taproot signatures commit to besides the transaction
+#[derive(Debug, Clone)]
+pub struct ExecData {
+    /// the leaf being executed, None for key path spends
+    pub tapleaf_hash: Option<Vec<u8>>,
+    /// the last witness element if it starts with 0x50
+    pub annex: Option<Vec<u8>>,
+    /// position of the last executed OP_CODESEPARATOR, 0xffffffff for none
+    pub codesep_pos: u32,
+    /// signature budget left, see VALIDATION_WEIGHT_OFFSET
+    pub validation_weight_left: i64,
+}
+
+impl Default for ExecData {
+    fn default() -> Self {
+        Self {
+            tapleaf_hash: None,
+            annex: None,
+            codesep_pos: 0xffffffff,
+            validation_weight_left: 0,
+        }
+    }
+}
+
+
+/// what the signature opcodes check against, normally the transaction spending the output
+pub trait SignatureChecker {
+    /// hash a signature with `sighash_type` commits to, `script_code` is the script being executed
+    fn sig_hash(&self, script_code: &Script, sighash_type: u8, sig_version: SigVersion) -> BigInt;
+
+    /// hash a taproot signature commits to (BIP341), None if `sighash_type` can't sign this input
+    fn tap_sig_hash(&self, sighash_type: u8, exec_data: &ExecData) -> Option<BigInt>;
+
+    /// whether the spending transaction is past the absolute `locktime`
+    fn check_locktime(&self, _locktime: i64) -> bool {
+        false
+    }
+
+    /// whether the spending input is past the relative locktime `sequence`
+    fn check_sequence(&self, _sequence: i64) -> bool {
+        false
+    }
+}
+
+/// the same hash for every signature, taproot ones only with SIGHASH_DEFAULT and no
+/// OP_CODESEPARATOR executed since they'd commit to more than one fixed hash can stand for
+impl SignatureChecker for BigInt {
+    fn sig_hash(&self, _script_code: &Script, _sighash_type: u8, _sig_version: SigVersion) -> BigInt {
+        self.clone()
+    }
+
+    fn tap_sig_hash(&self, sighash_type: u8, exec_data: &ExecData) -> Option<BigInt> {
+        if sighash_type != SIGHASH_DEFAULT || exec_data.codesep_pos != 0xffffffff {
+            return None;
+        }
+        Some(self.clone())
+    }
+}
+
+
+// -- Interpreter --
+
+/// executes a script one command at a time
+pub struct Interpreter<'a> {
+    pub stack: Vec<Vec<u8>>,
+    pub alt_stack: Vec<Vec<u8>>,
+    /// one entry per enclosing IF, whether its current branch executes
+    pub exec_stack: Vec<bool>,
+    pub cmds: Vec<ScriptCmd>,
+    /// index of the next command to execute
+    pub pc: usize,
+    /// opcodes above OP_16 seen so far plus CHECKMULTISIG keys
+    pub op_count: usize,
+    /// index of the command after the last executed OP_CODESEPARATOR, where the script
+    /// signatures commit to starts
+    pub code_start: usize,
+    /// tapscript signature context
+    pub exec_data: ExecData,
+    checker: &'a dyn SignatureChecker,
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(script: &Script, checker: &'a dyn SignatureChecker, flags: VerifyFlags) -> Self {
+        Self::with_stack(script, vec![], checker, flags, SigVersion::Base)
+    }
+
+    /// start on the stack a previous script left, or on a witness
+    pub fn with_stack(
+        script: &Script,
+        stack: Vec<Vec<u8>>,
+        checker: &'a dyn SignatureChecker,
+        flags: VerifyFlags,
+        sig_version: SigVersion,
+    ) -> Self {
+        Self {
+            stack,
+            alt_stack: vec![],
+            exec_stack: vec![],
+            cmds: script.cmds.clone(),
+            pc: 0,
+            op_count: 0,
+            code_start: 0,
+            exec_data: ExecData::default(),
+            checker,
+            flags,
+            sig_version,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pc >= self.cmds.len()
+    }
+
+    /// whether every enclosing IF branch is taken
+    pub fn is_executing(&self) -> bool {
+        self.exec_stack.iter().all(|b| *b)
+    }
+
+    /// execute the command at `pc`, skipping it inside an unexecuted branch
+    pub fn step(&mut self) -> Result<(), ScriptError> {
+        // tapscripts are only limited by the block size
+        let tapscript = self.sig_version == SigVersion::Tapscript;
+        if self.pc == 0 && !tapscript && self.script_code().raw_serialize().len() > MAX_SCRIPT_SIZE {
+            return Err(ScriptError::ScriptSize);
+        }
+
+        let cmd = self.cmds[self.pc].clone();
+        self.pc += 1;
+        self.execute(cmd)?;
+
+        if self.stack.len() + self.alt_stack.len() > MAX_STACK_SIZE {
+            return Err(ScriptError::StackSize);
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, cmd: ScriptCmd) -> Result<(), ScriptError> {
+        let executing = self.is_executing();
+
+        let op = match cmd {
+            ScriptCmd::OpCode(op) => op,
+            // undecodable bytes fail even in unexecuted branches
+            ScriptCmd::Invalid(bytes) => return Err(ScriptError::BadOpcode(bytes[0])),
+            push => {
+                if push.data().unwrap().len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    return Err(ScriptError::PushSize);
+                }
+                if executing {
+                    if self.flags.contains(VerifyFlags::MINIMALDATA) && !push.is_minimal_push() {
+                        return Err(ScriptError::MinimalData);
+                    }
+                    self.stack.push(push.data().unwrap().to_owned());
+                }
+                return Ok(());
+            },
+        };
+
+        // these fail even in unexecuted branches
+        if op > OP_16 && self.sig_version != SigVersion::Tapscript {
+            self.op_count += 1;
+            if self.op_count > MAX_OPS_PER_SCRIPT {
+                return Err(ScriptError::OpCount);
+            }
+        }
+        if is_disabled(op) {
+            return Err(ScriptError::DisabledOpcode(op));
+        }
+        if op == OP_VERIF || op == OP_VERNOTIF {
+            return Err(ScriptError::BadOpcode(op));
+        }
+        if op == OP_CODESEPARATOR && self.sig_version == SigVersion::Base && self.flags.contains(VerifyFlags::CONST_SCRIPTCODE) {
+            return Err(ScriptError::OpCodeSeparator);
+        }
+
+        match op {
+            OP_IF => return op_if(&mut self.stack, &mut self.exec_stack, self.flags, self.sig_version),
+            OP_NOTIF => return op_notif(&mut self.stack, &mut self.exec_stack, self.flags, self.sig_version),
+            OP_ELSE => return op_else(&mut self.exec_stack),
+            OP_ENDIF => return op_endif(&mut self.exec_stack),
+            _ => {},
+        }
+        if !executing {
+            return Ok(());
+        }
+
+        if let Some(result) = execute_stack_op(op, &mut self.stack, self.flags) {
+            return result;
+        }
+
+        if self.sig_version == SigVersion::Tapscript {
+            match op {
+                OP_CODESEPARATOR => {
+                    self.exec_data.codesep_pos = (self.pc - 1) as u32;
+                    return Ok(());
+                },
+                OP_CHECKSIG => return op_checksig_tapscript(&mut self.stack, self.checker, &mut self.exec_data, self.flags),
+                OP_CHECKSIGVERIFY => return op_checksigverify_tapscript(&mut self.stack, self.checker, &mut self.exec_data, self.flags),
+                OP_CHECKSIGADD => return op_checksigadd(&mut self.stack, self.checker, &mut self.exec_data, self.flags),
+                OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => return Err(ScriptError::TapscriptCheckMultiSig),
+                _ => {},
+            }
+        }
+
+        match op {
+            OP_TOALTSTACK => op_toaltstack(&mut self.stack, &mut self.alt_stack),
+            OP_FROMALTSTACK => op_fromaltstack(&mut self.stack, &mut self.alt_stack),
+            OP_CODESEPARATOR => {
+                self.code_start = self.pc;
+                Ok(())
+            },
+            OP_CHECKSIG => {
+                let script_code = self.script_code();
+                op_checksig(&mut self.stack, self.checker, &script_code, self.flags, self.sig_version)
+            },
+            OP_CHECKSIGVERIFY => {
+                let script_code = self.script_code();
+                op_checksigverify(&mut self.stack, self.checker, &script_code, self.flags, self.sig_version)
+            },
+            OP_CHECKMULTISIG => {
+                let script_code = self.script_code();
+                op_checkmultisig(&mut self.stack, self.checker, &script_code, self.flags, self.sig_version, &mut self.op_count)
+            },
+            OP_CHECKMULTISIGVERIFY => {
+                let script_code = self.script_code();
+                op_checkmultisigverify(&mut self.stack, self.checker, &script_code, self.flags, self.sig_version, &mut self.op_count)
+            },
+            OP_CHECKLOCKTIMEVERIFY => op_checklocktimeverify(&mut self.stack, self.checker, self.flags),
+            OP_CHECKSEQUENCEVERIFY => op_checksequenceverify(&mut self.stack, self.checker, self.flags),
+            _ => Err(ScriptError::BadOpcode(op)),
+        }
+    }
+
+    /// the script signatures commit to, from the last executed OP_CODESEPARATOR on
+    fn script_code(&self) -> Script {
+        Script::new(Some(&self.cmds[self.code_start..]))
+    }
+
+    /// run the remaining commands, succeeds if the script leaves a true value on top of the stack
+    pub fn run(&mut self) -> Result<(), ScriptError> {
+        self.execute_all()?;
+        self.finish()
+    }
+
+    /// run the remaining commands without looking at the result
+    fn execute_all(&mut self) -> Result<(), ScriptError> {
+        while !self.is_done() {
+            self.step()?;
+        }
+        if !self.exec_stack.is_empty() {
+            return Err(ScriptError::UnbalancedConditional);
+        }
+        Ok(())
+    }
+
+    /// the checks after the last command, whether the script succeeded
+    pub fn finish(&self) -> Result<(), ScriptError> {
+        if !self.exec_stack.is_empty() {
+            return Err(ScriptError::UnbalancedConditional);
+        }
+
+        match self.stack.last() {
+            Some(top) if cast_to_bool(top) => {},
+            _ => return Err(ScriptError::EvalFalse),
+        }
+        if self.flags.contains(VerifyFlags::CLEANSTACK) && self.stack.len() != 1 {
+            return Err(ScriptError::CleanStack);
+        }
+        Ok(())
+    }
+}
+
+
+
+// -- verification --
+
+/// run `script` on `stack`, leaving the stack as the script left it
+fn eval_script(
+    stack: Vec<Vec<u8>>,
+    script: &Script,
+    checker: &dyn SignatureChecker,
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+) -> Result<Vec<Vec<u8>>, ScriptError> {
+    let mut interpreter = Interpreter::with_stack(script, stack, checker, flags, sig_version);
+    interpreter.execute_all()?;
+    Ok(interpreter.stack)
+}
+
+fn require_true(stack: &[Vec<u8>]) -> Result<(), ScriptError> {
+    match stack.last() {
+        Some(top) if cast_to_bool(top) => Ok(()),
+        _ => Err(ScriptError::EvalFalse),
+    }
+}
+
+/// run a witness script on the rest of the witness, which must leave exactly one true element
+fn execute_witness_script(
+    stack: &[Vec<u8>],
+    script: &Script,
+    checker: &dyn SignatureChecker,
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+    exec_data: ExecData,
+) -> Result<(), ScriptError> {
+    if sig_version == SigVersion::Tapscript && stack.len() > MAX_STACK_SIZE {
+        return Err(ScriptError::StackSize);
+    }
+    if stack.iter().any(|element| element.len() > MAX_SCRIPT_ELEMENT_SIZE) {
+        return Err(ScriptError::PushSize);
+    }
+
+    let mut interpreter = Interpreter::with_stack(script, stack.to_owned(), checker, flags, sig_version);
+    interpreter.exec_data = exec_data;
+    interpreter.execute_all()?;
+    if interpreter.stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    require_true(&interpreter.stack)
+}
+
+/// the leaf version of tapscripts
+pub const TAPROOT_LEAF_TAPSCRIPT: u8 = 0xc0;
+/// a control block is the leaf version and internal key, then up to 128 merkle path hashes
+const TAPROOT_CONTROL_BASE_SIZE: usize = 33;
+const TAPROOT_CONTROL_NODE_SIZE: usize = 32;
+const TAPROOT_CONTROL_MAX_NODE_COUNT: usize = 128;
+
+/// whether the control block proves `tapleaf_hash` is committed to in the output key `program`
+fn verify_taproot_commitment(control: &[u8], program: &[u8], tapleaf_hash: &[u8]) -> bool {
+    let mut sec = vec![2u8];
+    sec.extend_from_slice(&control[1..TAPROOT_CONTROL_BASE_SIZE]);
+    if !S256Point::is_valid_sec(&sec) {
+        return false;
+    }
+
+    let mut k = tapleaf_hash.to_owned();
+    for node in control[TAPROOT_CONTROL_BASE_SIZE..].chunks(TAPROOT_CONTROL_NODE_SIZE) {
+        let mut msg = if k.as_slice() < node { k.clone() } else { node.to_owned() };
+        msg.extend_from_slice(if k.as_slice() < node { node } else { &k });
+        k = tagged_hash("TapBranch", &msg);
+    }
+
+    let output_key = S256Point::parse(sec).tap_tweak(Some(&k));
+    output_key.xonly() == program && output_key.has_even_y() == (control[0] & 1 == 0)
+}
+
+/// check the witness against a segwit output's program, version 0 programs are the hash of a
+/// public key (P2WPKH) or of a script (P2WSH) the witness reveals, version 1 programs are taproot
+/// output keys spent with a signature or a script committed to in the key
+fn verify_witness_program(
+    witness: &[Vec<u8>],
+    version: u8,
+    program: &[u8],
+    checker: &dyn SignatureChecker,
+    flags: VerifyFlags,
+    is_p2sh: bool,
+) -> Result<(), ScriptError> {
+    match version {
+        0 if program.len() == 32 => {
+            let (witness_script, stack) = witness.split_last().ok_or(ScriptError::WitnessProgramWitnessEmpty)?;
+            if sha256(witness_script) != program {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            let script = Script::parse_raw(witness_script).ok_or(ScriptError::BadRedeemScript)?;
+            execute_witness_script(stack, &script, checker, flags, SigVersion::WitnessV0, ExecData::default())
+        },
+        0 if program.len() == 20 => {
+            if witness.len() != 2 {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            execute_witness_script(witness, &Script::p2pkh(program), checker, flags, SigVersion::WitnessV0, ExecData::default())
+        },
+        0 => Err(ScriptError::WitnessProgramWrongLength),
+        // before TAPROOT these are unencumbered, whatever the discourage flag says
+        1 if program.len() == 32 && !is_p2sh => {
+            if !flags.contains(VerifyFlags::TAPROOT) {
+                return Ok(());
+            }
+            verify_taproot(witness, program, checker, flags)
+        },
+        // anyone can spend until a soft fork gives the version a meaning
+        _ if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM) => {
+            Err(ScriptError::DiscourageUpgradableWitnessProgram)
+        },
+        _ => Ok(()),
+    }
+}
+
+/// BIP341 key path and script path spends
+fn verify_taproot(witness: &[Vec<u8>], program: &[u8], checker: &dyn SignatureChecker, flags: VerifyFlags) -> Result<(), ScriptError> {
+    let mut stack = witness.to_owned();
+    if stack.is_empty() {
+        return Err(ScriptError::WitnessProgramWitnessEmpty);
+    }
+    let mut exec_data = ExecData::default();
+    if stack.len() >= 2 && stack[stack.len() - 1].first() == Some(&0x50) {
+        exec_data.annex = stack.pop();
+    }
+
+    if stack.len() == 1 {
+        return check_schnorr_sig(&stack[0], program, checker, &exec_data);
+    }
+
+    let control = stack.pop().unwrap();
+    let tapscript = stack.pop().unwrap();
+    if control.len() < TAPROOT_CONTROL_BASE_SIZE ||
+        control.len() > TAPROOT_CONTROL_BASE_SIZE + TAPROOT_CONTROL_NODE_SIZE * TAPROOT_CONTROL_MAX_NODE_COUNT ||
+        !(control.len() - TAPROOT_CONTROL_BASE_SIZE).is_multiple_of(TAPROOT_CONTROL_NODE_SIZE) {
+        return Err(ScriptError::TaprootWrongControlSize);
+    }
+
+    let leaf_version = control[0] & 0xfe;
+    let mut leaf = vec![leaf_version];
+    leaf.extend_from_slice(&encode_varint(&BigInt::from(tapscript.len())));
+    leaf.extend_from_slice(&tapscript);
+    let tapleaf_hash = tagged_hash("TapLeaf", &leaf);
+    if !verify_taproot_commitment(&control, program, &tapleaf_hash) {
+        return Err(ScriptError::WitnessProgramMismatch);
+    }
+
+    if leaf_version != TAPROOT_LEAF_TAPSCRIPT {
+        if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION) {
+            return Err(ScriptError::DiscourageUpgradableTaprootVersion);
+        }
+        return Ok(());
+    }
+
+    // the budget is paid for by the whole witness, annex included
+    let witness_size: usize = encode_varint(&BigInt::from(witness.len())).len() +
+        witness.iter().map(|element| encode_varint(&BigInt::from(element.len())).len() + element.len()).sum::<usize>();
+    exec_data.tapleaf_hash = Some(tapleaf_hash);
+    exec_data.validation_weight_left = witness_size as i64 + VALIDATION_WEIGHT_OFFSET;
+
+    // any OP_SUCCESSx makes the whole script succeed, before anything else is checked
+    if has_op_success(&tapscript)? {
+        if flags.contains(VerifyFlags::DISCOURAGE_OP_SUCCESS) {
+            return Err(ScriptError::DiscourageOpSuccess);
+        }
+        return Ok(());
+    }
+    let script = Script::parse_raw(&tapscript).ok_or(ScriptError::BadRedeemScript)?;
+    execute_witness_script(&stack, &script, checker, flags, SigVersion::Tapscript, exec_data)
+}
+
+/// walk the raw tapscript opcode by opcode up to the first OP_SUCCESSx, so a push running
+/// past the end only fails the script if it comes before one
+fn has_op_success(script: &[u8]) -> Result<bool, ScriptError> {
+    let mut count = 0usize;
+    while count < script.len() {
+        let op = script[count];
+        count += 1;
+        if is_op_success(op) {
+            return Ok(true);
+        }
+
+        let data_len = match op {
+            1..=75 => op as usize,
+            OP_PUSHDATA1 | OP_PUSHDATA2 | OP_PUSHDATA4 => {
+                let size = match op {
+                    OP_PUSHDATA1 => 1,
+                    OP_PUSHDATA2 => 2,
+                    _ => 4,
+                };
+                let len = script.get(count..(count + size)).ok_or(ScriptError::BadOpcode(op))?;
+                count += size;
+                little_endian_to_int(len).to_usize().unwrap()
+            },
+            _ => 0,
+        };
+        if script.len() - count < data_len {
+            return Err(ScriptError::BadOpcode(op));
+        }
+        count += data_len;
+    }
+
+    Ok(false)
+}
+
+/// whether an input with `script_sig` and `witness` may spend an output locked by
+/// `script_pubkey`, with the redeem script of P2SH outputs run on what the scriptSig pushed
+/// before it (BIP16)
+pub fn verify_script(
+    script_sig: &Script,
+    script_pubkey: &Script,
+    witness: &[Vec<u8>],
+    checker: &dyn SignatureChecker,
+    flags: VerifyFlags,
+) -> Result<(), ScriptError> {
+    if flags.contains(VerifyFlags::SIGPUSHONLY) && !script_sig.is_push_only() {
+        return Err(ScriptError::SigPushOnly);
+    }
+
+    let stack = eval_script(vec![], script_sig, checker, flags, SigVersion::Base)?;
+    let p2sh_stack = stack.clone();
+    let mut stack = eval_script(stack, script_pubkey, checker, flags, SigVersion::Base)?;
+    require_true(&stack)?;
+
+    let mut had_witness = false;
+    if flags.contains(VerifyFlags::WITNESS) {
+        if let Some((version, program)) = script_pubkey.witness_program() {
+            had_witness = true;
+            if !script_sig.cmds.is_empty() {
+                return Err(ScriptError::WitnessMalleated);
+            }
+            verify_witness_program(witness, version, &program, checker, flags, false)?;
+            // the witness ran on its own stack, leave a single true for CLEANSTACK
+            stack.truncate(1);
+        }
+    }
+
+    if flags.contains(VerifyFlags::P2SH) && matches!(script_pubkey.classify(), ScriptType::P2sh(_)) {
+        if !script_sig.is_push_only() {
+            return Err(ScriptError::SigPushOnly);
+        }
+
+        // the scriptSig pushed at least the redeem script for the hash to match
+        stack = p2sh_stack;
+        let serialized_redeem_script = stack.pop().unwrap();
+        let redeem_script = Script::parse_raw(&serialized_redeem_script).ok_or(ScriptError::BadRedeemScript)?;
+        stack = eval_script(stack, &redeem_script, checker, flags, SigVersion::Base)?;
+        require_true(&stack)?;
+
+        // P2SH wrapped segwit
+        if flags.contains(VerifyFlags::WITNESS) {
+            if let Some((version, program)) = redeem_script.witness_program() {
+                had_witness = true;
+                let single_push = Script::new(Some(&[ScriptCmd::Cmd(serialized_redeem_script)]));
+                if script_sig.raw_serialize() != single_push.raw_serialize() {
+                    return Err(ScriptError::WitnessMalleatedP2sh);
+                }
+                verify_witness_program(witness, version, &program, checker, flags, true)?;
+                stack.truncate(1);
+            }
+        }
+    }
+
+    if flags.contains(VerifyFlags::CLEANSTACK) && stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    if flags.contains(VerifyFlags::WITNESS) && !had_witness && !witness.is_empty() {
+        return Err(ScriptError::WitnessUnexpected);
+    }
+
+    Ok(())
+}
+
+
+
+// -- sigops --
+
+/// sigops of a witness program spend, a P2WPKH is one signature check and a P2WSH counts the
+/// witness script accurately, taproot and unknown versions have none
+fn witness_program_sigop_count(version: u8, program: &[u8], witness: &[Vec<u8>]) -> usize {
+    match (version, program.len()) {
+        (0, 20) => 1,
+        (0, 32) => match witness.last() {
+            Some(witness_script) => Script::parse_raw_lenient(witness_script).sigop_count(true),
+            None => 0,
+        },
+        _ => 0,
+    }
+}
+
+/// sigops the witness adds to spending `script_pubkey`, natively or wrapped in P2SH, these weigh a
+/// quarter of legacy sigops
+pub fn witness_sigop_count(script_sig: &Script, script_pubkey: &Script, witness: &[Vec<u8>], flags: VerifyFlags) -> usize {
+    if !flags.contains(VerifyFlags::WITNESS) {
+        return 0;
+    }
+
+    if let Some((version, program)) = script_pubkey.witness_program() {
+        return witness_program_sigop_count(version, &program, witness);
+    }
+
+    if matches!(script_pubkey.classify(), ScriptType::P2sh(_)) && script_sig.is_push_only() {
+        let redeem_script = script_sig.cmds.last().and_then(ScriptCmd::data).and_then(Script::parse_raw);
+        if let Some((version, program)) = redeem_script.and_then(|script| script.witness_program()) {
+            return witness_program_sigop_count(version, &program, witness);
+        }
+    }
+
+    0
+}
+
+#[cfg(test)]
+mod tests_interpreter {
+    use std::cell::RefCell;
+
+    use hex::ToHex;
+    use num_bigint::BigInt;
+
+    use crate::{
+        network::Network,
+        op::*,
+        script::{Script, ScriptCmd},
+        private_key::PrivateKey,
+        transaction::{Tx, TxIn, TransactionChecker, SIGHASH_ALL, SIGHASH_DEFAULT},
+        utils::{sha1, sha256, hash160, tagged_hash, encode_varint, int_to_big_endian},
+    };
+
+    use super::{ExecData, ScriptError, SigVersion, SignatureChecker, VerifyFlags, TAPROOT_LEAF_TAPSCRIPT, verify_script, witness_sigop_count};
+
+    fn script(cmds: &[ScriptCmd]) -> Script {
+        Script::new(Some(cmds))
+    }
+
+    /// a hash, an uncompressed public key and its signature of the hash with SIGHASH_ALL
+    fn signed_hash() -> (BigInt, Vec<u8>, Vec<u8>) {
+        let z = BigInt::parse_bytes(b"7c076ff316692a3d7eb3c3bb0f8b1488cf72e1afcd929e29307032997a838a3d", 16).unwrap();
+        let sec = hex::decode("04887387e452b8eacc4acfde10d9aaf7f6d9a0f975aabb10d006e4da568744d06c61de6d95231cd89026e286df3b6ae4a894a3378e393e93a0f45b666329a0ae34").unwrap();
+        let sig = hex::decode("3045022000eff69ef2b1bd93a66ed5219add4fb51e11a840f404876325a1e8ffe0529a2c022100c7207fee197d27c618aea621406f6bf5ef6fca38681d82b2f06fddbdce6feab601").unwrap();
+        (z, sec, sig)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        // 4 + 5 == 9
+        let script_sig = script(&[ScriptCmd::OpCode(OP_4)]);
+        let script_pubkey = script(&[ScriptCmd::OpCode(OP_5), ScriptCmd::OpCode(OP_ADD), ScriptCmd::OpCode(OP_9), ScriptCmd::OpCode(OP_EQUAL)]);
+        let combined = script_sig + script_pubkey.clone();
+        assert_eq!(Ok(()), combined.evaluate(&BigInt::from(0)));
+
+        let combined = script(&[ScriptCmd::OpCode(OP_3)]) + script_pubkey;
+        assert_eq!(Err(ScriptError::EvalFalse), combined.evaluate(&BigInt::from(0)));
+
+        // hash puzzles
+        for (op, hash) in [(OP_SHA1, sha1(b"abc")), (OP_SHA256, sha256(b"abc")), (OP_HASH160, hash160(b"abc"))] {
+            let script_pubkey = script(&[ScriptCmd::OpCode(op), ScriptCmd::Cmd(hash), ScriptCmd::OpCode(OP_EQUAL)]);
+            let combined = script(&[ScriptCmd::Cmd(b"abc".to_vec())]) + script_pubkey;
+            assert_eq!(Ok(()), combined.evaluate(&BigInt::from(0)));
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let z = BigInt::from(0);
+
+        let s = script(&[ScriptCmd::OpCode(OP_1), ScriptCmd::OpCode(OP_ADD)]);
+        assert_eq!(Err(ScriptError::InvalidStackOperation), s.evaluate(&z));
+
+        let s = script(&[ScriptCmd::OpCode(OP_1), ScriptCmd::OpCode(OP_1), ScriptCmd::OpCode(OP_CAT)]);
+        assert_eq!(Err(ScriptError::DisabledOpcode(OP_CAT)), s.evaluate(&z));
+
+        let s = script(&[ScriptCmd::OpCode(OP_1), ScriptCmd::OpCode(OP_RESERVED)]);
+        assert_eq!(Err(ScriptError::BadOpcode(OP_RESERVED)), s.evaluate(&z));
+
+        let s = script(&[ScriptCmd::OpCode(OP_1), ScriptCmd::OpCode(OP_2), ScriptCmd::OpCode(OP_EQUALVERIFY)]);
+        assert_eq!(Err(ScriptError::EqualVerify), s.evaluate(&z));
+
+        let s = Script::parse_raw(&hex::decode("4c0101").unwrap()).unwrap();
+        assert_eq!(Ok(()), s.evaluate(&z));
+        assert_eq!(Err(ScriptError::MinimalData), s.evaluate_with(&z, VerifyFlags::MINIMALDATA));
+
+        // negative zero is false
+        let s = script(&[ScriptCmd::Cmd(vec![0x00, 0x80])]);
+        assert_eq!(Err(ScriptError::EvalFalse), s.evaluate(&z));
+    }
+
+    #[test]
+    fn test_stack_ops() {
+        let z = BigInt::from(0);
+
+        // 1 2 3 ROT -> 2 3 1
+        let s = script(&[
+            ScriptCmd::OpCode(OP_1), ScriptCmd::OpCode(OP_2), ScriptCmd::OpCode(OP_3), ScriptCmd::OpCode(OP_ROT),
+            ScriptCmd::OpCode(OP_1), ScriptCmd::OpCode(OP_EQUALVERIFY),
+            ScriptCmd::OpCode(OP_3), ScriptCmd::OpCode(OP_EQUALVERIFY),
+            ScriptCmd::OpCode(OP_2), ScriptCmd::OpCode(OP_EQUAL),
+        ]);
+        assert_eq!(Ok(()), s.evaluate(&z));
+
+        // 5 in [2, 6)
+        let s = script(&[
+            ScriptCmd::OpCode(OP_5), ScriptCmd::OpCode(OP_2), ScriptCmd::OpCode(OP_6), ScriptCmd::OpCode(OP_WITHIN),
+        ]);
+        assert_eq!(Ok(()), s.evaluate(&z));
+
+        // 0 1 2 3 PICK(3) copies the bottom 0
+        let s = script(&[
+            ScriptCmd::OpCode(OP_0), ScriptCmd::OpCode(OP_1), ScriptCmd::OpCode(OP_2), ScriptCmd::OpCode(OP_3),
+            ScriptCmd::OpCode(OP_3), ScriptCmd::OpCode(OP_PICK), ScriptCmd::OpCode(OP_SIZE), ScriptCmd::OpCode(OP_0NOTEQUAL),
+            ScriptCmd::OpCode(OP_NOT),
+        ]);
+        assert_eq!(Ok(()), s.evaluate(&z));
+    }
+
+    #[test]
+    fn test_checksig() {
+        let (z, sec, sig) = signed_hash();
+
+        // p2pk
+        let script_pubkey = script(&[ScriptCmd::Cmd(sec.clone()), ScriptCmd::OpCode(OP_CHECKSIG)]);
+        let combined = script(&[ScriptCmd::Cmd(sig.clone())]) + script_pubkey.clone();
+        assert_eq!(Ok(()), combined.evaluate(&z));
+
+        // signature for another hash
+        assert_eq!(Err(ScriptError::EvalFalse), combined.evaluate(&(z.clone() + 1)));
+
+        // malformed signatures and keys push false instead of failing
+        let combined = script(&[ScriptCmd::Cmd(sig[1..].to_owned())]) + script_pubkey;
+        assert_eq!(Err(ScriptError::EvalFalse), combined.evaluate(&z));
+        let combined = script(&[ScriptCmd::Cmd(sig.clone()), ScriptCmd::Cmd(sec[..33].to_owned()), ScriptCmd::OpCode(OP_CHECKSIG)]);
+        assert_eq!(Err(ScriptError::EvalFalse), combined.evaluate(&z));
+
+        let combined = script(&[
+            ScriptCmd::Cmd(vec![]), ScriptCmd::Cmd(sec), ScriptCmd::OpCode(OP_CHECKSIGVERIFY), ScriptCmd::OpCode(OP_1),
+        ]);
+        assert_eq!(Err(ScriptError::CheckSigVerify), combined.evaluate(&z));
+    }
+
+    #[test]
+    fn test_checkmultisig() {
+        let z = BigInt::parse_bytes(b"e71bfa115715d6fd33796948126f40a8cdd39f187e4afb03896795189fe1423c", 16).unwrap();
+        let sig1 = hex::decode("3045022100dc92655fe37036f47756db8102e0d7d5e28b3beb83a8fef4f5dc0559bddfb94e02205a36d4e4e6c7fcd16658c50783e00c341609977aed3ad00937bf4ee942a8993701").unwrap();
+        let sig2 = hex::decode("3045022100da6bee3c93766232079a01639d07fa869598749729ae323eab8eef53577d611b02207bef15429dcadce2121ea07f233115c6f09034c0be68db99980b9a6c5e75402201").unwrap();
+        let sec1 = hex::decode("022626e955ea6ea6d98850c994f9107b036b1334f18ca8830bfff1295d21cfdb70").unwrap();
+        let sec2 = hex::decode("03b287eaf122eea69030a0e9feed096bed8045c8b98bec453e1ffac7fbdbd4bb71").unwrap();
+
+        let script_pubkey = script(&[
+            ScriptCmd::OpCode(OP_2), ScriptCmd::Cmd(sec1), ScriptCmd::Cmd(sec2), ScriptCmd::OpCode(OP_2), ScriptCmd::OpCode(OP_CHECKMULTISIG),
+        ]);
+        let script_sig = |dummy: ScriptCmd, sigs: &[&Vec<u8>]| {
+            let mut cmds = vec![dummy];
+            cmds.extend(sigs.iter().map(|s| ScriptCmd::Cmd((*s).clone())));
+            script(&cmds) + script_pubkey.clone()
+        };
+
+        assert_eq!(Ok(()), script_sig(ScriptCmd::OpCode(OP_0), &[&sig1, &sig2]).evaluate(&z));
+
+        // signatures must be in key order
+        assert_eq!(Err(ScriptError::EvalFalse), script_sig(ScriptCmd::OpCode(OP_0), &[&sig2, &sig1]).evaluate(&z));
+
+        // the dummy may be anything unless NULLDUMMY is set
+        let combined = script_sig(ScriptCmd::OpCode(OP_1), &[&sig1, &sig2]);
+        assert_eq!(Ok(()), combined.evaluate(&z));
+        assert_eq!(Err(ScriptError::SigNullDummy), combined.evaluate_with(&z, VerifyFlags::NULLDUMMY));
+
+        // without the dummy the stack runs out
+        let combined = script(&[ScriptCmd::Cmd(sig1.clone()), ScriptCmd::Cmd(sig2.clone())]) + script_pubkey.clone();
+        assert_eq!(Err(ScriptError::InvalidStackOperation), combined.evaluate(&z));
+
+        let mut cmds = vec![ScriptCmd::OpCode(OP_0), ScriptCmd::OpCode(OP_0)];
+        cmds.extend((0..21).map(|_| ScriptCmd::Cmd(vec![2; 33])));
+        cmds.extend([ScriptCmd::Cmd(vec![21]), ScriptCmd::OpCode(OP_CHECKMULTISIG)]);
+        assert_eq!(Err(ScriptError::PubKeyCount), script(&cmds).evaluate(&z));
+    }
+
+    #[test]
+    fn test_flow_control() {
+        let z = BigInt::from(0);
+        let ops = |ops: &[u8]| script(&ops.iter().map(|op| ScriptCmd::OpCode(*op)).collect::<Vec<_>>());
+
+        // 1 IF 2 ELSE 3 ENDIF -> 2
+        let s = ops(&[OP_1, OP_IF, OP_2, OP_ELSE, OP_3, OP_ENDIF, OP_2, OP_EQUAL]);
+        assert_eq!(Ok(()), s.evaluate(&z));
+        let s = ops(&[OP_0, OP_NOTIF, OP_2, OP_ELSE, OP_3, OP_ENDIF, OP_2, OP_EQUAL]);
+        assert_eq!(Ok(()), s.evaluate(&z));
+        let s = ops(&[OP_0, OP_IF, OP_2, OP_ELSE, OP_3, OP_ENDIF, OP_3, OP_EQUAL]);
+        assert_eq!(Ok(()), s.evaluate(&z));
+
+        // nested, the inner IF isn't evaluated in the skipped branch and neither is RETURN
+        let s = ops(&[OP_0, OP_IF, OP_IF, OP_RETURN, OP_ENDIF, OP_ELSE, OP_1, OP_ENDIF]);
+        assert_eq!(Ok(()), s.evaluate(&z));
+
+        // but disabled opcodes fail anyway
+        let s = ops(&[OP_0, OP_IF, OP_CAT, OP_ENDIF, OP_1]);
+        assert_eq!(Err(ScriptError::DisabledOpcode(OP_CAT)), s.evaluate(&z));
+
+        assert_eq!(Err(ScriptError::UnbalancedConditional), ops(&[OP_1, OP_IF, OP_1]).evaluate(&z));
+        assert_eq!(Err(ScriptError::UnbalancedConditional), ops(&[OP_1, OP_ENDIF]).evaluate(&z));
+        assert_eq!(Err(ScriptError::UnbalancedConditional), ops(&[OP_IF, OP_ENDIF, OP_1]).evaluate(&z));
+
+        assert_eq!(Err(ScriptError::Verify), ops(&[OP_0, OP_VERIFY, OP_1]).evaluate(&z));
+        assert_eq!(Err(ScriptError::OpReturn), ops(&[OP_1, OP_RETURN]).evaluate(&z));
+
+        // 2 is true, but not minimal, which only matters in witness scripts
+        let s = ops(&[OP_2, OP_IF, OP_1, OP_ENDIF]);
+        assert_eq!(Ok(()), s.evaluate(&z));
+        assert_eq!(Ok(()), s.evaluate_with(&z, VerifyFlags::MINIMALIF));
+        let witness_script = ops(&[OP_IF, OP_1, OP_ENDIF]).raw_serialize();
+        let script_pubkey = Script::p2wsh(&sha256(&witness_script));
+        let flags = VerifyFlags::P2SH | VerifyFlags::WITNESS;
+        let witness = vec![vec![2], witness_script];
+        assert_eq!(Ok(()), verify_script(&script(&[]), &script_pubkey, &witness, &z, flags));
+        assert_eq!(Err(ScriptError::MinimalIf), verify_script(&script(&[]), &script_pubkey, &witness, &z, flags | VerifyFlags::MINIMALIF));
+    }
+
+    #[test]
+    fn test_alt_stack() {
+        let z = BigInt::from(0);
+        let ops = |ops: &[u8]| script(&ops.iter().map(|op| ScriptCmd::OpCode(*op)).collect::<Vec<_>>());
+
+        let s = ops(&[OP_2, OP_3, OP_TOALTSTACK, OP_1, OP_ADD, OP_FROMALTSTACK, OP_EQUAL]);
+        assert_eq!(Ok(()), s.evaluate(&z));
+        assert_eq!(Err(ScriptError::InvalidAltstackOperation), ops(&[OP_1, OP_FROMALTSTACK]).evaluate(&z));
+        assert_eq!(Err(ScriptError::InvalidStackOperation), ops(&[OP_TOALTSTACK]).evaluate(&z));
+    }
+
+    #[test]
+    fn test_timelocks() {
+        let tx_in = TxIn::new(vec![0u8; 32], 0, None, 144);
+        let tx = Tx::new(2, vec![tx_in], vec![], BigInt::from(700_000), Network::Mainnet);
+        let checker = TransactionChecker::new(&tx, 0, BigInt::from(0));
+        let flags = VerifyFlags::CHECKLOCKTIMEVERIFY | VerifyFlags::CHECKSEQUENCEVERIFY;
+        let lock = |n: i64, op: u8| script(&[ScriptCmd::Cmd(encode_num(n)), ScriptCmd::OpCode(op)]);
+
+        assert_eq!(Ok(()), lock(700_000, OP_CHECKLOCKTIMEVERIFY).evaluate_with(&checker, flags));
+        assert_eq!(Err(ScriptError::UnsatisfiedLocktime), lock(700_001, OP_CHECKLOCKTIMEVERIFY).evaluate_with(&checker, flags));
+        assert_eq!(Err(ScriptError::NegativeLocktime), lock(-1, OP_CHECKLOCKTIMEVERIFY).evaluate_with(&checker, flags));
+        // locktimes go up to 5 bytes
+        assert_eq!(Err(ScriptError::UnsatisfiedLocktime), lock(0xffffffff, OP_CHECKLOCKTIMEVERIFY).evaluate_with(&checker, flags));
+
+        assert_eq!(Ok(()), lock(144, OP_CHECKSEQUENCEVERIFY).evaluate_with(&checker, flags));
+        assert_eq!(Err(ScriptError::UnsatisfiedLocktime), lock(145, OP_CHECKSEQUENCEVERIFY).evaluate_with(&checker, flags));
+        // the disable flag makes it a NOP
+        assert_eq!(Ok(()), lock(1 << 31, OP_CHECKSEQUENCEVERIFY).evaluate_with(&checker, flags));
+
+        // without the flags they're NOP2 and NOP3
+        assert_eq!(Ok(()), lock(700_001, OP_CHECKLOCKTIMEVERIFY).evaluate_with(&checker, VerifyFlags::NONE));
+        assert_eq!(Ok(()), lock(145, OP_CHECKSEQUENCEVERIFY).evaluate_with(&checker, VerifyFlags::NONE));
+    }
+
+    #[test]
+    fn test_limits() {
+        let z = BigInt::from(0);
+        let ops = |n: usize, op: u8| (0..n).map(|_| ScriptCmd::OpCode(op)).collect::<Vec<_>>();
+
+        assert_eq!(Err(ScriptError::ScriptSize), script(&ops(10_001, OP_0)).evaluate(&z));
+
+        // pushes are limited even in unexecuted branches
+        let s = script(&[ScriptCmd::Cmd(vec![1; 520])]);
+        assert_eq!(Ok(()), s.evaluate(&z));
+        let s = script(&[
+            ScriptCmd::OpCode(OP_0), ScriptCmd::OpCode(OP_IF), ScriptCmd::Cmd(vec![1; 521]), ScriptCmd::OpCode(OP_ENDIF),
+            ScriptCmd::OpCode(OP_1),
+        ]);
+        assert_eq!(Err(ScriptError::PushSize), s.evaluate(&z));
+
+        let mut cmds = ops(201, OP_NOP);
+        cmds.push(ScriptCmd::OpCode(OP_1));
+        assert_eq!(Ok(()), script(&cmds).evaluate(&z));
+        cmds.push(ScriptCmd::OpCode(OP_NOP));
+        assert_eq!(Err(ScriptError::OpCount), script(&cmds).evaluate(&z));
+
+        // CHECKMULTISIG counts its keys too
+        let multisig = |nops: usize| {
+            let mut cmds = ops(nops, OP_NOP);
+            cmds.extend([ScriptCmd::OpCode(OP_0), ScriptCmd::OpCode(OP_0)]);
+            cmds.extend((0..20).map(|_| ScriptCmd::Cmd(vec![2; 33])));
+            cmds.extend([ScriptCmd::Cmd(vec![20]), ScriptCmd::OpCode(OP_CHECKMULTISIG)]);
+            script(&cmds)
+        };
+        assert_eq!(Ok(()), multisig(180).evaluate(&z));
+        assert_eq!(Err(ScriptError::OpCount), multisig(181).evaluate(&z));
+
+        // the alt stack counts towards the stack size
+        assert_eq!(Ok(()), script(&ops(1000, OP_1)).evaluate(&z));
+        assert_eq!(Err(ScriptError::StackSize), script(&ops(1001, OP_1)).evaluate(&z));
+        let mut cmds = ops(1001, OP_1);
+        cmds.insert(1, ScriptCmd::OpCode(OP_TOALTSTACK));
+        assert_eq!(Err(ScriptError::StackSize), script(&cmds).evaluate(&z));
+    }
+
+    #[test]
+    fn test_flags() {
+        let (z, sec, sig) = signed_hash();
+        let p2pk = |sig: &[u8], sec: &[u8]| script(&[ScriptCmd::Cmd(sig.to_owned()), ScriptCmd::Cmd(sec.to_owned()), ScriptCmd::OpCode(OP_CHECKSIG)]);
+
+        // the signature is DER but its s is high
+        assert_eq!(Ok(()), p2pk(&sig, &sec).evaluate_with(&z, VerifyFlags::DERSIG | VerifyFlags::STRICTENC));
+        assert_eq!(Err(ScriptError::SigHighS), p2pk(&sig, &sec).evaluate_with(&z, VerifyFlags::LOW_S));
+
+        // a padded r still verifies, but isn't strict DER
+        let mut padded = hex::decode("3046022100").unwrap();
+        padded.extend_from_slice(&sig[4..]);
+        assert_eq!(Ok(()), p2pk(&padded, &sec).evaluate(&z));
+        assert_eq!(Err(ScriptError::SigDer), p2pk(&padded, &sec).evaluate_with(&z, VerifyFlags::DERSIG));
+        // so is a long form length, with trailing bytes
+        let mut long_form = vec![0x30, 0x81, sig[1]];
+        long_form.extend_from_slice(&sig[2..(sig.len() - 1)]);
+        long_form.extend_from_slice(&[0, 0, sig[sig.len() - 1]]);
+        assert_eq!(Ok(()), p2pk(&long_form, &sec).evaluate(&z));
+        assert_eq!(Err(ScriptError::SigDer), p2pk(&long_form, &sec).evaluate_with(&z, VerifyFlags::DERSIG));
+
+        let mut undefined = sig.clone();
+        *undefined.last_mut().unwrap() = 0x04;
+        assert_eq!(Err(ScriptError::SigHashType), p2pk(&undefined, &sec).evaluate_with(&z, VerifyFlags::STRICTENC));
+
+        let mut hybrid = sec.clone();
+        hybrid[0] = 0x06;
+        // hybrid keys read as uncompressed unless STRICTENC, if the prefix has the parity of y
+        assert_eq!(Ok(()), p2pk(&sig, &hybrid).evaluate(&z));
+        assert_eq!(Err(ScriptError::PubKeyType), p2pk(&sig, &hybrid).evaluate_with(&z, VerifyFlags::STRICTENC));
+        hybrid[0] = 0x07;
+        assert_eq!(Err(ScriptError::EvalFalse), p2pk(&sig, &hybrid).evaluate(&z));
+
+        // a failing check must use an empty signature
+        let wrong_z = z.clone() + 1;
+        assert_eq!(Err(ScriptError::EvalFalse), p2pk(&sig, &sec).evaluate(&wrong_z));
+        assert_eq!(Err(ScriptError::NullFail), p2pk(&sig, &sec).evaluate_with(&wrong_z, VerifyFlags::NULLFAIL));
+        assert_eq!(Err(ScriptError::EvalFalse), p2pk(&[], &sec).evaluate_with(&wrong_z, VerifyFlags::NULLFAIL));
+
+        let s = script(&[ScriptCmd::OpCode(OP_1), ScriptCmd::OpCode(OP_1)]);
+        assert_eq!(Ok(()), s.evaluate(&z));
+        assert_eq!(Err(ScriptError::CleanStack), s.evaluate_with(&z, VerifyFlags::CLEANSTACK));
+
+        let s = script(&[ScriptCmd::OpCode(OP_NOP1), ScriptCmd::OpCode(OP_1)]);
+        assert_eq!(Ok(()), s.evaluate(&z));
+        assert_eq!(Err(ScriptError::DiscourageUpgradableNops), s.evaluate_with(&z, VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS));
+
+        // number operands must be minimal too
+        let s = script(&[ScriptCmd::Cmd(vec![1, 0]), ScriptCmd::OpCode(OP_1ADD)]);
+        assert_eq!(Ok(()), s.evaluate(&z));
+        assert_eq!(Err(ScriptError::NonMinimalNum), s.evaluate_with(&z, VerifyFlags::MINIMALDATA));
+    }
+
+    #[test]
+    fn test_p2sh() {
+        let (z, sec, sig) = signed_hash();
+        let flags = VerifyFlags::P2SH | VerifyFlags::WITNESS;
+
+        let redeem_script = Script::p2pk(&sec).raw_serialize();
+        let script_pubkey = Script::p2sh(&hash160(&redeem_script));
+        let script_sig = script(&[ScriptCmd::Cmd(sig.clone()), ScriptCmd::Cmd(redeem_script.clone())]);
+        assert_eq!(Ok(()), verify_script(&script_sig, &script_pubkey, &[], &z, flags));
+        assert_eq!(Ok(()), verify_script(&script_sig, &script_pubkey, &[], &z, flags | VerifyFlags::CLEANSTACK));
+
+        // the hash matches, but the redeem script fails, which only matters under P2SH
+        let wrong_z = z.clone() + 1;
+        assert_eq!(Ok(()), verify_script(&script_sig, &script_pubkey, &[], &wrong_z, VerifyFlags::NONE));
+        assert_eq!(Err(ScriptError::EvalFalse), verify_script(&script_sig, &script_pubkey, &[], &wrong_z, flags));
+
+        // the scriptSig may only push
+        let script_sig = script(&[
+            ScriptCmd::Cmd(sig.clone()), ScriptCmd::OpCode(OP_NOP), ScriptCmd::Cmd(redeem_script.clone()),
+        ]);
+        assert_eq!(Ok(()), verify_script(&script_sig, &script_pubkey, &[], &z, VerifyFlags::NONE));
+        assert_eq!(Err(ScriptError::SigPushOnly), verify_script(&script_sig, &script_pubkey, &[], &z, flags));
+
+        // leftovers under the redeem script
+        let script_sig = script(&[ScriptCmd::OpCode(OP_1), ScriptCmd::Cmd(sig), ScriptCmd::Cmd(redeem_script)]);
+        assert_eq!(Ok(()), verify_script(&script_sig, &script_pubkey, &[], &z, flags));
+        assert_eq!(Err(ScriptError::CleanStack), verify_script(&script_sig, &script_pubkey, &[], &z, flags | VerifyFlags::CLEANSTACK));
+
+        // a truncated push in the redeem script
+        let script_pubkey = Script::p2sh(&hash160(&[0x4c]));
+        let script_sig = script(&[ScriptCmd::Cmd(vec![0x4c])]);
+        assert_eq!(Err(ScriptError::BadRedeemScript), verify_script(&script_sig, &script_pubkey, &[], &z, flags));
+
+        // P2SH wrapped witness programs, the scriptSig must be just the redeem script
+        let redeem_script = Script::segwit(2, &[1; 20]).raw_serialize();
+        let script_pubkey = Script::p2sh(&hash160(&redeem_script));
+        let script_sig = script(&[ScriptCmd::Cmd(redeem_script.clone())]);
+        let witness = vec![vec![1]];
+        assert_eq!(Ok(()), verify_script(&script_sig, &script_pubkey, &witness, &z, flags));
+        assert_eq!(
+            Err(ScriptError::DiscourageUpgradableWitnessProgram),
+            verify_script(&script_sig, &script_pubkey, &witness, &z, flags | VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM),
+        );
+        let script_sig = script(&[ScriptCmd::OpCode(OP_1), ScriptCmd::OpCode(OP_DROP), ScriptCmd::Cmd(redeem_script)]);
+        assert_eq!(Err(ScriptError::SigPushOnly), verify_script(&script_sig, &script_pubkey, &witness, &z, flags));
+        let script_sig = script(&[ScriptCmd::OpCode(OP_0), ScriptCmd::Cmd(Script::segwit(2, &[1; 20]).raw_serialize())]);
+        assert_eq!(Err(ScriptError::WitnessMalleatedP2sh), verify_script(&script_sig, &script_pubkey, &witness, &z, flags));
+
+        // witnesses only go with witness programs
+        let script_pubkey = Script::segwit(2, &[1; 20]);
+        assert_eq!(Ok(()), verify_script(&script(&[]), &script_pubkey, &witness, &z, flags));
+        assert_eq!(Err(ScriptError::WitnessMalleated), verify_script(&script(&[ScriptCmd::OpCode(OP_1)]), &script_pubkey, &witness, &z, flags));
+        let script_pubkey = script(&[ScriptCmd::OpCode(OP_1)]);
+        assert_eq!(Err(ScriptError::WitnessUnexpected), verify_script(&script(&[]), &script_pubkey, &witness, &z, flags));
+    }
+
+    #[test]
+    fn test_witness_v0() {
+        let (z, sec, sig) = signed_hash();
+        let flags = VerifyFlags::P2SH | VerifyFlags::WITNESS;
+        let empty = script(&[]);
+
+        // P2WPKH runs the implied P2PKH script on the witness
+        let script_pubkey = Script::p2wpkh(&hash160(&sec));
+        let witness = vec![sig.clone(), sec.clone()];
+        assert_eq!(Ok(()), verify_script(&empty, &script_pubkey, &witness, &z, flags));
+        let wrong_z = z.clone() + 1;
+        assert_eq!(Err(ScriptError::EvalFalse), verify_script(&empty, &script_pubkey, &witness, &wrong_z, flags));
+        assert_eq!(
+            Err(ScriptError::WitnessPubKeyType),
+            verify_script(&empty, &script_pubkey, &witness, &z, flags | VerifyFlags::WITNESS_PUBKEYTYPE),
+        );
+        assert_eq!(Err(ScriptError::WitnessProgramMismatch), verify_script(&empty, &script_pubkey, &witness[1..], &z, flags));
+
+        // the same through P2SH
+        let redeem_script = script_pubkey.raw_serialize();
+        let script_sig = script(&[ScriptCmd::Cmd(redeem_script.clone())]);
+        let p2sh = Script::p2sh(&hash160(&redeem_script));
+        assert_eq!(Ok(()), verify_script(&script_sig, &p2sh, &witness, &z, flags));
+        // without WITNESS the witness isn't looked at
+        assert_eq!(Ok(()), verify_script(&script_sig, &p2sh, &[], &z, VerifyFlags::P2SH));
+        assert_eq!(Err(ScriptError::WitnessProgramMismatch), verify_script(&script_sig, &p2sh, &[], &z, flags));
+
+        // P2WSH checks the witness script against the program
+        let witness_script = Script::p2pk(&sec).raw_serialize();
+        let script_pubkey = Script::p2wsh(&sha256(&witness_script));
+        let witness = vec![sig.clone(), witness_script.clone()];
+        assert_eq!(Ok(()), verify_script(&empty, &script_pubkey, &witness, &z, flags));
+        assert_eq!(Err(ScriptError::WitnessProgramWitnessEmpty), verify_script(&empty, &script_pubkey, &[], &z, flags));
+        let mut other_script = witness_script.clone();
+        other_script.push(OP_NOP);
+        assert_eq!(Err(ScriptError::WitnessProgramMismatch), verify_script(&empty, &script_pubkey, &[sig.clone(), other_script], &z, flags));
+
+        // witness scripts must leave exactly one element, whatever the flags
+        let witness = vec![vec![1], sig.clone(), witness_script.clone()];
+        assert_eq!(Err(ScriptError::CleanStack), verify_script(&empty, &script_pubkey, &witness, &z, flags));
+
+        let witness = vec![vec![1; 521], witness_script];
+        assert_eq!(Err(ScriptError::PushSize), verify_script(&empty, &script_pubkey, &witness, &z, flags));
+
+        let script_pubkey = Script::segwit(0, &[1; 21]);
+        assert_eq!(Err(ScriptError::WitnessProgramWrongLength), verify_script(&empty, &script_pubkey, &[sig], &z, flags));
+    }
+
+    /// remembers the script code of every signature check
+    struct ScriptCodeChecker {
+        z: BigInt,
+        script_codes: RefCell<Vec<String>>,
+    }
+
+    impl SignatureChecker for ScriptCodeChecker {
+        fn sig_hash(&self, script_code: &Script, _sighash_type: u8, _sig_version: SigVersion) -> BigInt {
+            self.script_codes.borrow_mut().push(script_code.raw_serialize().encode_hex());
+            self.z.clone()
+        }
+
+        fn tap_sig_hash(&self, _sighash_type: u8, _exec_data: &ExecData) -> Option<BigInt> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_script_code() {
+        let (z, sec, sig) = signed_hash();
+        let checker = ScriptCodeChecker { z, script_codes: RefCell::new(vec![]) };
+        let script_sig = script(&[ScriptCmd::Cmd(sig.clone())]);
+        let checksig = script(&[ScriptCmd::Cmd(sec.clone()), ScriptCmd::OpCode(OP_CHECKSIG)]);
+        let verify = |script_sig: &Script, script_pubkey: &Script, witness: &[Vec<u8>], flags: VerifyFlags| {
+            checker.script_codes.borrow_mut().clear();
+            let result = verify_script(script_sig, script_pubkey, witness, &checker, flags);
+            (result, checker.script_codes.borrow().clone())
+        };
+        let p2pk = checksig.raw_serialize().encode_hex::<String>();
+
+        // signatures commit to the script after the last executed OP_CODESEPARATOR
+        let script_pubkey = script(&[ScriptCmd::OpCode(OP_NOP), ScriptCmd::OpCode(OP_CODESEPARATOR)]) + checksig.clone();
+        assert_eq!((Ok(()), vec![p2pk.clone()]), verify(&script_sig, &script_pubkey, &[], VerifyFlags::NONE));
+        let skipped = script(&[ScriptCmd::OpCode(OP_0), ScriptCmd::OpCode(OP_IF), ScriptCmd::OpCode(OP_CODESEPARATOR), ScriptCmd::OpCode(OP_ENDIF)]);
+        let script_pubkey = skipped + checksig.clone();
+        let whole = script_pubkey.raw_serialize().encode_hex::<String>();
+        assert_eq!((Ok(()), vec![whole]), verify(&script_sig, &script_pubkey, &[], VerifyFlags::NONE));
+        // even in an unexecuted branch under CONST_SCRIPTCODE
+        assert_eq!(Err(ScriptError::OpCodeSeparator), verify(&script_sig, &script_pubkey, &[], VerifyFlags::CONST_SCRIPTCODE).0);
+
+        // the signature is removed from the script, but not a push of it with another opcode
+        let script_pubkey = script(&[ScriptCmd::Cmd(sig.clone()), ScriptCmd::OpCode(OP_DROP)]) + checksig.clone();
+        let dropped = format!("75{}", p2pk);
+        assert_eq!((Ok(()), vec![dropped]), verify(&script_sig, &script_pubkey, &[], VerifyFlags::NONE));
+        assert_eq!(Err(ScriptError::SigFindAndDelete), verify(&script_sig, &script_pubkey, &[], VerifyFlags::CONST_SCRIPTCODE).0);
+        let script_pubkey = script(&[ScriptCmd::PushData(OP_PUSHDATA1, sig.clone()), ScriptCmd::OpCode(OP_DROP)]) + checksig.clone();
+        let kept = script_pubkey.raw_serialize().encode_hex::<String>();
+        assert_eq!((Ok(()), vec![kept]), verify(&script_sig, &script_pubkey, &[], VerifyFlags::CONST_SCRIPTCODE));
+
+        // segwit scripts keep signatures and may use OP_CODESEPARATOR
+        let flags = VerifyFlags::P2SH | VerifyFlags::WITNESS | VerifyFlags::CONST_SCRIPTCODE;
+        let witness_script = script(&[ScriptCmd::Cmd(sig.clone()), ScriptCmd::OpCode(OP_DROP), ScriptCmd::OpCode(OP_CODESEPARATOR)]) + checksig.clone();
+        let witness_script = witness_script.raw_serialize();
+        let script_pubkey = Script::p2wsh(&sha256(&witness_script));
+        assert_eq!((Ok(()), vec![p2pk.clone()]), verify(&script(&[]), &script_pubkey, &[sig.clone(), witness_script], flags));
+        let witness_script = (script(&[ScriptCmd::Cmd(sig.clone()), ScriptCmd::OpCode(OP_DROP)]) + checksig).raw_serialize();
+        let script_pubkey = Script::p2wsh(&sha256(&witness_script));
+        let whole = witness_script.encode_hex::<String>();
+        assert_eq!((Ok(()), vec![whole]), verify(&script(&[]), &script_pubkey, &[sig, witness_script], flags));
+    }
+
+    #[test]
+    fn test_tapscript() {
+        let z = BigInt::parse_bytes(b"7c076ff316692a3d7eb3c3bb0f8b1488cf72e1afcd929e29307032997a838a3d", 16).unwrap();
+        let msg = int_to_big_endian(&z, 32);
+        let flags = VerifyFlags::P2SH | VerifyFlags::WITNESS | VerifyFlags::TAPROOT;
+        let empty = script(&[]);
+        let key_1 = PrivateKey::new(BigInt::from(1001));
+        let key_2 = PrivateKey::new(BigInt::from(1002));
+        let sig_1 = key_1.sign_schnorr(&msg, &[0u8; 32]).serialize();
+        let sig_2 = key_2.sign_schnorr(&msg, &[0u8; 32]).serialize();
+
+        // key path, a signature for the output key
+        let script_pubkey = Script::p2tr(&key_1.get_pub_key().xonly());
+        assert_eq!(Ok(()), verify_script(&empty, &script_pubkey, std::slice::from_ref(&sig_1), &z, flags));
+        assert_eq!(Err(ScriptError::SchnorrSig), verify_script(&empty, &script_pubkey, std::slice::from_ref(&sig_2), &z, flags));
+        // a fixed hash can only stand for SIGHASH_DEFAULT
+        let mut with_type = sig_1.clone();
+        with_type.push(SIGHASH_ALL);
+        assert_eq!(Err(ScriptError::SchnorrSigHashType), verify_script(&empty, &script_pubkey, &[with_type.clone()], &z, flags));
+        *with_type.last_mut().unwrap() = SIGHASH_DEFAULT;
+        assert_eq!(Err(ScriptError::SchnorrSigHashType), verify_script(&empty, &script_pubkey, &[with_type], &z, flags));
+        assert_eq!(Err(ScriptError::SchnorrSigSize), verify_script(&empty, &script_pubkey, &[sig_1[1..].to_vec()], &z, flags));
+        // the annex is left out of the stack
+        assert_eq!(Ok(()), verify_script(&empty, &script_pubkey, &[sig_1.clone(), vec![0x50, 1]], &z, flags));
+        // without TAPROOT, or wrapped in P2SH, version 1 is still anyone can spend
+        assert_eq!(Ok(()), verify_script(&empty, &script_pubkey, std::slice::from_ref(&sig_2), &z, VerifyFlags::P2SH | VerifyFlags::WITNESS));
+        let discourage = VerifyFlags::P2SH | VerifyFlags::WITNESS | VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM;
+        assert_eq!(Ok(()), verify_script(&empty, &script_pubkey, std::slice::from_ref(&sig_2), &z, discourage));
+
+        // script path, a 2 of 2 with CHECKSIGADD next to another leaf
+        let internal_key = PrivateKey::new(BigInt::from(1000)).get_pub_key();
+        let tapscript = script(&[
+            ScriptCmd::Cmd(key_1.get_pub_key().xonly()), ScriptCmd::OpCode(OP_CHECKSIG),
+            ScriptCmd::Cmd(key_2.get_pub_key().xonly()), ScriptCmd::OpCode(OP_CHECKSIGADD),
+            ScriptCmd::OpCode(OP_2), ScriptCmd::OpCode(OP_EQUAL),
+        ]).raw_serialize();
+        let tapleaf_hash = |script: &[u8]| {
+            let mut leaf = vec![TAPROOT_LEAF_TAPSCRIPT];
+            leaf.extend_from_slice(&encode_varint(&BigInt::from(script.len())));
+            leaf.extend_from_slice(script);
+            tagged_hash("TapLeaf", &leaf)
+        };
+        let spend = |tapscript: &[u8], stack: &[Vec<u8>], flags: VerifyFlags| {
+            let sibling = tapleaf_hash(&[OP_1]);
+            let leaf = tapleaf_hash(tapscript);
+            let mut branch = if leaf < sibling { leaf.clone() } else { sibling.clone() };
+            branch.extend_from_slice(if leaf < sibling { &sibling } else { &leaf });
+            let output_key = internal_key.tap_tweak(Some(&tagged_hash("TapBranch", &branch)));
+            let mut control = vec![TAPROOT_LEAF_TAPSCRIPT | !output_key.has_even_y() as u8];
+            control.extend_from_slice(&internal_key.xonly());
+            control.extend_from_slice(&sibling);
+
+            let mut witness = stack.to_owned();
+            witness.push(tapscript.to_owned());
+            witness.push(control);
+            verify_script(&empty, &Script::p2tr(&output_key.xonly()), &witness, &z, flags)
+        };
+        assert_eq!(Ok(()), spend(&tapscript, &[sig_2.clone(), sig_1.clone()], flags));
+        // empty signatures are false, invalid ones fail the script
+        assert_eq!(Err(ScriptError::EvalFalse), spend(&tapscript, &[vec![], sig_1.clone()], flags));
+        assert_eq!(Err(ScriptError::SchnorrSig), spend(&tapscript, &[sig_1.clone(), sig_1.clone()], flags));
+
+        // a fixed hash can't stand for signatures after an OP_CODESEPARATOR either
+        let codesep_script = script(&[ScriptCmd::OpCode(OP_CODESEPARATOR), ScriptCmd::Cmd(key_1.get_pub_key().xonly()), ScriptCmd::OpCode(OP_CHECKSIG)]);
+        assert_eq!(Err(ScriptError::SchnorrSigHashType), spend(&codesep_script.raw_serialize(), std::slice::from_ref(&sig_1), flags));
+
+        // the control block has to commit to the script
+        let mut other_script = tapscript.clone();
+        other_script.push(OP_NOP);
+        let mut witness = vec![sig_2.clone(), sig_1.clone(), other_script];
+        witness.push([vec![TAPROOT_LEAF_TAPSCRIPT], internal_key.xonly(), tapleaf_hash(&[OP_1])].concat());
+        let output_key = internal_key.tap_tweak(Some(&tapleaf_hash(&tapscript)));
+        assert_eq!(Err(ScriptError::WitnessProgramMismatch), verify_script(&empty, &Script::p2tr(&output_key.xonly()), &witness, &z, flags));
+        witness.last_mut().unwrap().push(0);
+        assert_eq!(Err(ScriptError::TaprootWrongControlSize), verify_script(&empty, &Script::p2tr(&output_key.xonly()), &witness, &z, flags));
+
+        // no CHECKMULTISIG, and IF needs a minimal argument whatever the flags
+        let multisig = script(&[ScriptCmd::OpCode(OP_1), ScriptCmd::Cmd(key_1.get_pub_key().xonly()), ScriptCmd::OpCode(OP_1), ScriptCmd::OpCode(OP_CHECKMULTISIG)]);
+        assert_eq!(Err(ScriptError::TapscriptCheckMultiSig), spend(&multisig.raw_serialize(), &[vec![], sig_1.clone()], flags));
+        let if_script = script(&[ScriptCmd::OpCode(OP_IF), ScriptCmd::OpCode(OP_1), ScriptCmd::OpCode(OP_ENDIF)]).raw_serialize();
+        assert_eq!(Ok(()), spend(&if_script, &[vec![1]], flags));
+        assert_eq!(Err(ScriptError::TapscriptMinimalIf), spend(&if_script, &[vec![2]], flags));
+
+        // OP_SUCCESSx succeeds even with an opcode that would fail before it
+        let success_script = vec![OP_RETURN, 0x50];
+        assert!(is_op_success(0x50));
+        assert_eq!(Ok(()), spend(&success_script, &[], flags));
+        assert_eq!(Err(ScriptError::DiscourageOpSuccess), spend(&success_script, &[], flags | VerifyFlags::DISCOURAGE_OP_SUCCESS));
+        // found before a push that runs past the end, but not after one
+        assert_eq!(Ok(()), spend(&[0x50, OP_PUSHDATA1], &[], flags));
+        assert_eq!(Err(ScriptError::BadOpcode(OP_PUSHDATA1)), spend(&[OP_PUSHDATA1, 0x50], &[], flags));
+
+        // every signature is paid for with 50 witness bytes, checking one 13 times isn't
+        let mut many_sigs = script(&[]);
+        for _ in 0..12 {
+            many_sigs = many_sigs + script(&[ScriptCmd::OpCode(OP_DUP), ScriptCmd::Cmd(key_1.get_pub_key().xonly()), ScriptCmd::OpCode(OP_CHECKSIGVERIFY)]);
+        }
+        let many_sigs = many_sigs + script(&[ScriptCmd::Cmd(key_1.get_pub_key().xonly()), ScriptCmd::OpCode(OP_CHECKSIG)]);
+        assert_eq!(Err(ScriptError::TapscriptValidationWeight), spend(&many_sigs.raw_serialize(), std::slice::from_ref(&sig_1), flags));
+        let padded_script = script(&[ScriptCmd::OpCode(OP_SWAP), ScriptCmd::OpCode(OP_DROP)]) + many_sigs;
+        assert_eq!(Ok(()), spend(&padded_script.raw_serialize(), &[vec![0; 100], sig_1.clone()], flags));
+    }
+
+    #[test]
+    fn test_witness_sigop_count() {
+        let flags = VerifyFlags::P2SH | VerifyFlags::WITNESS;
+        let empty = script(&[]);
+        let witness_script = Script::from_asm("OP_1 02aa 02bb OP_2 OP_CHECKMULTISIG OP_CHECKSIG").unwrap().raw_serialize();
+        let p2wsh = Script::p2wsh(&sha256(&witness_script));
+        let witness = vec![vec![], witness_script.clone()];
+        assert_eq!(3, witness_sigop_count(&empty, &p2wsh, &witness, flags));
+        assert_eq!(0, witness_sigop_count(&empty, &p2wsh, &witness, VerifyFlags::P2SH));
+        assert_eq!(0, witness_sigop_count(&empty, &p2wsh, &[], flags));
+        // a truncated witness script counts up to the push that runs past the end
+        let truncated = vec![OP_CHECKSIG, OP_PUSHDATA1];
+        let p2wsh_truncated = Script::p2wsh(&sha256(&truncated));
+        assert_eq!(1, witness_sigop_count(&empty, &p2wsh_truncated, &[truncated], flags));
+
+        let p2wpkh = Script::p2wpkh(&[0; 20]);
+        assert_eq!(1, witness_sigop_count(&empty, &p2wpkh, &[], flags));
+        assert_eq!(0, witness_sigop_count(&empty, &Script::p2tr(&[0; 32]), &witness, flags));
+
+        // wrapped in P2SH, the scriptSig pushes the witness program
+        let script_sig = script(&[ScriptCmd::Cmd(p2wsh.raw_serialize())]);
+        let p2sh = Script::p2sh(&hash160(&p2wsh.raw_serialize()));
+        assert_eq!(3, witness_sigop_count(&script_sig, &p2sh, &witness, flags));
+        let script_sig = script(&[ScriptCmd::Cmd(p2wsh.raw_serialize()), ScriptCmd::OpCode(OP_NOP)]);
+        assert_eq!(0, witness_sigop_count(&script_sig, &p2sh, &witness, flags));
+    }
+}
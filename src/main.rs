@@ -3,11 +3,13 @@
 
 use num_bigint::BigInt;
 use hex::ToHex;
+use network::Network;
 use private_key::PrivateKey;
 use signature::Signature;
 use utils::encode_base58;
 
 use crate::utils::{u8_slice_to_string, u8_slice_base58_to_string};
+use crate::{interpreter::VerifyFlags, script::Script, tracer::Tracer};
 
 mod field_element;
 mod elliptic_curve;
@@ -22,10 +24,93 @@ mod tx_fetcher;
 mod op;
 mod block;
 mod merkle_tree;
+mod network;
+mod extended_key;
+mod mnemonic;
+mod bech32;
+mod descriptor;
+mod miniscript;
+mod interpreter;
+mod tracer;
+
+
+/// a script given as raw hex, or as ASM if it isn't hex
+fn parse_script_arg(arg: &str) -> Option<Script> {
+    match hex::decode(arg) {
+        Ok(raw) => Script::parse_raw(&raw),
+        Err(_) => Script::from_asm(arg),
+    }
+}
+
+/// print every step of `tracer`, waiting for a line before each command while `stepping`,
+/// false if the user quit
+fn step_through(tracer: &mut Tracer, stepping: &mut bool) -> bool {
+    while let Some(step) = tracer.next() {
+        println!("{}", step);
+
+        if *stepping && tracer.next_cmd().is_some() {
+            let mut line = String::new();
+            // at the end of input just run the rest
+            if std::io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+                *stepping = false;
+            }
+            match line.trim() {
+                "c" => *stepping = false,
+                "q" => return false,
+                _ => {},
+            }
+        }
+    }
+    true
+}
+
+/// `trace <scriptSig> <scriptPubKey> [z]`, steps through the scriptSig and then the scriptPubKey
+/// on the stack it left, enter runs the next command, c runs to the end and q quits
+fn trace(args: &[String]) {
+    if args.len() < 2 {
+        eprintln!("usage: trace <scriptSig> <scriptPubKey> [z]");
+        std::process::exit(1);
+    }
+    let (script_sig, script_pubkey) = match (parse_script_arg(&args[0]), parse_script_arg(&args[1])) {
+        (Some(script_sig), Some(script_pubkey)) => (script_sig, script_pubkey),
+        _ => {
+            eprintln!("scripts must be hex or ASM");
+            std::process::exit(1);
+        },
+    };
+    let z = args.get(2).and_then(|z| BigInt::parse_bytes(z.as_bytes(), 16)).unwrap_or_default();
+    let mut stepping = true;
+
+    println!("scriptSig: {}", script_sig);
+    let mut tracer = Tracer::new(&script_sig, &z, VerifyFlags::STANDARD);
+    if !step_through(&mut tracer, &mut stepping) {
+        return;
+    }
+    // each script has to close its own IFs
+    if let Err(error) = tracer.executed() {
+        println!("failed: {}", error);
+        return;
+    }
+    let stack = tracer.interpreter().stack.clone();
+
+    println!("scriptPubKey: {}", script_pubkey);
+    let mut tracer = Tracer::with_stack(&script_pubkey, stack, &z, VerifyFlags::STANDARD);
+    if !step_through(&mut tracer, &mut stepping) {
+        return;
+    }
 
+    match tracer.result() {
+        Ok(()) => println!("success"),
+        Err(error) => println!("failed: {}", error),
+    }
+}
 
 fn main() {
     env_logger::init();
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|arg| arg.as_str()) == Some("trace") {
+        return trace(&args[2..]);
+    }
     //let x1 = Rc::new(S256Field::new(BigInt::from(15i32)));
     //let y1 = Rc::new(S256Field::new(BigInt::from(86i32)));
     //let x2 = Rc::new(S256Field::new(BigInt::from(17i32)));
@@ -54,7 +139,7 @@ fn main() {
 
     //println!("{:?}", signature);
 
-    let wif = private_key.wif(true, true);
+    let wif = private_key.wif(true, Network::Testnet);
     
     println!("{}", u8_slice_base58_to_string(&wif));
 }
@@ -68,7 +153,7 @@ mod tests {
 
     use hex::ToHex;
 
-    use crate::{field_element::FieldElement, elliptic_curve::Point, s256field::S256Field, s256point::{S256Point, self}, signature::Signature, private_key::PrivateKey, utils::{u8_slice_to_string, u8_slice_base58_to_string, encode_base58}};
+    use crate::{field_element::FieldElement, elliptic_curve::Point, s256field::S256Field, s256point::{S256Point, self}, signature::{Signature, SchnorrSignature}, private_key::PrivateKey, network::Network, utils::{u8_slice_to_string, u8_slice_base58_to_string, encode_base58}};
 
     #[test]
     fn test_on_curve() {
@@ -181,6 +266,26 @@ mod tests {
         assert!(signature.is_valid(&z, &pub_key));
     }
 
+    #[test]
+    fn test_schnorr() {
+        // BIP340 test vector 0
+        let private_key = PrivateKey::new(BigInt::from(3i32));
+        let pub_key = private_key.get_pub_key().xonly();
+        assert_eq!("f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9", pub_key.encode_hex::<String>());
+
+        let msg = [0u8; 32];
+        let signature = private_key.sign_schnorr(&msg, &[0u8; 32]);
+        assert_eq!(
+            "e907831f80848d1069a5371b402410364bdf1c5f8307b0084c55f1ce2dca821525f66a4a85ea8b71e482a74f382d2ce5ebeee8fdb2172f477df4900d310536c0",
+            signature.serialize().encode_hex::<String>(),
+        );
+        assert!(signature.is_valid(&msg, &pub_key));
+        assert!(!signature.is_valid(&[1u8; 32], &pub_key));
+
+        let parsed = SchnorrSignature::parse(&signature.serialize()).unwrap();
+        assert!(parsed.is_valid(&msg, &pub_key));
+    }
+
     #[test]
     fn test_sec_format() {
         let secret = BigInt::parse_bytes(b"deadbeef12345", 16).unwrap();
@@ -235,6 +340,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_der_parse_lax() {
+        let der = hex::decode("3045022037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c60221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec").unwrap();
+        let signature = Signature::parse_lax(&der).unwrap();
+        assert_eq!(der, signature.der());
+
+        // long form lengths, extra padding and trailing bytes read the same
+        let lax = hex::decode("3081ff028200240000000037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c60221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaecffff").unwrap();
+        assert!(Signature::parse(&lax).is_none());
+        assert_eq!(der, Signature::parse_lax(&lax).unwrap().der());
+
+        // r over 32 bytes, or running past the end
+        let long_r = hex::decode("3046022101ff37206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c6020101").unwrap();
+        assert!(Signature::parse_lax(&long_r).is_none());
+        assert!(Signature::parse_lax(&der[..40]).is_none());
+    }
+
     #[test]
     fn test_encode_base58() {
         let a = String::from("7c076ff316692a3d7eb3c3bb0f8b1488cf72e1afcd929e29307032997a838a3d");
@@ -267,7 +389,7 @@ mod tests {
         let secret = BigInt::parse_bytes(b"12345deadbeef", 16).unwrap();
         let private_key = PrivateKey::new(secret);
         let pub_key = private_key.get_pub_key();
-        let address = pub_key.address(true, false);
+        let address = pub_key.address(true, Network::Mainnet);
      
         assert_eq!(
             String::from("1F1Pn2y6pDb68E5nYJJeba4TLg2U7B6KF1"),
@@ -277,7 +399,7 @@ mod tests {
         let secret = BigInt::from(5002i32);
         let private_key = PrivateKey::new(secret);
         let pub_key = private_key.get_pub_key();
-        let address = pub_key.address(false, true);
+        let address = pub_key.address(false, Network::Testnet);
      
         assert_eq!(
             String::from("mmTPbXQFxboEtNRkwfh6K51jvdtHLxGeMA"),
@@ -287,7 +409,7 @@ mod tests {
         let secret = BigInt::from(2020i32).pow(5);
         let private_key = PrivateKey::new(secret);
         let pub_key = private_key.get_pub_key();
-        let address = pub_key.address(true, true);
+        let address = pub_key.address(true, Network::Testnet);
      
         assert_eq!(
             String::from("mopVkxp8UhXqRYbCYJsbeE1h1fiF64jcoH"),
@@ -299,7 +421,7 @@ mod tests {
     fn test_wif() {
         let secret = BigInt::from(5003i32);
         let private_key = PrivateKey::new(secret);
-        let wif = private_key.wif(true, true);
+        let wif = private_key.wif(true, Network::Testnet);
 
         assert_eq!(
             String::from("cMahea7zqjxrtgAbB7LSGbcQUr1uX1ojuat9jZodMN8rFTv2sfUK"),
@@ -308,7 +430,7 @@ mod tests {
 
         let secret = BigInt::from(2021i32).pow(5);
         let private_key = PrivateKey::new(secret);
-        let wif = private_key.wif(false, true);
+        let wif = private_key.wif(false, Network::Testnet);
 
         assert_eq!(
             String::from("91avARGdfge8E4tZfYLoxeJ5sGBdNJQH4kvjpWAxgzczjbCwxic"),
@@ -317,7 +439,7 @@ mod tests {
 
         let secret = BigInt::parse_bytes(b"54321deadbeef", 16).unwrap();
         let private_key = PrivateKey::new(secret);
-        let wif = private_key.wif(true, false);
+        let wif = private_key.wif(true, Network::Mainnet);
 
         assert_eq!(
             String::from("KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgiuQJv1h8Ytr2S53a"),
@@ -0,0 +1,1150 @@
+use std::{collections::HashMap, fmt::Display};
+
+use hex::ToHex;
+
+use crate::{
+    descriptor::split_call,
+    op::*,
+    script::{Script, ScriptCmd},
+    transaction::{LOCKTIME_THRESHOLD, SEQUENCE_FINAL, SEQUENCE_LOCKTIME_DISABLE_FLAG, SEQUENCE_LOCKTIME_MASK, SEQUENCE_LOCKTIME_TYPE_FLAG},
+    utils::hash160,
+};
+
+
+/// assumed size of a DER signature plus the sighash byte when estimating witnesses
+const MAX_SIG_SIZE: usize = 73;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MiniscriptError {
+    Syntax(String),
+    UnknownFragment(String),
+    InvalidKey(String),
+    InvalidHash(String),
+    InvalidTimelock(String),
+    InvalidThreshold(usize, usize),
+    /// a fragment got a subexpression of the wrong type
+    TypeCheck(String),
+    /// the policy can't be expressed, e.g. a threshold over too many keys
+    Compile(String),
+}
+
+impl Display for MiniscriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MiniscriptError::Syntax(s) => write!(f, "syntax error: {}", s),
+            MiniscriptError::UnknownFragment(s) => write!(f, "unknown fragment: {}", s),
+            MiniscriptError::InvalidKey(s) => write!(f, "invalid key: {}", s),
+            MiniscriptError::InvalidHash(s) => write!(f, "invalid hash: {}", s),
+            MiniscriptError::InvalidTimelock(s) => write!(f, "invalid timelock: {}", s),
+            MiniscriptError::InvalidThreshold(k, n) => write!(f, "invalid threshold {} of {}", k, n),
+            MiniscriptError::TypeCheck(s) => write!(f, "type check failed: {}", s),
+            MiniscriptError::Compile(s) => write!(f, "cannot compile: {}", s),
+        }
+    }
+}
+
+
+// -- Type --
+
+/// basic miniscript types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaseType {
+    /// pushes a nonzero value on satisfaction and an exact 0 on dissatisfaction
+    B,
+    /// continues on satisfaction, can't be dissatisfied
+    V,
+    /// pushes a public key for a following CHECKSIG
+    K,
+    /// like B, but takes its inputs from below the top of the stack
+    W,
+}
+
+/// a base type plus the z (zero-arg), o (one-arg), n (nonzero), d (dissatisfiable) and u (unit)
+/// properties
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Type {
+    pub base: BaseType,
+    pub z: bool,
+    pub o: bool,
+    pub n: bool,
+    pub d: bool,
+    pub u: bool,
+}
+
+impl Type {
+    fn new(base: BaseType, props: &str) -> Self {
+        Self {
+            base,
+            z: props.contains('z'),
+            o: props.contains('o'),
+            n: props.contains('n'),
+            d: props.contains('d'),
+            u: props.contains('u'),
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.base)?;
+        for (has, c) in [(self.z, 'z'), (self.o, 'o'), (self.n, 'n'), (self.d, 'd'), (self.u, 'u')] {
+            if has {
+                write!(f, "{}", c)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+// -- Miniscript --
+
+/// miniscript for P2WSH, keys are 33 bytes compressed SEC keys
+#[derive(Debug, Clone, PartialEq)]
+pub enum Miniscript {
+    False,
+    True,
+    PkK(Vec<u8>),
+    PkH(Vec<u8>),
+    Older(u32),
+    After(u32),
+    Sha256(Vec<u8>),
+    Hash256(Vec<u8>),
+    Ripemd160(Vec<u8>),
+    Hash160(Vec<u8>),
+    AndOr(Box<Miniscript>, Box<Miniscript>, Box<Miniscript>),
+    AndV(Box<Miniscript>, Box<Miniscript>),
+    AndB(Box<Miniscript>, Box<Miniscript>),
+    OrB(Box<Miniscript>, Box<Miniscript>),
+    OrC(Box<Miniscript>, Box<Miniscript>),
+    OrD(Box<Miniscript>, Box<Miniscript>),
+    OrI(Box<Miniscript>, Box<Miniscript>),
+    Thresh(usize, Vec<Miniscript>),
+    Multi(usize, Vec<Vec<u8>>),
+    /// a: wrapper
+    Alt(Box<Miniscript>),
+    /// s: wrapper
+    Swap(Box<Miniscript>),
+    /// c: wrapper
+    Check(Box<Miniscript>),
+    /// d: wrapper
+    DupIf(Box<Miniscript>),
+    /// v: wrapper
+    Verify(Box<Miniscript>),
+    /// j: wrapper
+    NonZero(Box<Miniscript>),
+    /// n: wrapper
+    ZeroNotEqual(Box<Miniscript>),
+}
+
+fn parse_key(s: &str) -> Result<Vec<u8>, MiniscriptError> {
+    match hex::decode(s) {
+        Ok(key) if key.len() == 33 && (key[0] == 2 || key[0] == 3) => Ok(key),
+        _ => Err(MiniscriptError::InvalidKey(s.to_string())),
+    }
+}
+
+fn parse_hash(s: &str, len: usize) -> Result<Vec<u8>, MiniscriptError> {
+    match hex::decode(s) {
+        Ok(hash) if hash.len() == len => Ok(hash),
+        _ => Err(MiniscriptError::InvalidHash(s.to_string())),
+    }
+}
+
+fn parse_timelock(s: &str) -> Result<u32, MiniscriptError> {
+    match s.parse::<u32>() {
+        Ok(n) if (1..0x80000000).contains(&n) => Ok(n),
+        _ => Err(MiniscriptError::InvalidTimelock(s.to_string())),
+    }
+}
+
+fn parse_threshold(s: &str, n: usize) -> Result<usize, MiniscriptError> {
+    let k = s.parse::<usize>().map_err(|_| MiniscriptError::Syntax(s.to_string()))?;
+    if k == 0 || k > n {
+        return Err(MiniscriptError::InvalidThreshold(k, n));
+    }
+
+    Ok(k)
+}
+
+/// minimal push of a script number
+fn push_int(n: i64) -> ScriptCmd {
+    if n == 0 {
+        return ScriptCmd::OpCode(OP_0);
+    }
+    if (1..=16).contains(&n) {
+        return ScriptCmd::OpCode(OP_1 + (n as u8) - 1);
+    }
+
+    ScriptCmd::Cmd(encode_num(n))
+}
+
+/// serialized size of a witness stack, each element has a one byte length prefix
+fn witness_size(witness: &[Vec<u8>]) -> usize {
+    witness.iter().map(|e| 1 + e.len()).sum()
+}
+
+/// the smaller of two optional witnesses
+fn smallest(a: Option<Vec<Vec<u8>>>, b: Option<Vec<Vec<u8>>>) -> Option<Vec<Vec<u8>>> {
+    match (a, b) {
+        (Some(a), Some(b)) => if witness_size(&b) < witness_size(&a) { Some(b) } else { Some(a) },
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// witness elements `top` executed first sit above the elements of `bottom`
+fn concat(bottom: Option<Vec<Vec<u8>>>, top: Option<Vec<Vec<u8>>>) -> Option<Vec<Vec<u8>>> {
+    let mut result = bottom?;
+    result.extend(top?);
+    Some(result)
+}
+
+fn add(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    Some(a? + b?)
+}
+
+fn max(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// (satisfaction, dissatisfaction) witness stacks, bottom first
+type Satisfactions = (Option<Vec<Vec<u8>>>, Option<Vec<Vec<u8>>>);
+
+impl Miniscript {
+    /// parse miniscript, also accepting the pk(), pkh(), and_n() and t:, l:, u: shorthands
+    pub fn parse(s: &str) -> Result<Self, MiniscriptError> {
+        let s = s.trim();
+
+        // wrappers like `vc:` apply right to left onto the fragment after the colon
+        let colon = s.find(':');
+        let paren = s.find('(').unwrap_or(s.len());
+        if let Some(colon) = colon.filter(|c| *c < paren) {
+            let mut ms = Self::parse(&s[(colon + 1)..])?;
+            for wrapper in s[..colon].chars().rev() {
+                ms = match wrapper {
+                    'a' => Miniscript::Alt(Box::new(ms)),
+                    's' => Miniscript::Swap(Box::new(ms)),
+                    'c' => Miniscript::Check(Box::new(ms)),
+                    'd' => Miniscript::DupIf(Box::new(ms)),
+                    'v' => Miniscript::Verify(Box::new(ms)),
+                    'j' => Miniscript::NonZero(Box::new(ms)),
+                    'n' => Miniscript::ZeroNotEqual(Box::new(ms)),
+                    't' => Miniscript::AndV(Box::new(ms), Box::new(Miniscript::True)),
+                    'l' => Miniscript::OrI(Box::new(Miniscript::False), Box::new(ms)),
+                    'u' => Miniscript::OrI(Box::new(ms), Box::new(Miniscript::False)),
+                    _ => return Err(MiniscriptError::UnknownFragment(format!("{}:", wrapper))),
+                };
+            }
+
+            return Ok(ms);
+        }
+
+        match s {
+            "0" => return Ok(Miniscript::False),
+            "1" => return Ok(Miniscript::True),
+            _ => {},
+        }
+
+        let (name, args) = split_call(s).map_err(|_| MiniscriptError::Syntax(s.to_string()))?;
+        let expect_args = |n: usize| {
+            if args.len() != n {
+                return Err(MiniscriptError::Syntax(s.to_string()));
+            }
+            Ok(())
+        };
+        let sub = |i: usize| -> Result<Box<Miniscript>, MiniscriptError> { Ok(Box::new(Self::parse(args[i])?)) };
+
+        let ms = match name {
+            "pk_k" => { expect_args(1)?; Miniscript::PkK(parse_key(args[0])?) },
+            "pk_h" => { expect_args(1)?; Miniscript::PkH(parse_key(args[0])?) },
+            "pk" => { expect_args(1)?; Miniscript::Check(Box::new(Miniscript::PkK(parse_key(args[0])?))) },
+            "pkh" => { expect_args(1)?; Miniscript::Check(Box::new(Miniscript::PkH(parse_key(args[0])?))) },
+            "older" => { expect_args(1)?; Miniscript::Older(parse_timelock(args[0])?) },
+            "after" => { expect_args(1)?; Miniscript::After(parse_timelock(args[0])?) },
+            "sha256" => { expect_args(1)?; Miniscript::Sha256(parse_hash(args[0], 32)?) },
+            "hash256" => { expect_args(1)?; Miniscript::Hash256(parse_hash(args[0], 32)?) },
+            "ripemd160" => { expect_args(1)?; Miniscript::Ripemd160(parse_hash(args[0], 20)?) },
+            "hash160" => { expect_args(1)?; Miniscript::Hash160(parse_hash(args[0], 20)?) },
+            "andor" => { expect_args(3)?; Miniscript::AndOr(sub(0)?, sub(1)?, sub(2)?) },
+            "and_n" => { expect_args(2)?; Miniscript::AndOr(sub(0)?, sub(1)?, Box::new(Miniscript::False)) },
+            "and_v" => { expect_args(2)?; Miniscript::AndV(sub(0)?, sub(1)?) },
+            "and_b" => { expect_args(2)?; Miniscript::AndB(sub(0)?, sub(1)?) },
+            "or_b" => { expect_args(2)?; Miniscript::OrB(sub(0)?, sub(1)?) },
+            "or_c" => { expect_args(2)?; Miniscript::OrC(sub(0)?, sub(1)?) },
+            "or_d" => { expect_args(2)?; Miniscript::OrD(sub(0)?, sub(1)?) },
+            "or_i" => { expect_args(2)?; Miniscript::OrI(sub(0)?, sub(1)?) },
+            "thresh" => {
+                if args.len() < 2 {
+                    return Err(MiniscriptError::Syntax(s.to_string()));
+                }
+                let k = parse_threshold(args[0], args.len() - 1)?;
+                let subs = args[1..].iter().map(|a| Self::parse(a)).collect::<Result<Vec<_>, _>>()?;
+                Miniscript::Thresh(k, subs)
+            },
+            "multi" => {
+                if args.len() < 2 || args.len() > 21 {
+                    return Err(MiniscriptError::Syntax(s.to_string()));
+                }
+                let k = parse_threshold(args[0], args.len() - 1)?;
+                let keys = args[1..].iter().map(|a| parse_key(a)).collect::<Result<Vec<_>, _>>()?;
+                Miniscript::Multi(k, keys)
+            },
+            _ => return Err(MiniscriptError::UnknownFragment(name.to_string())),
+        };
+
+        Ok(ms)
+    }
+
+    /// type and properties, fails if a fragment's arguments have the wrong types
+    pub fn ty(&self) -> Result<Type, MiniscriptError> {
+        use BaseType::*;
+
+        let fail = || Err(MiniscriptError::TypeCheck(self.to_string()));
+
+        let ty = match self {
+            Miniscript::False => Type::new(B, "zud"),
+            Miniscript::True => Type::new(B, "zu"),
+            Miniscript::PkK(_) => Type::new(K, "ondu"),
+            Miniscript::PkH(_) => Type::new(K, "ndu"),
+            Miniscript::Older(_) | Miniscript::After(_) => Type::new(B, "z"),
+            Miniscript::Sha256(_) | Miniscript::Hash256(_) | Miniscript::Ripemd160(_) | Miniscript::Hash160(_) => Type::new(B, "ondu"),
+            Miniscript::AndOr(x, y, z) => {
+                let (x, y, z) = (x.ty()?, y.ty()?, z.ty()?);
+                if x.base != B || !x.d || !x.u || y.base != z.base || y.base == W {
+                    return fail();
+                }
+                Type {
+                    base: y.base,
+                    z: x.z && y.z && z.z,
+                    o: (x.z && y.o && z.o) || (x.o && y.z && z.z),
+                    n: false,
+                    d: z.d,
+                    u: y.u && z.u,
+                }
+            },
+            Miniscript::AndV(x, y) => {
+                let (x, y) = (x.ty()?, y.ty()?);
+                if x.base != V || y.base == W {
+                    return fail();
+                }
+                Type {
+                    base: y.base,
+                    z: x.z && y.z,
+                    o: (x.z && y.o) || (x.o && y.z),
+                    n: x.n || (x.z && y.n),
+                    d: false,
+                    u: y.u,
+                }
+            },
+            Miniscript::AndB(x, y) => {
+                let (x, y) = (x.ty()?, y.ty()?);
+                if x.base != B || y.base != W {
+                    return fail();
+                }
+                Type {
+                    base: B,
+                    z: x.z && y.z,
+                    o: (x.z && y.o) || (x.o && y.z),
+                    n: x.n || (x.z && y.n),
+                    d: x.d && y.d,
+                    u: true,
+                }
+            },
+            Miniscript::OrB(x, z) => {
+                let (x, z) = (x.ty()?, z.ty()?);
+                if x.base != B || !x.d || z.base != W || !z.d {
+                    return fail();
+                }
+                Type {
+                    base: B,
+                    z: x.z && z.z,
+                    o: (x.z && z.o) || (x.o && z.z),
+                    n: false,
+                    d: true,
+                    u: true,
+                }
+            },
+            Miniscript::OrC(x, z) => {
+                let (x, z) = (x.ty()?, z.ty()?);
+                if x.base != B || !x.d || !x.u || z.base != V {
+                    return fail();
+                }
+                Type {
+                    base: V,
+                    z: x.z && z.z,
+                    o: x.o && z.z,
+                    n: false,
+                    d: false,
+                    u: false,
+                }
+            },
+            Miniscript::OrD(x, z) => {
+                let (x, z) = (x.ty()?, z.ty()?);
+                if x.base != B || !x.d || !x.u || z.base != B {
+                    return fail();
+                }
+                Type {
+                    base: B,
+                    z: x.z && z.z,
+                    o: x.o && z.z,
+                    n: false,
+                    d: z.d,
+                    u: z.u,
+                }
+            },
+            Miniscript::OrI(x, z) => {
+                let (x, z) = (x.ty()?, z.ty()?);
+                if x.base != z.base || x.base == W {
+                    return fail();
+                }
+                Type {
+                    base: x.base,
+                    z: false,
+                    o: x.z && z.z,
+                    n: false,
+                    d: x.d || z.d,
+                    u: x.u && z.u,
+                }
+            },
+            Miniscript::Thresh(_, subs) => {
+                let mut ones = 0;
+                let mut all_z = true;
+                for (i, sub) in subs.iter().enumerate() {
+                    let t = sub.ty()?;
+                    let base = if i == 0 { B } else { W };
+                    if t.base != base || !t.d || !t.u {
+                        return fail();
+                    }
+                    if !t.z {
+                        all_z = false;
+                        if t.o {
+                            ones += 1;
+                        } else {
+                            ones += 2;
+                        }
+                    }
+                }
+                Type {
+                    base: B,
+                    z: all_z,
+                    o: ones == 1,
+                    n: false,
+                    d: true,
+                    u: true,
+                }
+            },
+            Miniscript::Multi(_, _) => Type::new(B, "ndu"),
+            Miniscript::Alt(x) => {
+                let x = x.ty()?;
+                if x.base != B {
+                    return fail();
+                }
+                Type { base: W, z: false, o: false, n: false, d: x.d, u: x.u }
+            },
+            Miniscript::Swap(x) => {
+                let x = x.ty()?;
+                if x.base != B || !x.o {
+                    return fail();
+                }
+                Type { base: W, z: false, o: false, n: false, d: x.d, u: x.u }
+            },
+            Miniscript::Check(x) => {
+                let x = x.ty()?;
+                if x.base != K {
+                    return fail();
+                }
+                Type { base: B, z: false, o: x.o, n: x.n, d: x.d, u: true }
+            },
+            Miniscript::DupIf(x) => {
+                let x = x.ty()?;
+                if x.base != V || !x.z {
+                    return fail();
+                }
+                // OP_IF only requires a minimal argument by policy outside tapscript, so no u
+                Type { base: B, z: false, o: true, n: true, d: true, u: false }
+            },
+            Miniscript::Verify(x) => {
+                let x = x.ty()?;
+                if x.base != B {
+                    return fail();
+                }
+                Type { base: V, z: x.z, o: x.o, n: x.n, d: false, u: false }
+            },
+            Miniscript::NonZero(x) => {
+                let x = x.ty()?;
+                if x.base != B || !x.n {
+                    return fail();
+                }
+                Type { base: B, z: false, o: x.o, n: true, d: true, u: x.u }
+            },
+            Miniscript::ZeroNotEqual(x) => {
+                let x = x.ty()?;
+                if x.base != B {
+                    return fail();
+                }
+                Type { base: B, z: x.z, o: x.o, n: x.n, d: x.d, u: true }
+            },
+        };
+
+        Ok(ty)
+    }
+
+    /// a top level miniscript must be of type B
+    pub fn check(&self) -> Result<Type, MiniscriptError> {
+        let ty = self.ty()?;
+        if ty.base != BaseType::B {
+            return Err(MiniscriptError::TypeCheck(format!("top level {} is not B", self)));
+        }
+
+        Ok(ty)
+    }
+
+    /// the witness script
+    pub fn encode(&self) -> Script {
+        let mut cmds = vec![];
+        self.encode_into(&mut cmds);
+        Script::new(Some(&cmds))
+    }
+
+    fn encode_into(&self, cmds: &mut Vec<ScriptCmd>) {
+        use ScriptCmd::{OpCode, Cmd};
+
+        let hash_check = |cmds: &mut Vec<ScriptCmd>, op: u8, h: &[u8]| {
+            cmds.extend([OpCode(OP_SIZE), push_int(32), OpCode(OP_EQUALVERIFY), OpCode(op), Cmd(h.to_owned()), OpCode(OP_EQUAL)]);
+        };
+
+        match self {
+            Miniscript::False => cmds.push(OpCode(OP_0)),
+            Miniscript::True => cmds.push(OpCode(OP_1)),
+            Miniscript::PkK(key) => cmds.push(Cmd(key.clone())),
+            Miniscript::PkH(key) => {
+                cmds.extend([OpCode(OP_DUP), OpCode(OP_HASH160), Cmd(hash160(key)), OpCode(OP_EQUALVERIFY)]);
+            },
+            Miniscript::Older(n) => cmds.extend([push_int(*n as i64), OpCode(OP_CHECKSEQUENCEVERIFY)]),
+            Miniscript::After(n) => cmds.extend([push_int(*n as i64), OpCode(OP_CHECKLOCKTIMEVERIFY)]),
+            Miniscript::Sha256(h) => hash_check(cmds, OP_SHA256, h),
+            Miniscript::Hash256(h) => hash_check(cmds, OP_HASH256, h),
+            Miniscript::Ripemd160(h) => hash_check(cmds, OP_RIPEMD160, h),
+            Miniscript::Hash160(h) => hash_check(cmds, OP_HASH160, h),
+            Miniscript::AndOr(x, y, z) => {
+                x.encode_into(cmds);
+                cmds.push(OpCode(OP_NOTIF));
+                z.encode_into(cmds);
+                cmds.push(OpCode(OP_ELSE));
+                y.encode_into(cmds);
+                cmds.push(OpCode(OP_ENDIF));
+            },
+            Miniscript::AndV(x, y) => {
+                x.encode_into(cmds);
+                y.encode_into(cmds);
+            },
+            Miniscript::AndB(x, y) => {
+                x.encode_into(cmds);
+                y.encode_into(cmds);
+                cmds.push(OpCode(OP_BOOLAND));
+            },
+            Miniscript::OrB(x, z) => {
+                x.encode_into(cmds);
+                z.encode_into(cmds);
+                cmds.push(OpCode(OP_BOOLOR));
+            },
+            Miniscript::OrC(x, z) => {
+                x.encode_into(cmds);
+                cmds.push(OpCode(OP_NOTIF));
+                z.encode_into(cmds);
+                cmds.push(OpCode(OP_ENDIF));
+            },
+            Miniscript::OrD(x, z) => {
+                x.encode_into(cmds);
+                cmds.extend([OpCode(OP_IFDUP), OpCode(OP_NOTIF)]);
+                z.encode_into(cmds);
+                cmds.push(OpCode(OP_ENDIF));
+            },
+            Miniscript::OrI(x, z) => {
+                cmds.push(OpCode(OP_IF));
+                x.encode_into(cmds);
+                cmds.push(OpCode(OP_ELSE));
+                z.encode_into(cmds);
+                cmds.push(OpCode(OP_ENDIF));
+            },
+            Miniscript::Thresh(k, subs) => {
+                for (i, sub) in subs.iter().enumerate() {
+                    sub.encode_into(cmds);
+                    if i > 0 {
+                        cmds.push(OpCode(OP_ADD));
+                    }
+                }
+                cmds.extend([push_int(*k as i64), OpCode(OP_EQUAL)]);
+            },
+            Miniscript::Multi(k, keys) => {
+                cmds.push(push_int(*k as i64));
+                cmds.extend(keys.iter().map(|key| Cmd(key.clone())));
+                cmds.extend([push_int(keys.len() as i64), OpCode(OP_CHECKMULTISIG)]);
+            },
+            Miniscript::Alt(x) => {
+                cmds.push(OpCode(OP_TOALTSTACK));
+                x.encode_into(cmds);
+                cmds.push(OpCode(OP_FROMALTSTACK));
+            },
+            Miniscript::Swap(x) => {
+                cmds.push(OpCode(OP_SWAP));
+                x.encode_into(cmds);
+            },
+            Miniscript::Check(x) => {
+                x.encode_into(cmds);
+                cmds.push(OpCode(OP_CHECKSIG));
+            },
+            Miniscript::DupIf(x) => {
+                cmds.extend([OpCode(OP_DUP), OpCode(OP_IF)]);
+                x.encode_into(cmds);
+                cmds.push(OpCode(OP_ENDIF));
+            },
+            Miniscript::Verify(x) => {
+                x.encode_into(cmds);
+                // fold into the VERIFY variant of the last opcode when there is one
+                match cmds.last() {
+                    Some(OpCode(OP_EQUAL)) => { cmds.pop(); cmds.push(OpCode(OP_EQUALVERIFY)); },
+                    Some(OpCode(OP_NUMEQUAL)) => { cmds.pop(); cmds.push(OpCode(OP_NUMEQUALVERIFY)); },
+                    Some(OpCode(OP_CHECKSIG)) => { cmds.pop(); cmds.push(OpCode(OP_CHECKSIGVERIFY)); },
+                    Some(OpCode(OP_CHECKMULTISIG)) => { cmds.pop(); cmds.push(OpCode(OP_CHECKMULTISIGVERIFY)); },
+                    _ => cmds.push(OpCode(OP_VERIFY)),
+                }
+            },
+            Miniscript::NonZero(x) => {
+                cmds.extend([OpCode(OP_SIZE), OpCode(OP_0NOTEQUAL), OpCode(OP_IF)]);
+                x.encode_into(cmds);
+                cmds.push(OpCode(OP_ENDIF));
+            },
+            Miniscript::ZeroNotEqual(x) => {
+                x.encode_into(cmds);
+                cmds.push(OpCode(OP_0NOTEQUAL));
+            },
+        }
+    }
+
+    pub fn script_size(&self) -> usize {
+        self.encode().raw_serialize().len()
+    }
+
+    /// upper bound of the (satisfaction, dissatisfaction) witness sizes in bytes, None when impossible
+    fn witness_sizes(&self) -> (Option<usize>, Option<usize>) {
+        let sig = 1 + MAX_SIG_SIZE;
+        let key = 1 + 33;
+
+        match self {
+            Miniscript::False => (None, Some(0)),
+            Miniscript::True => (Some(0), None),
+            Miniscript::PkK(_) => (Some(sig), Some(1)),
+            Miniscript::PkH(_) => (Some(sig + key), Some(1 + key)),
+            Miniscript::Older(_) | Miniscript::After(_) => (Some(0), None),
+            Miniscript::Sha256(_) | Miniscript::Hash256(_) | Miniscript::Ripemd160(_) | Miniscript::Hash160(_) => (Some(33), Some(33)),
+            Miniscript::AndOr(x, y, z) => {
+                let ((sx, dx), (sy, _), (sz, dz)) = (x.witness_sizes(), y.witness_sizes(), z.witness_sizes());
+                (max(add(sx, sy), add(dx, sz)), add(dx, dz))
+            },
+            Miniscript::AndV(x, y) => {
+                let ((sx, _), (sy, _)) = (x.witness_sizes(), y.witness_sizes());
+                (add(sx, sy), None)
+            },
+            Miniscript::AndB(x, y) => {
+                let ((sx, dx), (sy, dy)) = (x.witness_sizes(), y.witness_sizes());
+                (add(sx, sy), add(dx, dy))
+            },
+            Miniscript::OrB(x, z) => {
+                let ((sx, dx), (sz, dz)) = (x.witness_sizes(), z.witness_sizes());
+                (max(add(sx, dz), add(dx, sz)), add(dx, dz))
+            },
+            Miniscript::OrC(x, z) => {
+                let ((sx, dx), (sz, _)) = (x.witness_sizes(), z.witness_sizes());
+                (max(sx, add(dx, sz)), None)
+            },
+            Miniscript::OrD(x, z) => {
+                let ((sx, dx), (sz, dz)) = (x.witness_sizes(), z.witness_sizes());
+                (max(sx, add(dx, sz)), add(dx, dz))
+            },
+            Miniscript::OrI(x, z) => {
+                let ((sx, dx), (sz, dz)) = (x.witness_sizes(), z.witness_sizes());
+                (max(add(sx, Some(2)), add(sz, Some(1))), max(add(dx, Some(2)), add(dz, Some(1))))
+            },
+            Miniscript::Thresh(k, subs) => {
+                let sizes: Vec<_> = subs.iter().map(|s| s.witness_sizes()).collect();
+                let dissat = sizes.iter().try_fold(0, |acc, (_, d)| Some(acc + (*d)?));
+                // the largest satisfaction replaces the k dissatisfactions with the largest growth
+                let mut growth: Vec<usize> = sizes.iter().filter_map(|(s, d)| Some((*s)?.saturating_sub((*d)?))).collect();
+                growth.sort_unstable_by(|a, b| b.cmp(a));
+                let sat = if growth.len() >= *k { dissat.map(|d| d + growth[..*k].iter().sum::<usize>()) } else { None };
+                (sat, dissat)
+            },
+            Miniscript::Multi(k, _) => (Some(1 + k * sig), Some(1 + k)),
+            Miniscript::Alt(x) | Miniscript::Swap(x) | Miniscript::Check(x) | Miniscript::ZeroNotEqual(x) => x.witness_sizes(),
+            Miniscript::DupIf(x) => (add(x.witness_sizes().0, Some(2)), Some(1)),
+            Miniscript::Verify(x) => (x.witness_sizes().0, None),
+            Miniscript::NonZero(x) => (x.witness_sizes().0, Some(1)),
+        }
+    }
+
+    /// upper bound of the witness size needed to satisfy this script, excluding the witness script
+    pub fn max_satisfaction_size(&self) -> Option<usize> {
+        self.witness_sizes().0
+    }
+
+    /// smallest witness stack (bottom first) satisfying this script with what `satisfier` has
+    pub fn satisfy(&self, satisfier: &Satisfier) -> Option<Vec<Vec<u8>>> {
+        self.satisfactions(satisfier).0
+    }
+
+    /// (satisfaction, dissatisfaction) witness stacks, bottom first
+    fn satisfactions(&self, satisfier: &Satisfier) -> Satisfactions {
+        let empty = || Some(vec![vec![]]);
+        let one = || Some(vec![vec![1u8]]);
+
+        match self {
+            Miniscript::False => (None, Some(vec![])),
+            Miniscript::True => (Some(vec![]), None),
+            Miniscript::PkK(key) => (satisfier.signatures.get(key).map(|sig| vec![sig.clone()]), empty()),
+            Miniscript::PkH(key) => (
+                satisfier.signatures.get(key).map(|sig| vec![sig.clone(), key.clone()]),
+                Some(vec![vec![], key.clone()]),
+            ),
+            Miniscript::Older(n) => (if satisfier.check_older(*n) { Some(vec![]) } else { None }, None),
+            Miniscript::After(n) => (if satisfier.check_after(*n) { Some(vec![]) } else { None }, None),
+            Miniscript::Sha256(h) | Miniscript::Hash256(h) | Miniscript::Ripemd160(h) | Miniscript::Hash160(h) => (
+                satisfier.preimages.get(h).map(|p| vec![p.clone()]),
+                Some(vec![vec![0u8; 32]]),
+            ),
+            Miniscript::AndOr(x, y, z) => {
+                let ((sx, dx), (sy, _), (sz, dz)) = (x.satisfactions(satisfier), y.satisfactions(satisfier), z.satisfactions(satisfier));
+                (smallest(concat(sy, sx), concat(sz, dx.clone())), concat(dz, dx))
+            },
+            Miniscript::AndV(x, y) => {
+                let ((sx, _), (sy, _)) = (x.satisfactions(satisfier), y.satisfactions(satisfier));
+                (concat(sy, sx), None)
+            },
+            Miniscript::AndB(x, y) => {
+                let ((sx, dx), (sy, dy)) = (x.satisfactions(satisfier), y.satisfactions(satisfier));
+                (concat(sy, sx), concat(dy, dx))
+            },
+            Miniscript::OrB(x, z) => {
+                let ((sx, dx), (sz, dz)) = (x.satisfactions(satisfier), z.satisfactions(satisfier));
+                (smallest(concat(dz.clone(), sx), concat(sz, dx.clone())), concat(dz, dx))
+            },
+            Miniscript::OrC(x, z) => {
+                let ((sx, dx), (sz, _)) = (x.satisfactions(satisfier), z.satisfactions(satisfier));
+                (smallest(sx, concat(sz, dx)), None)
+            },
+            Miniscript::OrD(x, z) => {
+                let ((sx, dx), (sz, dz)) = (x.satisfactions(satisfier), z.satisfactions(satisfier));
+                (smallest(sx, concat(sz, dx.clone())), concat(dz, dx))
+            },
+            Miniscript::OrI(x, z) => {
+                let ((sx, dx), (sz, dz)) = (x.satisfactions(satisfier), z.satisfactions(satisfier));
+                (
+                    smallest(concat(sx, one()), concat(sz, empty())),
+                    smallest(concat(dx, one()), concat(dz, empty())),
+                )
+            },
+            Miniscript::Thresh(k, subs) => {
+                let options: Vec<_> = subs.iter().map(|s| s.satisfactions(satisfier)).collect();
+                let dissat = options.iter().rev().try_fold(vec![], |mut acc, (_, d)| {
+                    acc.extend(d.clone()?);
+                    Some(acc)
+                });
+
+                // satisfy the k subs where satisfying costs the least over dissatisfying
+                let mut costs: Vec<(usize, usize)> = options.iter().enumerate().filter_map(|(i, (s, d))| {
+                    Some((i, witness_size(s.as_ref()?).saturating_sub(witness_size(d.as_ref()?))))
+                }).collect();
+                costs.sort_by_key(|(_, cost)| *cost);
+
+                let sat = if costs.len() >= *k {
+                    let chosen: Vec<usize> = costs[..*k].iter().map(|(i, _)| *i).collect();
+                    // the first sub executes first, so its elements go on top
+                    options.iter().enumerate().rev().try_fold(vec![], |mut acc, (i, (s, d))| {
+                        acc.extend(if chosen.contains(&i) { s.clone()? } else { d.clone()? });
+                        Some(acc)
+                    })
+                } else {
+                    None
+                };
+
+                (sat, dissat)
+            },
+            Miniscript::Multi(k, keys) => {
+                // CHECKMULTISIG pops one extra element, signatures follow key order
+                let sigs: Vec<Vec<u8>> = keys.iter().filter_map(|key| satisfier.signatures.get(key).cloned()).take(*k).collect();
+                let sat = if sigs.len() == *k {
+                    let mut witness = vec![vec![]];
+                    witness.extend(sigs);
+                    Some(witness)
+                } else {
+                    None
+                };
+
+                (sat, Some(vec![vec![]; k + 1]))
+            },
+            Miniscript::Alt(x) | Miniscript::Swap(x) | Miniscript::Check(x) | Miniscript::ZeroNotEqual(x) => x.satisfactions(satisfier),
+            Miniscript::DupIf(x) => (concat(x.satisfactions(satisfier).0, one()), empty()),
+            Miniscript::Verify(x) => (x.satisfactions(satisfier).0, None),
+            Miniscript::NonZero(x) => (x.satisfactions(satisfier).0, empty()),
+        }
+    }
+
+    fn wrapper(&self) -> Option<(char, &Miniscript)> {
+        match self {
+            Miniscript::Alt(x) => Some(('a', x)),
+            Miniscript::Swap(x) => Some(('s', x)),
+            Miniscript::Check(x) => match **x {
+                // shown as the pk() and pkh() shorthands instead
+                Miniscript::PkK(_) | Miniscript::PkH(_) => None,
+                _ => Some(('c', x)),
+            },
+            Miniscript::DupIf(x) => Some(('d', x)),
+            Miniscript::Verify(x) => Some(('v', x)),
+            Miniscript::NonZero(x) => Some(('j', x)),
+            Miniscript::ZeroNotEqual(x) => Some(('n', x)),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Miniscript {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.wrapper().is_some() {
+            let mut inner = self;
+            while let Some((c, x)) = inner.wrapper() {
+                write!(f, "{}", c)?;
+                inner = x;
+            }
+            return write!(f, ":{}", inner);
+        }
+
+        let join = |subs: &[Miniscript]| subs.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(",");
+
+        match self {
+            Miniscript::False => write!(f, "0"),
+            Miniscript::True => write!(f, "1"),
+            Miniscript::PkK(key) => write!(f, "pk_k({})", key.encode_hex::<String>()),
+            Miniscript::PkH(key) => write!(f, "pk_h({})", key.encode_hex::<String>()),
+            Miniscript::Older(n) => write!(f, "older({})", n),
+            Miniscript::After(n) => write!(f, "after({})", n),
+            Miniscript::Sha256(h) => write!(f, "sha256({})", h.encode_hex::<String>()),
+            Miniscript::Hash256(h) => write!(f, "hash256({})", h.encode_hex::<String>()),
+            Miniscript::Ripemd160(h) => write!(f, "ripemd160({})", h.encode_hex::<String>()),
+            Miniscript::Hash160(h) => write!(f, "hash160({})", h.encode_hex::<String>()),
+            Miniscript::AndOr(x, y, z) => write!(f, "andor({},{},{})", x, y, z),
+            Miniscript::AndV(x, y) => write!(f, "and_v({},{})", x, y),
+            Miniscript::AndB(x, y) => write!(f, "and_b({},{})", x, y),
+            Miniscript::OrB(x, z) => write!(f, "or_b({},{})", x, z),
+            Miniscript::OrC(x, z) => write!(f, "or_c({},{})", x, z),
+            Miniscript::OrD(x, z) => write!(f, "or_d({},{})", x, z),
+            Miniscript::OrI(x, z) => write!(f, "or_i({},{})", x, z),
+            Miniscript::Thresh(k, subs) => write!(f, "thresh({},{})", k, join(subs)),
+            Miniscript::Multi(k, keys) => {
+                let keys = keys.iter().map(|k| k.encode_hex::<String>()).collect::<Vec<_>>().join(",");
+                write!(f, "multi({},{})", k, keys)
+            },
+            Miniscript::Check(x) => match &**x {
+                Miniscript::PkK(key) => write!(f, "pk({})", key.encode_hex::<String>()),
+                Miniscript::PkH(key) => write!(f, "pkh({})", key.encode_hex::<String>()),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+}
+
+
+// -- Satisfier --
+
+/// what is available to satisfy a miniscript
+#[derive(Debug, Clone, Default)]
+pub struct Satisfier {
+    /// DER signatures with the sighash byte, by 33 bytes public key
+    pub signatures: HashMap<Vec<u8>, Vec<u8>>,
+    /// preimages by their hash, as written in the hash fragments
+    pub preimages: HashMap<Vec<u8>, Vec<u8>>,
+    /// nSequence of the spending input
+    pub sequence: Option<u32>,
+    /// nLockTime of the spending transaction
+    pub locktime: Option<u32>,
+}
+
+impl Satisfier {
+    /// older(n) is satisfied if the input's sequence is a relative lock of the same kind and at
+    /// least n, and doesn't disable relative locks
+    fn check_older(&self, n: u32) -> bool {
+        match self.sequence {
+            Some(sequence) if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 => false,
+            Some(sequence) => {
+                let mask = SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK;
+                (sequence & SEQUENCE_LOCKTIME_TYPE_FLAG) == (n & SEQUENCE_LOCKTIME_TYPE_FLAG) && (sequence & mask) >= (n & mask)
+            },
+            None => false,
+        }
+    }
+
+    /// after(n) is satisfied if the locktime is of the same kind (height or time) and at least n,
+    /// and the input's sequence isn't final, which would leave the locktime unenforced
+    fn check_after(&self, n: u32) -> bool {
+        if self.sequence == Some(SEQUENCE_FINAL) {
+            return false;
+        }
+        match self.locktime {
+            Some(locktime) => (locktime < LOCKTIME_THRESHOLD) == (n < LOCKTIME_THRESHOLD) && locktime >= n,
+            None => false,
+        }
+    }
+}
+
+
+// -- Policy --
+
+/// spending policy, `or` branches may carry `N@` probability weights
+#[derive(Debug, Clone, PartialEq)]
+pub enum Policy {
+    Key(Vec<u8>),
+    After(u32),
+    Older(u32),
+    Sha256(Vec<u8>),
+    Hash256(Vec<u8>),
+    Ripemd160(Vec<u8>),
+    Hash160(Vec<u8>),
+    And(Box<Policy>, Box<Policy>),
+    Or(usize, Box<Policy>, usize, Box<Policy>),
+    Thresh(usize, Vec<Policy>),
+}
+
+impl Policy {
+    pub fn parse(s: &str) -> Result<Self, MiniscriptError> {
+        let s = s.trim();
+        let (name, args) = split_call(s).map_err(|_| MiniscriptError::Syntax(s.to_string()))?;
+        let expect_args = |n: usize| {
+            if args.len() != n {
+                return Err(MiniscriptError::Syntax(s.to_string()));
+            }
+            Ok(())
+        };
+
+        let policy = match name {
+            "pk" => { expect_args(1)?; Policy::Key(parse_key(args[0])?) },
+            "after" => { expect_args(1)?; Policy::After(parse_timelock(args[0])?) },
+            "older" => { expect_args(1)?; Policy::Older(parse_timelock(args[0])?) },
+            "sha256" => { expect_args(1)?; Policy::Sha256(parse_hash(args[0], 32)?) },
+            "hash256" => { expect_args(1)?; Policy::Hash256(parse_hash(args[0], 32)?) },
+            "ripemd160" => { expect_args(1)?; Policy::Ripemd160(parse_hash(args[0], 20)?) },
+            "hash160" => { expect_args(1)?; Policy::Hash160(parse_hash(args[0], 20)?) },
+            "and" => { expect_args(2)?; Policy::And(Box::new(Self::parse(args[0])?), Box::new(Self::parse(args[1])?)) },
+            "or" => {
+                expect_args(2)?;
+                let (wa, a) = Self::parse_weighted(args[0])?;
+                let (wb, b) = Self::parse_weighted(args[1])?;
+                Policy::Or(wa, Box::new(a), wb, Box::new(b))
+            },
+            "thresh" => {
+                if args.len() < 2 {
+                    return Err(MiniscriptError::Syntax(s.to_string()));
+                }
+                let k = parse_threshold(args[0], args.len() - 1)?;
+                let subs = args[1..].iter().map(|a| Self::parse(a)).collect::<Result<Vec<_>, _>>()?;
+                Policy::Thresh(k, subs)
+            },
+            _ => return Err(MiniscriptError::UnknownFragment(name.to_string())),
+        };
+
+        Ok(policy)
+    }
+
+    fn parse_weighted(s: &str) -> Result<(usize, Self), MiniscriptError> {
+        match s.split_once('@') {
+            Some((weight, policy)) if !weight.contains('(') => {
+                let weight = weight.trim().parse::<usize>().map_err(|_| MiniscriptError::Syntax(s.to_string()))?;
+                Ok((weight, Self::parse(policy)?))
+            },
+            _ => Ok((1, Self::parse(s)?)),
+        }
+    }
+
+    /// compile into a B type miniscript using fixed templates, likelier `or` branches are placed
+    /// first so they are cheaper to satisfy
+    pub fn compile(&self) -> Result<Miniscript, MiniscriptError> {
+        let ms = match self {
+            Policy::Key(key) => Miniscript::Check(Box::new(Miniscript::PkK(key.clone()))),
+            Policy::After(n) => Miniscript::After(*n),
+            Policy::Older(n) => Miniscript::Older(*n),
+            Policy::Sha256(h) => Miniscript::Sha256(h.clone()),
+            Policy::Hash256(h) => Miniscript::Hash256(h.clone()),
+            Policy::Ripemd160(h) => Miniscript::Ripemd160(h.clone()),
+            Policy::Hash160(h) => Miniscript::Hash160(h.clone()),
+            Policy::And(a, b) => Miniscript::AndV(Box::new(Miniscript::Verify(Box::new(a.compile()?))), Box::new(b.compile()?)),
+            Policy::Or(wa, a, wb, b) => {
+                let (mut x, mut z) = (a.compile()?, b.compile()?);
+                if wb > wa {
+                    std::mem::swap(&mut x, &mut z);
+                }
+
+                let (tx, tz) = (x.ty()?, z.ty()?);
+                if tx.d && tx.u {
+                    Miniscript::OrD(Box::new(x), Box::new(z))
+                } else if tz.d && tz.u {
+                    Miniscript::OrD(Box::new(z), Box::new(x))
+                } else {
+                    Miniscript::OrI(Box::new(x), Box::new(z))
+                }
+            },
+            Policy::Thresh(k, subs) => {
+                let keys: Vec<Vec<u8>> = subs.iter().filter_map(|s| match s {
+                    Policy::Key(key) => Some(key.clone()),
+                    _ => None,
+                }).collect();
+
+                if keys.len() == subs.len() && keys.len() <= 20 {
+                    Miniscript::Multi(*k, keys)
+                } else if *k == subs.len() {
+                    let mut iter = subs.iter().rev();
+                    let last = iter.next().unwrap().clone();
+                    iter.fold(last, |acc, p| Policy::And(Box::new(p.clone()), Box::new(acc))).compile()?
+                } else if *k == 1 {
+                    let mut iter = subs.iter().rev();
+                    let last = iter.next().unwrap().clone();
+                    iter.fold(last, |acc, p| Policy::Or(1, Box::new(p.clone()), 1, Box::new(acc))).compile()?
+                } else {
+                    let mut compiled = vec![];
+                    for (i, sub) in subs.iter().enumerate() {
+                        let ms = make_du(sub.compile()?)?;
+                        compiled.push(if i == 0 { ms } else { make_w(ms)? });
+                    }
+                    Miniscript::Thresh(*k, compiled)
+                }
+            },
+        };
+
+        ms.check()?;
+        Ok(ms)
+    }
+}
+
+/// wrap a B expression so it becomes dissatisfiable and unit, as thresh() requires
+fn make_du(ms: Miniscript) -> Result<Miniscript, MiniscriptError> {
+    let ty = ms.ty()?;
+    if ty.d && ty.u {
+        return Ok(ms);
+    }
+    if ty.d {
+        return Ok(Miniscript::ZeroNotEqual(Box::new(ms)));
+    }
+    if ty.z {
+        return Ok(Miniscript::ZeroNotEqual(Box::new(Miniscript::DupIf(Box::new(Miniscript::Verify(Box::new(ms)))))));
+    }
+
+    let ms = Miniscript::OrI(Box::new(Miniscript::False), Box::new(ms));
+    if ms.ty()?.u {
+        Ok(ms)
+    } else {
+        Ok(Miniscript::ZeroNotEqual(Box::new(ms)))
+    }
+}
+
+/// turn a B expression into W, swapping single argument expressions and moving others through the
+/// alt stack
+fn make_w(ms: Miniscript) -> Result<Miniscript, MiniscriptError> {
+    if ms.ty()?.o {
+        Ok(Miniscript::Swap(Box::new(ms)))
+    } else {
+        Ok(Miniscript::Alt(Box::new(ms)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests_miniscript {
+    use hex::ToHex;
+
+    use crate::transaction::{SEQUENCE_FINAL, SEQUENCE_LOCKTIME_DISABLE_FLAG};
+
+    use super::{Miniscript, Policy, Satisfier, MiniscriptError};
+
+    const A: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+    const B: &str = "03fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556";
+    const C: &str = "022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01";
+    const D: &str = "02e493dbf1c10d80f3581e4904930b1404cc6c13900ee0758474fa94abe8c4cd13";
+
+    #[test]
+    fn test_parse_and_type() {
+        let s = format!("or_d(multi(2,{},{},{}),and_v(v:pk({}),older(12960)))", A, B, C, D);
+        let ms = Miniscript::parse(&s).unwrap();
+        assert_eq!(s, ms.to_string());
+        assert_eq!("B", ms.check().unwrap().to_string());
+
+        let ms = Miniscript::parse(&format!("and_b(pk({}),s:pk({}))", A, B)).unwrap();
+        assert_eq!("Bndu", ms.check().unwrap().to_string());
+
+        // a V expression can't be the first argument of or_d
+        let ms = Miniscript::parse(&format!("or_d(v:pk({}),pk({}))", A, B)).unwrap();
+        assert!(matches!(ms.check(), Err(MiniscriptError::TypeCheck(_))));
+
+        // t: is and_v(X,1)
+        let ms = Miniscript::parse(&format!("t:v:pk({})", A)).unwrap();
+        assert_eq!(format!("and_v(v:pk({}),1)", A), ms.to_string());
+    }
+
+    #[test]
+    fn test_encode() {
+        let ms = Miniscript::parse(&format!("and_v(v:pk({}),older(144))", A)).unwrap();
+        assert_eq!(
+            format!("21{}ad029000b2", A),
+            ms.encode().raw_serialize().encode_hex::<String>(),
+        );
+        assert_eq!(39, ms.script_size());
+    }
+
+    #[test]
+    fn test_compile() {
+        // 2 of 3 keys, or one key after 90 days
+        let policy = Policy::parse(&format!("or(99@thresh(2,pk({}),pk({}),pk({})),1@and(pk({}),older(12960)))", A, B, C, D)).unwrap();
+        let ms = policy.compile().unwrap();
+        assert_eq!(format!("or_d(multi(2,{},{},{}),and_v(v:pk({}),older(12960)))", A, B, C, D), ms.to_string());
+        assert_eq!(Some(1 + 2 * 74), ms.max_satisfaction_size());
+
+        let policy = Policy::parse(&format!("thresh(2,pk({}),pk({}),older(144))", A, B)).unwrap();
+        let ms = policy.compile().unwrap();
+        assert_eq!(format!("thresh(2,pk({}),s:pk({}),sndv:older(144))", A, B), ms.to_string());
+        assert_eq!("Bdu", ms.check().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_satisfy() {
+        let ms = Miniscript::parse(&format!("or_d(multi(2,{},{},{}),and_v(v:pk({}),older(12960)))", A, B, C, D)).unwrap();
+        let sig = |b: u8| vec![0x30, b];
+
+        let mut satisfier = Satisfier::default();
+        satisfier.signatures.insert(hex::decode(C).unwrap(), sig(3));
+        satisfier.signatures.insert(hex::decode(A).unwrap(), sig(1));
+        assert_eq!(Some(vec![vec![], sig(1), sig(3)]), ms.satisfy(&satisfier));
+
+        // the recovery key alone needs the timelock
+        let mut satisfier = Satisfier::default();
+        satisfier.signatures.insert(hex::decode(D).unwrap(), sig(4));
+        assert_eq!(None, ms.satisfy(&satisfier));
+
+        satisfier.sequence = Some(12960);
+        assert_eq!(Some(vec![sig(4), vec![], vec![], vec![]]), ms.satisfy(&satisfier));
+        // CSV fails on a sequence that disables relative locks
+        satisfier.sequence = Some(12960 | SEQUENCE_LOCKTIME_DISABLE_FLAG);
+        assert_eq!(None, ms.satisfy(&satisfier));
+
+        // and CLTV on a final sequence
+        let ms = Miniscript::parse(&format!("and_v(v:pk({}),after(100))", D)).unwrap();
+        satisfier.locktime = Some(100);
+        satisfier.sequence = None;
+        assert_eq!(Some(vec![sig(4)]), ms.satisfy(&satisfier));
+        satisfier.sequence = Some(SEQUENCE_FINAL);
+        assert_eq!(None, ms.satisfy(&satisfier));
+    }
+}
@@ -0,0 +1,296 @@
+use std::fmt::Display;
+
+use hmac::{Hmac, Mac};
+use sha2::{Sha256, Sha512, Digest};
+use unicode_normalization::UnicodeNormalization;
+
+
+type HmacSha512 = Hmac<Sha512>;
+
+/// rounds of PBKDF2 used to stretch a mnemonic into a seed
+const PBKDF2_ROUNDS: usize = 2048;
+
+/// BIP39 wordlist languages, everything except English sits behind a cargo feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    English,
+    #[cfg(feature = "chinese-simplified")]
+    ChineseSimplified,
+    #[cfg(feature = "chinese-traditional")]
+    ChineseTraditional,
+    #[cfg(feature = "czech")]
+    Czech,
+    #[cfg(feature = "french")]
+    French,
+    #[cfg(feature = "italian")]
+    Italian,
+    #[cfg(feature = "japanese")]
+    Japanese,
+    #[cfg(feature = "korean")]
+    Korean,
+    #[cfg(feature = "portuguese")]
+    Portuguese,
+    #[cfg(feature = "spanish")]
+    Spanish,
+}
+
+impl Language {
+    /// the 2048 words in NFKD form, sorted as in the BIP39 lists
+    pub fn word_list(&self) -> Vec<&'static str> {
+        let words = match self {
+            Language::English => include_str!("wordlists/english.txt"),
+            #[cfg(feature = "chinese-simplified")]
+            Language::ChineseSimplified => include_str!("wordlists/chinese_simplified.txt"),
+            #[cfg(feature = "chinese-traditional")]
+            Language::ChineseTraditional => include_str!("wordlists/chinese_traditional.txt"),
+            #[cfg(feature = "czech")]
+            Language::Czech => include_str!("wordlists/czech.txt"),
+            #[cfg(feature = "french")]
+            Language::French => include_str!("wordlists/french.txt"),
+            #[cfg(feature = "italian")]
+            Language::Italian => include_str!("wordlists/italian.txt"),
+            #[cfg(feature = "japanese")]
+            Language::Japanese => include_str!("wordlists/japanese.txt"),
+            #[cfg(feature = "korean")]
+            Language::Korean => include_str!("wordlists/korean.txt"),
+            #[cfg(feature = "portuguese")]
+            Language::Portuguese => include_str!("wordlists/portuguese.txt"),
+            #[cfg(feature = "spanish")]
+            Language::Spanish => include_str!("wordlists/spanish.txt"),
+        };
+
+        words.lines().collect()
+    }
+
+    /// separator between words when displaying a phrase, japanese uses an ideographic space
+    pub fn separator(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "japanese")]
+            Language::Japanese => "\u{3000}",
+            _ => " ",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MnemonicError {
+    /// entropy must be 128 to 256 bits in steps of 32
+    InvalidEntropyLength(usize),
+    /// phrases have 12, 15, 18, 21 or 24 words
+    InvalidWordCount(usize),
+    UnknownWord(String),
+    InvalidChecksum,
+}
+
+impl Display for MnemonicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MnemonicError::InvalidEntropyLength(len) => write!(f, "invalid entropy length of {} bytes", len),
+            MnemonicError::InvalidWordCount(count) => write!(f, "invalid word count {}", count),
+            MnemonicError::UnknownWord(w) => write!(f, "unknown word: {}", w),
+            MnemonicError::InvalidChecksum => write!(f, "invalid checksum"),
+        }
+    }
+}
+
+
+// -- Mnemonic --
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mnemonic {
+    pub language: Language,
+    /// wordlist indexes, 11 bits each
+    pub indexes: Vec<u16>,
+}
+
+impl Mnemonic {
+    /// encode 16, 20, 24, 28 or 32 bytes of entropy into 12 to 24 words
+    pub fn from_entropy(entropy: &[u8], language: Language) -> Result<Self, MnemonicError> {
+        if entropy.len() < 16 || entropy.len() > 32 || !entropy.len().is_multiple_of(4) {
+            return Err(MnemonicError::InvalidEntropyLength(entropy.len()));
+        }
+
+        // the checksum is the first ENT / 32 bits of sha256(entropy)
+        let checksum_bits = entropy.len() / 4;
+        let mut bits = bytes_to_bits(entropy);
+        bits.extend_from_slice(&bytes_to_bits(&Sha256::digest(entropy))[..checksum_bits]);
+
+        let mut indexes = vec![];
+        for chunk in bits.chunks(11) {
+            let mut index = 0u16;
+            for bit in chunk {
+                index = (index << 1) | (*bit as u16);
+            }
+            indexes.push(index);
+        }
+
+        Ok(Self {
+            language,
+            indexes,
+        })
+    }
+
+    /// parse a phrase and validate its checksum, words may be separated by any whitespace
+    pub fn parse(phrase: &str, language: Language) -> Result<Self, MnemonicError> {
+        let word_list = language.word_list();
+        let phrase = phrase.nfkd().collect::<String>();
+        let words: Vec<&str> = phrase.split_whitespace().collect();
+        if words.len() < 12 || words.len() > 24 || !words.len().is_multiple_of(3) {
+            return Err(MnemonicError::InvalidWordCount(words.len()));
+        }
+
+        let mut indexes = vec![];
+        for word in words {
+            match word_list.iter().position(|w| *w == word) {
+                Some(index) => indexes.push(index as u16),
+                None => return Err(MnemonicError::UnknownWord(word.to_string())),
+            }
+        }
+
+        let mnemonic = Self {
+            language,
+            indexes,
+        };
+
+        let expected = Self::from_entropy(&mnemonic.to_entropy(), language)?;
+        if expected.indexes != mnemonic.indexes {
+            return Err(MnemonicError::InvalidChecksum);
+        }
+
+        Ok(mnemonic)
+    }
+
+    /// recover the entropy, dropping the checksum bits
+    pub fn to_entropy(&self) -> Vec<u8> {
+        let mut bits = vec![];
+        for index in &self.indexes {
+            for i in (0..11).rev() {
+                bits.push((index >> i) & 1 == 1);
+            }
+        }
+
+        let entropy_bits = bits.len() * 32 / 33;
+        bits[..entropy_bits].chunks(8).map(|byte| {
+            byte.iter().fold(0u8, |acc, bit| (acc << 1) | (*bit as u8))
+        }).collect()
+    }
+
+    pub fn words(&self) -> Vec<&'static str> {
+        let word_list = self.language.word_list();
+        self.indexes.iter().map(|i| word_list[*i as usize]).collect()
+    }
+
+    /// 64 bytes seed for `ExtendedPrivKey::new_master`, PBKDF2-HMAC-SHA512 of the phrase salted with
+    /// "mnemonic" + passphrase
+    pub fn to_seed(&self, passphrase: &str) -> Vec<u8> {
+        let phrase = self.words().join(" ");
+        let salt = format!("mnemonic{}", passphrase).nfkd().collect::<String>();
+
+        pbkdf2_hmac_sha512(phrase.as_bytes(), salt.as_bytes(), PBKDF2_ROUNDS)
+    }
+}
+
+impl Display for Mnemonic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.words().join(self.language.separator()))
+    }
+}
+
+fn bytes_to_bits(b: &[u8]) -> Vec<bool> {
+    let mut bits = vec![];
+    for byte in b {
+        for i in (0..8).rev() {
+            bits.push((byte >> i) & 1 == 1);
+        }
+    }
+
+    bits
+}
+
+/// PBKDF2 with a single 64 bytes output block
+fn pbkdf2_hmac_sha512(password: &[u8], salt: &[u8], rounds: usize) -> Vec<u8> {
+    let mut mac = HmacSha512::new_from_slice(password).unwrap();
+    let mut block = salt.to_owned();
+    block.extend_from_slice(&1u32.to_be_bytes());
+    mac.update(&block);
+    let mut u = mac.finalize().into_bytes().to_vec();
+    let mut result = u.clone();
+
+    for _ in 1..rounds {
+        let mut mac = HmacSha512::new_from_slice(password).unwrap();
+        mac.update(&u);
+        u = mac.finalize().into_bytes().to_vec();
+        for (r, x) in result.iter_mut().zip(&u) {
+            *r ^= x;
+        }
+    }
+
+    result
+}
+
+
+#[cfg(test)]
+mod tests_mnemonic {
+    use hex::ToHex;
+
+    use crate::{extended_key::ExtendedPrivKey, network::Network};
+
+    use super::{Mnemonic, Language, MnemonicError};
+
+    #[test]
+    fn test_from_entropy() {
+        let mnemonic = Mnemonic::from_entropy(&[0u8; 16], Language::English).unwrap();
+        assert_eq!(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+            mnemonic.to_string(),
+        );
+
+        let mnemonic = Mnemonic::from_entropy(&[0x7fu8; 16], Language::English).unwrap();
+        assert_eq!(
+            "legal winner thank year wave sausage worth useful legal winner thank yellow",
+            mnemonic.to_string(),
+        );
+
+        let mnemonic = Mnemonic::from_entropy(&[0xffu8; 32], Language::English).unwrap();
+        assert_eq!(24, mnemonic.indexes.len());
+        assert_eq!(vec![0xffu8; 32], mnemonic.to_entropy());
+
+        assert_eq!(
+            Err(MnemonicError::InvalidEntropyLength(15)),
+            Mnemonic::from_entropy(&[0u8; 15], Language::English),
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let phrase = "legal winner thank year wave sausage worth useful legal winner thank yellow";
+        let mnemonic = Mnemonic::parse(phrase, Language::English).unwrap();
+        assert_eq!(vec![0x7fu8; 16], mnemonic.to_entropy());
+
+        let phrase = "legal winner thank year wave sausage worth useful legal winner thank thank";
+        assert_eq!(Err(MnemonicError::InvalidChecksum), Mnemonic::parse(phrase, Language::English));
+
+        let phrase = "legal winner thank year wave sausage worth useful legal winner thank bitcoin";
+        assert_eq!(
+            Err(MnemonicError::UnknownWord("bitcoin".to_string())),
+            Mnemonic::parse(phrase, Language::English),
+        );
+    }
+
+    #[test]
+    fn test_to_seed() {
+        let mnemonic = Mnemonic::from_entropy(&[0u8; 16], Language::English).unwrap();
+        let seed = mnemonic.to_seed("TREZOR");
+
+        assert_eq!(
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+            seed.encode_hex::<String>(),
+        );
+
+        let master = ExtendedPrivKey::new_master(&seed, Network::Mainnet).unwrap();
+        assert_eq!(
+            "xprv9s21ZrQH143K3h3fDYiay8mocZ3afhfULfb5GX8kCBdno77K4HiA15Tg23wpbeF1pLfs1c5SPmYHrEpTuuRhxMwvKDwqdKiGJS9XFKzUsAF",
+            master.to_string(),
+        );
+    }
+}
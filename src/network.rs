@@ -0,0 +1,128 @@
+use std::fmt::Display;
+
+
+/// the bitcoin network a key, address or transaction belongs to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Testnet4,
+    Signet,
+    Regtest,
+}
+
+impl Network {
+    /// version byte prepended to a hash160 for P2PKH addresses
+    pub fn p2pkh_prefix(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0x00,
+            _ => 0x6f,
+        }
+    }
+
+    /// version byte prepended to a hash160 for P2SH addresses
+    pub fn p2sh_prefix(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0x05,
+            _ => 0xc4,
+        }
+    }
+
+    /// version byte prepended to a secret for WIF encoding
+    pub fn wif_prefix(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0x80,
+            _ => 0xef,
+        }
+    }
+
+    /// version bytes of BIP32 extended private keys (xprv/tprv)
+    pub fn xprv_version(&self) -> [u8; 4] {
+        match self {
+            Network::Mainnet => [0x04, 0x88, 0xad, 0xe4],
+            _ => [0x04, 0x35, 0x83, 0x94],
+        }
+    }
+
+    /// version bytes of BIP32 extended public keys (xpub/tpub)
+    pub fn xpub_version(&self) -> [u8; 4] {
+        match self {
+            Network::Mainnet => [0x04, 0x88, 0xb2, 0x1e],
+            _ => [0x04, 0x35, 0x87, 0xcf],
+        }
+    }
+
+    /// human-readable part of bech32 segwit addresses
+    pub fn bech32_hrp(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "bc",
+            Network::Testnet | Network::Testnet4 | Network::Signet => "tb",
+            Network::Regtest => "bcrt",
+        }
+    }
+
+    /// magic bytes starting every p2p message, in wire order
+    pub fn magic(&self) -> [u8; 4] {
+        match self {
+            Network::Mainnet => [0xf9, 0xbe, 0xb4, 0xd9],
+            Network::Testnet => [0x0b, 0x11, 0x09, 0x07],
+            Network::Testnet4 => [0x1c, 0x16, 0x3f, 0x28],
+            Network::Signet => [0x0a, 0x03, 0xcf, 0x40],
+            Network::Regtest => [0xfa, 0xbf, 0xb5, 0xda],
+        }
+    }
+
+    /// default p2p port
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Network::Mainnet => 8333,
+            Network::Testnet => 18333,
+            Network::Testnet4 => 48333,
+            Network::Signet => 38333,
+            Network::Regtest => 18444,
+        }
+    }
+
+    /// hash of the genesis block, big-endian as shown by block explorers
+    pub fn genesis_hash(&self) -> Vec<u8> {
+        let h = match self {
+            Network::Mainnet => "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+            Network::Testnet => "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943",
+            Network::Testnet4 => "00000000da84f2bafbbc53dee25a72ae507ff4914b867c565be350b0da8bf043",
+            Network::Signet => "00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6",
+            Network::Regtest => "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+        };
+
+        hex::decode(h).unwrap()
+    }
+}
+
+impl Display for Network {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Testnet4 => "testnet4",
+            Network::Signet => "signet",
+            Network::Regtest => "regtest",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+
+#[cfg(test)]
+mod tests_network {
+    use super::Network;
+
+    #[test]
+    fn test_prefixes() {
+        assert_eq!(0x00, Network::Mainnet.p2pkh_prefix());
+        assert_eq!(0x6f, Network::Signet.p2pkh_prefix());
+        assert_eq!(0xc4, Network::Regtest.p2sh_prefix());
+        assert_eq!(0xef, Network::Testnet4.wif_prefix());
+        assert_eq!("bcrt", Network::Regtest.bech32_hrp());
+        assert_eq!(32, Network::Testnet4.genesis_hash().len());
+    }
+}
@@ -1,27 +1,1261 @@
-use crate::utils::{hash256, hash160};
+use num_bigint::BigInt;
 
-pub fn op_dup(stack: &mut Vec<Vec<u8>>) -> bool {
-    if stack.len() < 1 {
-        return false;
+use crate::{
+    interpreter::{ExecData, ScriptError, SigVersion, SignatureChecker, VerifyFlags},
+    s256point::S256Point,
+    script::Script,
+    signature::{Signature, SchnorrSignature},
+    transaction::{SEQUENCE_LOCKTIME_DISABLE_FLAG, SIGHASH_DEFAULT, SIGHASH_ALL, SIGHASH_SINGLE, SIGHASH_ANYONECANPAY},
+    utils::{hash256, hash160, sha256, sha1, ripemd160, int_to_big_endian},
+};
+
+
+// -- op codes --
+
+pub const OP_0: u8 = 0x00;
+pub const OP_PUSHDATA1: u8 = 0x4c;
+pub const OP_PUSHDATA2: u8 = 0x4d;
+pub const OP_PUSHDATA4: u8 = 0x4e;
+pub const OP_1NEGATE: u8 = 0x4f;
+pub const OP_RESERVED: u8 = 0x50;
+pub const OP_1: u8 = 0x51;
+pub const OP_2: u8 = 0x52;
+pub const OP_3: u8 = 0x53;
+pub const OP_4: u8 = 0x54;
+pub const OP_5: u8 = 0x55;
+pub const OP_6: u8 = 0x56;
+pub const OP_7: u8 = 0x57;
+pub const OP_8: u8 = 0x58;
+pub const OP_9: u8 = 0x59;
+pub const OP_10: u8 = 0x5a;
+pub const OP_11: u8 = 0x5b;
+pub const OP_12: u8 = 0x5c;
+pub const OP_13: u8 = 0x5d;
+pub const OP_14: u8 = 0x5e;
+pub const OP_15: u8 = 0x5f;
+pub const OP_16: u8 = 0x60;
+pub const OP_NOP: u8 = 0x61;
+pub const OP_VER: u8 = 0x62;
+pub const OP_IF: u8 = 0x63;
+pub const OP_NOTIF: u8 = 0x64;
+pub const OP_VERIF: u8 = 0x65;
+pub const OP_VERNOTIF: u8 = 0x66;
+pub const OP_ELSE: u8 = 0x67;
+pub const OP_ENDIF: u8 = 0x68;
+pub const OP_VERIFY: u8 = 0x69;
+pub const OP_RETURN: u8 = 0x6a;
+pub const OP_TOALTSTACK: u8 = 0x6b;
+pub const OP_FROMALTSTACK: u8 = 0x6c;
+pub const OP_2DROP: u8 = 0x6d;
+pub const OP_2DUP: u8 = 0x6e;
+pub const OP_3DUP: u8 = 0x6f;
+pub const OP_2OVER: u8 = 0x70;
+pub const OP_2ROT: u8 = 0x71;
+pub const OP_2SWAP: u8 = 0x72;
+pub const OP_IFDUP: u8 = 0x73;
+pub const OP_DEPTH: u8 = 0x74;
+pub const OP_DROP: u8 = 0x75;
+pub const OP_DUP: u8 = 0x76;
+pub const OP_NIP: u8 = 0x77;
+pub const OP_OVER: u8 = 0x78;
+pub const OP_PICK: u8 = 0x79;
+pub const OP_ROLL: u8 = 0x7a;
+pub const OP_ROT: u8 = 0x7b;
+pub const OP_SWAP: u8 = 0x7c;
+pub const OP_TUCK: u8 = 0x7d;
+pub const OP_CAT: u8 = 0x7e;
+pub const OP_SUBSTR: u8 = 0x7f;
+pub const OP_LEFT: u8 = 0x80;
+pub const OP_RIGHT: u8 = 0x81;
+pub const OP_SIZE: u8 = 0x82;
+pub const OP_INVERT: u8 = 0x83;
+pub const OP_AND: u8 = 0x84;
+pub const OP_OR: u8 = 0x85;
+pub const OP_XOR: u8 = 0x86;
+pub const OP_EQUAL: u8 = 0x87;
+pub const OP_EQUALVERIFY: u8 = 0x88;
+pub const OP_RESERVED1: u8 = 0x89;
+pub const OP_RESERVED2: u8 = 0x8a;
+pub const OP_1ADD: u8 = 0x8b;
+pub const OP_1SUB: u8 = 0x8c;
+pub const OP_2MUL: u8 = 0x8d;
+pub const OP_2DIV: u8 = 0x8e;
+pub const OP_NEGATE: u8 = 0x8f;
+pub const OP_ABS: u8 = 0x90;
+pub const OP_NOT: u8 = 0x91;
+pub const OP_0NOTEQUAL: u8 = 0x92;
+pub const OP_ADD: u8 = 0x93;
+pub const OP_SUB: u8 = 0x94;
+pub const OP_MUL: u8 = 0x95;
+pub const OP_DIV: u8 = 0x96;
+pub const OP_MOD: u8 = 0x97;
+pub const OP_LSHIFT: u8 = 0x98;
+pub const OP_RSHIFT: u8 = 0x99;
+pub const OP_BOOLAND: u8 = 0x9a;
+pub const OP_BOOLOR: u8 = 0x9b;
+pub const OP_NUMEQUAL: u8 = 0x9c;
+pub const OP_NUMEQUALVERIFY: u8 = 0x9d;
+pub const OP_NUMNOTEQUAL: u8 = 0x9e;
+pub const OP_LESSTHAN: u8 = 0x9f;
+pub const OP_GREATERTHAN: u8 = 0xa0;
+pub const OP_LESSTHANOREQUAL: u8 = 0xa1;
+pub const OP_GREATERTHANOREQUAL: u8 = 0xa2;
+pub const OP_MIN: u8 = 0xa3;
+pub const OP_MAX: u8 = 0xa4;
+pub const OP_WITHIN: u8 = 0xa5;
+pub const OP_RIPEMD160: u8 = 0xa6;
+pub const OP_SHA1: u8 = 0xa7;
+pub const OP_SHA256: u8 = 0xa8;
+pub const OP_HASH160: u8 = 0xa9;
+pub const OP_HASH256: u8 = 0xaa;
+pub const OP_CODESEPARATOR: u8 = 0xab;
+pub const OP_CHECKSIG: u8 = 0xac;
+pub const OP_CHECKSIGVERIFY: u8 = 0xad;
+pub const OP_CHECKMULTISIG: u8 = 0xae;
+pub const OP_CHECKMULTISIGVERIFY: u8 = 0xaf;
+pub const OP_NOP1: u8 = 0xb0;
+pub const OP_CHECKLOCKTIMEVERIFY: u8 = 0xb1;
+pub const OP_CHECKSEQUENCEVERIFY: u8 = 0xb2;
+pub const OP_NOP4: u8 = 0xb3;
+pub const OP_NOP5: u8 = 0xb4;
+pub const OP_NOP6: u8 = 0xb5;
+pub const OP_NOP7: u8 = 0xb6;
+pub const OP_NOP8: u8 = 0xb7;
+pub const OP_NOP9: u8 = 0xb8;
+pub const OP_NOP10: u8 = 0xb9;
+pub const OP_CHECKSIGADD: u8 = 0xba;
+pub const OP_INVALIDOPCODE: u8 = 0xff;
+
+/// name of an opcode as Bitcoin Core writes it in ASM, small integers are plain numbers
+pub fn op_code_name(op: u8) -> &'static str {
+    match op {
+        OP_0 => "0",
+        OP_PUSHDATA1 => "OP_PUSHDATA1",
+        OP_PUSHDATA2 => "OP_PUSHDATA2",
+        OP_PUSHDATA4 => "OP_PUSHDATA4",
+        OP_1NEGATE => "-1",
+        OP_RESERVED => "OP_RESERVED",
+        OP_1 => "1",
+        OP_2 => "2",
+        OP_3 => "3",
+        OP_4 => "4",
+        OP_5 => "5",
+        OP_6 => "6",
+        OP_7 => "7",
+        OP_8 => "8",
+        OP_9 => "9",
+        OP_10 => "10",
+        OP_11 => "11",
+        OP_12 => "12",
+        OP_13 => "13",
+        OP_14 => "14",
+        OP_15 => "15",
+        OP_16 => "16",
+        OP_NOP => "OP_NOP",
+        OP_VER => "OP_VER",
+        OP_IF => "OP_IF",
+        OP_NOTIF => "OP_NOTIF",
+        OP_VERIF => "OP_VERIF",
+        OP_VERNOTIF => "OP_VERNOTIF",
+        OP_ELSE => "OP_ELSE",
+        OP_ENDIF => "OP_ENDIF",
+        OP_VERIFY => "OP_VERIFY",
+        OP_RETURN => "OP_RETURN",
+        OP_TOALTSTACK => "OP_TOALTSTACK",
+        OP_FROMALTSTACK => "OP_FROMALTSTACK",
+        OP_2DROP => "OP_2DROP",
+        OP_2DUP => "OP_2DUP",
+        OP_3DUP => "OP_3DUP",
+        OP_2OVER => "OP_2OVER",
+        OP_2ROT => "OP_2ROT",
+        OP_2SWAP => "OP_2SWAP",
+        OP_IFDUP => "OP_IFDUP",
+        OP_DEPTH => "OP_DEPTH",
+        OP_DROP => "OP_DROP",
+        OP_DUP => "OP_DUP",
+        OP_NIP => "OP_NIP",
+        OP_OVER => "OP_OVER",
+        OP_PICK => "OP_PICK",
+        OP_ROLL => "OP_ROLL",
+        OP_ROT => "OP_ROT",
+        OP_SWAP => "OP_SWAP",
+        OP_TUCK => "OP_TUCK",
+        OP_CAT => "OP_CAT",
+        OP_SUBSTR => "OP_SUBSTR",
+        OP_LEFT => "OP_LEFT",
+        OP_RIGHT => "OP_RIGHT",
+        OP_SIZE => "OP_SIZE",
+        OP_INVERT => "OP_INVERT",
+        OP_AND => "OP_AND",
+        OP_OR => "OP_OR",
+        OP_XOR => "OP_XOR",
+        OP_EQUAL => "OP_EQUAL",
+        OP_EQUALVERIFY => "OP_EQUALVERIFY",
+        OP_RESERVED1 => "OP_RESERVED1",
+        OP_RESERVED2 => "OP_RESERVED2",
+        OP_1ADD => "OP_1ADD",
+        OP_1SUB => "OP_1SUB",
+        OP_2MUL => "OP_2MUL",
+        OP_2DIV => "OP_2DIV",
+        OP_NEGATE => "OP_NEGATE",
+        OP_ABS => "OP_ABS",
+        OP_NOT => "OP_NOT",
+        OP_0NOTEQUAL => "OP_0NOTEQUAL",
+        OP_ADD => "OP_ADD",
+        OP_SUB => "OP_SUB",
+        OP_MUL => "OP_MUL",
+        OP_DIV => "OP_DIV",
+        OP_MOD => "OP_MOD",
+        OP_LSHIFT => "OP_LSHIFT",
+        OP_RSHIFT => "OP_RSHIFT",
+        OP_BOOLAND => "OP_BOOLAND",
+        OP_BOOLOR => "OP_BOOLOR",
+        OP_NUMEQUAL => "OP_NUMEQUAL",
+        OP_NUMEQUALVERIFY => "OP_NUMEQUALVERIFY",
+        OP_NUMNOTEQUAL => "OP_NUMNOTEQUAL",
+        OP_LESSTHAN => "OP_LESSTHAN",
+        OP_GREATERTHAN => "OP_GREATERTHAN",
+        OP_LESSTHANOREQUAL => "OP_LESSTHANOREQUAL",
+        OP_GREATERTHANOREQUAL => "OP_GREATERTHANOREQUAL",
+        OP_MIN => "OP_MIN",
+        OP_MAX => "OP_MAX",
+        OP_WITHIN => "OP_WITHIN",
+        OP_RIPEMD160 => "OP_RIPEMD160",
+        OP_SHA1 => "OP_SHA1",
+        OP_SHA256 => "OP_SHA256",
+        OP_HASH160 => "OP_HASH160",
+        OP_HASH256 => "OP_HASH256",
+        OP_CODESEPARATOR => "OP_CODESEPARATOR",
+        OP_CHECKSIG => "OP_CHECKSIG",
+        OP_CHECKSIGVERIFY => "OP_CHECKSIGVERIFY",
+        OP_CHECKMULTISIG => "OP_CHECKMULTISIG",
+        OP_CHECKMULTISIGVERIFY => "OP_CHECKMULTISIGVERIFY",
+        OP_NOP1 => "OP_NOP1",
+        OP_CHECKLOCKTIMEVERIFY => "OP_CHECKLOCKTIMEVERIFY",
+        OP_CHECKSEQUENCEVERIFY => "OP_CHECKSEQUENCEVERIFY",
+        OP_NOP4 => "OP_NOP4",
+        OP_NOP5 => "OP_NOP5",
+        OP_NOP6 => "OP_NOP6",
+        OP_NOP7 => "OP_NOP7",
+        OP_NOP8 => "OP_NOP8",
+        OP_NOP9 => "OP_NOP9",
+        OP_NOP10 => "OP_NOP10",
+        OP_CHECKSIGADD => "OP_CHECKSIGADD",
+        OP_INVALIDOPCODE => "OP_INVALIDOPCODE",
+        _ => "OP_UNKNOWN",
+    }
+}
+
+/// opcode for a name, with or without the OP_ prefix, also accepting the OP_FALSE, OP_TRUE, OP_NOP2
+/// and OP_NOP3 aliases
+pub fn op_code_from_name(name: &str) -> Option<u8> {
+    let name = name.strip_prefix("OP_").unwrap_or(name);
+    match name {
+        "FALSE" => return Some(OP_0),
+        "TRUE" => return Some(OP_1),
+        "NOP2" => return Some(OP_CHECKLOCKTIMEVERIFY),
+        "NOP3" => return Some(OP_CHECKSEQUENCEVERIFY),
+        _ => {},
+    }
+
+    (0..=255u8).find(|op| {
+        let op_name = op_code_name(*op);
+        op_name != "OP_UNKNOWN" && op_name.strip_prefix("OP_").unwrap_or(op_name) == name
+    })
+}
+
+
+// -- limits --
+
+/// serialized scripts longer than this fail
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+/// pushes and stack elements are at most this many bytes
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+/// opcodes above OP_16 a script may contain, executed or not
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+/// main and alt stack elements together
+pub const MAX_STACK_SIZE: usize = 1000;
+
+
+// -- numbers --
+
+/// operands of the arithmetic opcodes are at most 4 bytes, results may overflow that
+pub const MAX_NUM_SIZE: usize = 4;
+
+/// little-endian sign-magnitude encoding of a script number, 0 is the empty vector
+pub fn encode_num(num: i64) -> Vec<u8> {
+    if num == 0 {
+        return vec![];
+    }
+
+    let negative = num < 0;
+    let mut abs = num.unsigned_abs();
+    let mut result = vec![];
+    while abs > 0 {
+        result.push((abs & 0xff) as u8);
+        abs >>= 8;
+    }
+
+    // the top bit of the last byte is the sign, add a byte if the magnitude already uses it
+    let last = result.len() - 1;
+    if result[last] & 0x80 != 0 {
+        result.push(if negative { 0x80 } else { 0x00 });
+    } else if negative {
+        result[last] |= 0x80;
+    }
+
+    result
+}
+
+/// decode a script number of at most `max_size` bytes, with `require_minimal` it must also be
+/// encoded as `encode_num` would
+pub fn decode_num(element: &[u8], max_size: usize, require_minimal: bool) -> Result<i64, ScriptError> {
+    if element.len() > max_size {
+        return Err(ScriptError::NumOverflow(element.len()));
+    }
+    if element.is_empty() {
+        return Ok(0);
+    }
+
+    let last = element.len() - 1;
+    // a last byte of only the sign bit is padding, fine only if the byte below needs its top bit
+    if require_minimal && element[last] & 0x7f == 0 && (last == 0 || element[last - 1] & 0x80 == 0) {
+        return Err(ScriptError::NonMinimalNum);
+    }
+
+    let negative = element[last] & 0x80 != 0;
+    let mut result = (element[last] & 0x7f) as i64;
+    for b in element[..last].iter().rev() {
+        result = (result << 8) + *b as i64;
+    }
+
+    Ok(if negative { -result } else { result })
+}
+
+/// any nonzero byte is true, except for negative zero
+pub fn cast_to_bool(element: &[u8]) -> bool {
+    for (i, b) in element.iter().enumerate() {
+        if *b != 0 {
+            return !(i == element.len() - 1 && *b == 0x80);
+        }
+    }
+
+    false
+}
+
+fn encode_bool(b: bool) -> Vec<u8> {
+    if b { encode_num(1) } else { encode_num(0) }
+}
+
+fn pop(stack: &mut Vec<Vec<u8>>) -> Result<Vec<u8>, ScriptError> {
+    stack.pop().ok_or(ScriptError::InvalidStackOperation)
+}
+
+/// numbers must be minimally encoded under MINIMALDATA
+fn pop_num(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<i64, ScriptError> {
+    decode_num(&pop(stack)?, MAX_NUM_SIZE, flags.contains(VerifyFlags::MINIMALDATA))
+}
+
+/// check there are at least `n` elements on the stack
+fn require(stack: &[Vec<u8>], n: usize) -> Result<(), ScriptError> {
+    if stack.len() < n {
+        return Err(ScriptError::InvalidStackOperation);
+    }
+
+    Ok(())
+}
+
+
+// -- constants --
+
+/// OP_1NEGATE and OP_1 to OP_16
+pub fn op_push_num(stack: &mut Vec<Vec<u8>>, num: i64) -> Result<(), ScriptError> {
+    stack.push(encode_num(num));
+    Ok(())
+}
+
+
+// -- flow control --
+
+/// push whether the new branch executes, the condition is only popped inside an executed branch
+fn push_condition(
+    stack: &mut Vec<Vec<u8>>,
+    exec_stack: &mut Vec<bool>,
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+    negate: bool,
+) -> Result<(), ScriptError> {
+    if !exec_stack.iter().all(|b| *b) {
+        exec_stack.push(false);
+        return Ok(());
+    }
+
+    let condition = stack.pop().ok_or(ScriptError::UnbalancedConditional)?;
+    // policy in witness v0 scripts, consensus in tapscripts
+    if !(condition.is_empty() || condition == [1]) {
+        if sig_version == SigVersion::Tapscript {
+            return Err(ScriptError::TapscriptMinimalIf);
+        }
+        if sig_version == SigVersion::WitnessV0 && flags.contains(VerifyFlags::MINIMALIF) {
+            return Err(ScriptError::MinimalIf);
+        }
     }
+    exec_stack.push(cast_to_bool(&condition) != negate);
+    Ok(())
+}
+
+pub fn op_if(stack: &mut Vec<Vec<u8>>, exec_stack: &mut Vec<bool>, flags: VerifyFlags, sig_version: SigVersion) -> Result<(), ScriptError> {
+    push_condition(stack, exec_stack, flags, sig_version, false)
+}
+
+pub fn op_notif(stack: &mut Vec<Vec<u8>>, exec_stack: &mut Vec<bool>, flags: VerifyFlags, sig_version: SigVersion) -> Result<(), ScriptError> {
+    push_condition(stack, exec_stack, flags, sig_version, true)
+}
+
+pub fn op_else(exec_stack: &mut [bool]) -> Result<(), ScriptError> {
+    let last = exec_stack.last_mut().ok_or(ScriptError::UnbalancedConditional)?;
+    *last = !*last;
+    Ok(())
+}
+
+pub fn op_endif(exec_stack: &mut Vec<bool>) -> Result<(), ScriptError> {
+    exec_stack.pop().ok_or(ScriptError::UnbalancedConditional)?;
+    Ok(())
+}
+
+/// NOPs reserved for soft forks, DISCOURAGE_UPGRADABLE_NOPS makes using them an error
+pub fn op_nop(flags: VerifyFlags) -> Result<(), ScriptError> {
+    if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS) {
+        return Err(ScriptError::DiscourageUpgradableNops);
+    }
+    Ok(())
+}
+
+pub fn op_verify(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    if !cast_to_bool(&pop(stack)?) {
+        return Err(ScriptError::Verify);
+    }
+    Ok(())
+}
+
+pub fn op_return(_stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    Err(ScriptError::OpReturn)
+}
+
+
+// -- stack --
+
+pub fn op_toaltstack(stack: &mut Vec<Vec<u8>>, alt_stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    alt_stack.push(pop(stack)?);
+    Ok(())
+}
+
+pub fn op_fromaltstack(stack: &mut Vec<Vec<u8>>, alt_stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    stack.push(alt_stack.pop().ok_or(ScriptError::InvalidAltstackOperation)?);
+    Ok(())
+}
+
+pub fn op_2drop(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    require(stack, 2)?;
+    stack.truncate(stack.len() - 2);
+    Ok(())
+}
+
+pub fn op_2dup(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    require(stack, 2)?;
+    stack.extend_from_within((stack.len() - 2)..);
+    Ok(())
+}
+
+pub fn op_3dup(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    require(stack, 3)?;
+    stack.extend_from_within((stack.len() - 3)..);
+    Ok(())
+}
+
+pub fn op_2over(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    require(stack, 4)?;
+    let len = stack.len();
+    stack.extend_from_within((len - 4)..(len - 2));
+    Ok(())
+}
+
+pub fn op_2rot(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    require(stack, 6)?;
+    let len = stack.len();
+    let moved: Vec<Vec<u8>> = stack.drain((len - 6)..(len - 4)).collect();
+    stack.extend(moved);
+    Ok(())
+}
+
+pub fn op_2swap(stack: &mut [Vec<u8>]) -> Result<(), ScriptError> {
+    require(stack, 4)?;
+    let len = stack.len();
+    stack[(len - 4)..].rotate_left(2);
+    Ok(())
+}
+
+pub fn op_ifdup(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    require(stack, 1)?;
+    let top = stack[stack.len() - 1].clone();
+    if cast_to_bool(&top) {
+        stack.push(top);
+    }
+    Ok(())
+}
+
+pub fn op_depth(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    stack.push(encode_num(stack.len() as i64));
+    Ok(())
+}
+
+pub fn op_drop(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    pop(stack)?;
+    Ok(())
+}
+
+pub fn op_dup(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    require(stack, 1)?;
     stack.push(stack[stack.len() - 1].clone());
-    return true;
+    Ok(())
 }
 
-pub fn op_hash256(stack: &mut Vec<Vec<u8>>) -> bool {
-    if stack.len() < 1 {
-        return false;
+pub fn op_nip(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    require(stack, 2)?;
+    stack.remove(stack.len() - 2);
+    Ok(())
+}
+
+pub fn op_over(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    require(stack, 2)?;
+    stack.push(stack[stack.len() - 2].clone());
+    Ok(())
+}
+
+pub fn op_pick(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    let n = pop_num(stack, flags)?;
+    if n < 0 || n as usize >= stack.len() {
+        return Err(ScriptError::InvalidStackOperation);
+    }
+    stack.push(stack[stack.len() - 1 - n as usize].clone());
+    Ok(())
+}
+
+pub fn op_roll(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    let n = pop_num(stack, flags)?;
+    if n < 0 || n as usize >= stack.len() {
+        return Err(ScriptError::InvalidStackOperation);
+    }
+    let element = stack.remove(stack.len() - 1 - n as usize);
+    stack.push(element);
+    Ok(())
+}
+
+pub fn op_rot(stack: &mut [Vec<u8>]) -> Result<(), ScriptError> {
+    require(stack, 3)?;
+    let len = stack.len();
+    stack[(len - 3)..].rotate_left(1);
+    Ok(())
+}
+
+pub fn op_swap(stack: &mut [Vec<u8>]) -> Result<(), ScriptError> {
+    require(stack, 2)?;
+    let len = stack.len();
+    stack.swap(len - 1, len - 2);
+    Ok(())
+}
+
+pub fn op_tuck(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    require(stack, 2)?;
+    let top = stack[stack.len() - 1].clone();
+    stack.insert(stack.len() - 2, top);
+    Ok(())
+}
+
+
+// -- splice --
+
+pub fn op_size(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    require(stack, 1)?;
+    stack.push(encode_num(stack[stack.len() - 1].len() as i64));
+    Ok(())
+}
+
+
+// -- bitwise logic --
+
+pub fn op_equal(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    let a = pop(stack)?;
+    let b = pop(stack)?;
+    stack.push(encode_bool(a == b));
+    Ok(())
+}
+
+pub fn op_equalverify(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    op_equal(stack)?;
+    if !cast_to_bool(&pop(stack)?) {
+        return Err(ScriptError::EqualVerify);
+    }
+    Ok(())
+}
+
+
+// -- arithmetic --
+
+fn unary_num(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags, f: impl Fn(i64) -> i64) -> Result<(), ScriptError> {
+    let a = pop_num(stack, flags)?;
+    stack.push(encode_num(f(a)));
+    Ok(())
+}
+
+/// pops b then a and pushes f(a, b)
+fn binary_num(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags, f: impl Fn(i64, i64) -> i64) -> Result<(), ScriptError> {
+    let b = pop_num(stack, flags)?;
+    let a = pop_num(stack, flags)?;
+    stack.push(encode_num(f(a, b)));
+    Ok(())
+}
+
+pub fn op_1add(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    unary_num(stack, flags, |a| a + 1)
+}
+
+pub fn op_1sub(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    unary_num(stack, flags, |a| a - 1)
+}
+
+pub fn op_negate(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    unary_num(stack, flags, |a| -a)
+}
+
+pub fn op_abs(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    unary_num(stack, flags, |a| a.abs())
+}
+
+pub fn op_not(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    unary_num(stack, flags, |a| (a == 0) as i64)
+}
+
+pub fn op_0notequal(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    unary_num(stack, flags, |a| (a != 0) as i64)
+}
+
+pub fn op_add(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    binary_num(stack, flags, |a, b| a + b)
+}
+
+pub fn op_sub(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    binary_num(stack, flags, |a, b| a - b)
+}
+
+pub fn op_booland(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    binary_num(stack, flags, |a, b| (a != 0 && b != 0) as i64)
+}
+
+pub fn op_boolor(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    binary_num(stack, flags, |a, b| (a != 0 || b != 0) as i64)
+}
+
+pub fn op_numequal(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    binary_num(stack, flags, |a, b| (a == b) as i64)
+}
+
+pub fn op_numequalverify(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    op_numequal(stack, flags)?;
+    if !cast_to_bool(&pop(stack)?) {
+        return Err(ScriptError::NumEqualVerify);
     }
-    let element = stack.pop().unwrap();
-    stack.push(hash256(&element));
-    return true;
+    Ok(())
+}
+
+pub fn op_numnotequal(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    binary_num(stack, flags, |a, b| (a != b) as i64)
+}
+
+pub fn op_lessthan(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    binary_num(stack, flags, |a, b| (a < b) as i64)
+}
+
+pub fn op_greaterthan(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    binary_num(stack, flags, |a, b| (a > b) as i64)
+}
+
+pub fn op_lessthanorequal(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    binary_num(stack, flags, |a, b| (a <= b) as i64)
+}
+
+pub fn op_greaterthanorequal(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    binary_num(stack, flags, |a, b| (a >= b) as i64)
+}
+
+pub fn op_min(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    binary_num(stack, flags, |a, b| a.min(b))
+}
+
+pub fn op_max(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    binary_num(stack, flags, |a, b| a.max(b))
 }
 
-pub fn op_hash160(stack: &mut Vec<Vec<u8>>) -> bool {
-    if stack.len() < 1 {
+/// x min max -> whether min <= x < max
+pub fn op_within(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<(), ScriptError> {
+    let max = pop_num(stack, flags)?;
+    let min = pop_num(stack, flags)?;
+    let x = pop_num(stack, flags)?;
+    stack.push(encode_bool(min <= x && x < max));
+    Ok(())
+}
+
+
+// -- crypto --
+
+fn hash_top(stack: &mut Vec<Vec<u8>>, f: fn(&[u8]) -> Vec<u8>) -> Result<(), ScriptError> {
+    let element = pop(stack)?;
+    stack.push(f(&element));
+    Ok(())
+}
+
+pub fn op_ripemd160(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    hash_top(stack, ripemd160)
+}
+
+pub fn op_sha1(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    hash_top(stack, sha1)
+}
+
+pub fn op_sha256(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    hash_top(stack, sha256)
+}
+
+pub fn op_hash256(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    hash_top(stack, hash256)
+}
+
+pub fn op_hash160(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    hash_top(stack, hash160)
+}
+
+
+
+// -- signatures --
+
+/// BIP66 strict DER, `sig` includes the sighash byte
+fn is_valid_signature_encoding(sig: &[u8]) -> bool {
+    // 0x30 [total-length] 0x02 [R-length] [R] 0x02 [S-length] [S] [sighash]
+    if sig.len() < 9 || sig.len() > 73 || sig[0] != 0x30 || sig[1] as usize != sig.len() - 3 {
+        return false;
+    }
+    let len_r = sig[3] as usize;
+    if 5 + len_r >= sig.len() {
+        return false;
+    }
+    let len_s = sig[5 + len_r] as usize;
+    if len_r + len_s + 7 != sig.len() {
+        return false;
+    }
+
+    // both integers positive and without padding, unless a zero byte is needed to stay positive
+    if sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 || (len_r > 1 && sig[4] == 0 && sig[5] & 0x80 == 0) {
         return false;
     }
-    let element = stack.pop().unwrap();
-    stack.push(hash160(&element));
-    return true;
+    let s = len_r + 6;
+    if sig[s - 2] != 0x02 || len_s == 0 || sig[s] & 0x80 != 0 || (len_s > 1 && sig[s] == 0 && sig[s + 1] & 0x80 == 0) {
+        return false;
+    }
+
+    true
+}
+
+/// s at most n/2, the other half of the curve makes the same signature malleable
+fn is_low_s(der: &[u8]) -> bool {
+    let n = BigInt::parse_bytes(b"fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141", 16).unwrap();
+    match Signature::parse(der) {
+        Some(signature) => signature.s <= n / 2,
+        None => false,
+    }
+}
+
+/// the signature rules the flags ask for, an empty signature always passes as a compact false
+fn check_signature_encoding(sig: &[u8], flags: VerifyFlags) -> Result<(), ScriptError> {
+    if sig.is_empty() {
+        return Ok(());
+    }
+    if (flags.contains(VerifyFlags::DERSIG) || flags.contains(VerifyFlags::LOW_S) || flags.contains(VerifyFlags::STRICTENC))
+        && !is_valid_signature_encoding(sig) {
+        return Err(ScriptError::SigDer);
+    }
+    if flags.contains(VerifyFlags::LOW_S) && !is_low_s(&sig[..(sig.len() - 1)]) {
+        return Err(ScriptError::SigHighS);
+    }
+    let base_type = sig[sig.len() - 1] & !SIGHASH_ANYONECANPAY;
+    if flags.contains(VerifyFlags::STRICTENC) && !(SIGHASH_ALL..=SIGHASH_SINGLE).contains(&base_type) {
+        return Err(ScriptError::SigHashType);
+    }
+
+    Ok(())
+}
+
+/// STRICTENC only takes 33 bytes compressed or 65 bytes uncompressed keys, WITNESS_PUBKEYTYPE only
+/// compressed ones in witness scripts
+fn check_pub_key_encoding(pub_key: &[u8], flags: VerifyFlags, sig_version: SigVersion) -> Result<(), ScriptError> {
+    let compressed = matches!((pub_key.first(), pub_key.len()), (Some(2), 33) | (Some(3), 33));
+    let well_formed = compressed || matches!((pub_key.first(), pub_key.len()), (Some(4), 65));
+    if flags.contains(VerifyFlags::STRICTENC) && !well_formed {
+        return Err(ScriptError::PubKeyType);
+    }
+    if sig_version == SigVersion::WitnessV0 && flags.contains(VerifyFlags::WITNESS_PUBKEYTYPE) && !compressed {
+        return Err(ScriptError::WitnessPubKeyType);
+    }
+
+    Ok(())
+}
+
+/// a SEC public key, or a hybrid one (0x06 or 0x07 and the parity of y, then x and y) that
+/// reads as uncompressed where STRICTENC doesn't reject it, None if it isn't on the curve
+fn parse_pub_key(pub_key: &[u8]) -> Option<S256Point> {
+    let mut sec = pub_key.to_owned();
+    if sec.len() == 65 && (sec[0] == 6 || sec[0] == 7) {
+        if sec[0] & 1 != sec[64] & 1 {
+            return None;
+        }
+        sec[0] = 4;
+    }
+
+    if !S256Point::is_valid_sec(&sec) {
+        return None;
+    }
+    Some(S256Point::parse(sec))
+}
+
+/// check a DER signature with a trailing sighash byte against a SEC public key, anything malformed
+/// is just an invalid signature unless the flags make it an error
+fn check_sig(
+    sig: &[u8],
+    pub_key: &[u8],
+    checker: &dyn SignatureChecker,
+    script_code: &Script,
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+) -> Result<bool, ScriptError> {
+    check_signature_encoding(sig, flags)?;
+    check_pub_key_encoding(pub_key, flags, sig_version)?;
+
+    let (sighash_type, der) = match sig.split_last() {
+        Some((sighash_type, der)) => (*sighash_type, der),
+        None => return Ok(false),
+    };
+    let point = match parse_pub_key(pub_key) {
+        Some(point) => point,
+        None => return Ok(false),
+    };
+    // the flags that need strict DER have checked it already
+    let signature = match Signature::parse_lax(der) {
+        Some(signature) => signature,
+        None => return Ok(false),
+    };
+
+    let z = checker.sig_hash(script_code, sighash_type, sig_version);
+    Ok(signature.is_valid(&z, &point))
+}
+
+/// a signature can't sign itself, so legacy scripts drop every push of the signatures being
+/// checked from the script they commit to, segwit scripts are left as they are
+fn legacy_script_code(
+    script_code: &Script,
+    sigs: &[Vec<u8>],
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+) -> Result<Script, ScriptError> {
+    let mut script_code = script_code.clone();
+    if sig_version != SigVersion::Base {
+        return Ok(script_code);
+    }
+
+    for sig in sigs {
+        if script_code.find_and_delete(sig) > 0 && flags.contains(VerifyFlags::CONST_SCRIPTCODE) {
+            return Err(ScriptError::SigFindAndDelete);
+        }
+    }
+    Ok(script_code)
+}
+
+pub fn op_checksig(
+    stack: &mut Vec<Vec<u8>>,
+    checker: &dyn SignatureChecker,
+    script_code: &Script,
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+) -> Result<(), ScriptError> {
+    let pub_key = pop(stack)?;
+    let sig = pop(stack)?;
+    let script_code = legacy_script_code(script_code, std::slice::from_ref(&sig), flags, sig_version)?;
+    let success = check_sig(&sig, &pub_key, checker, &script_code, flags, sig_version)?;
+    if !success && flags.contains(VerifyFlags::NULLFAIL) && !sig.is_empty() {
+        return Err(ScriptError::NullFail);
+    }
+    stack.push(encode_bool(success));
+    Ok(())
+}
+
+pub fn op_checksigverify(
+    stack: &mut Vec<Vec<u8>>,
+    checker: &dyn SignatureChecker,
+    script_code: &Script,
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+) -> Result<(), ScriptError> {
+    op_checksig(stack, checker, script_code, flags, sig_version)?;
+    if !cast_to_bool(&pop(stack)?) {
+        return Err(ScriptError::CheckSigVerify);
+    }
+    Ok(())
+}
+
+/// CHECKMULTISIG takes at most this many public keys
+pub const MAX_PUBKEYS_PER_MULTISIG: i64 = 20;
+
+/// <dummy> <sig>... m <pubkey>... n, each signature must match a key after the key the previous
+/// signature matched, every key counts against the script's op limit
+pub fn op_checkmultisig(
+    stack: &mut Vec<Vec<u8>>,
+    checker: &dyn SignatureChecker,
+    script_code: &Script,
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+    op_count: &mut usize,
+) -> Result<(), ScriptError> {
+    let n = pop_num(stack, flags)?;
+    if !(0..=MAX_PUBKEYS_PER_MULTISIG).contains(&n) {
+        return Err(ScriptError::PubKeyCount);
+    }
+    *op_count += n as usize;
+    if *op_count > MAX_OPS_PER_SCRIPT {
+        return Err(ScriptError::OpCount);
+    }
+    require(stack, n as usize)?;
+    let pub_keys = stack.split_off(stack.len() - n as usize);
+
+    let m = pop_num(stack, flags)?;
+    if m < 0 || m > n {
+        return Err(ScriptError::SigCount);
+    }
+    require(stack, m as usize)?;
+    let sigs = stack.split_off(stack.len() - m as usize);
+
+    // an off-by-one in the original implementation pops one more element
+    let dummy = pop(stack)?;
+    if flags.contains(VerifyFlags::NULLDUMMY) && !dummy.is_empty() {
+        return Err(ScriptError::SigNullDummy);
+    }
+
+    let script_code = legacy_script_code(script_code, &sigs, flags, sig_version)?;
+
+    // walk both lists from the top of the stack, giving up once fewer keys than signatures are left
+    let (mut isig, mut ikey) = (sigs.len(), pub_keys.len());
+    let mut success = true;
+    while success && isig > 0 {
+        if check_sig(&sigs[isig - 1], &pub_keys[ikey - 1], checker, &script_code, flags, sig_version)? {
+            isig -= 1;
+        }
+        ikey -= 1;
+        if isig > ikey {
+            success = false;
+        }
+    }
+
+    if !success && flags.contains(VerifyFlags::NULLFAIL) && sigs.iter().any(|sig| !sig.is_empty()) {
+        return Err(ScriptError::NullFail);
+    }
+
+    stack.push(encode_bool(success));
+    Ok(())
+}
+
+pub fn op_checkmultisigverify(
+    stack: &mut Vec<Vec<u8>>,
+    checker: &dyn SignatureChecker,
+    script_code: &Script,
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+    op_count: &mut usize,
+) -> Result<(), ScriptError> {
+    op_checkmultisig(stack, checker, script_code, flags, sig_version, op_count)?;
+    if !cast_to_bool(&pop(stack)?) {
+        return Err(ScriptError::CheckMultiSigVerify);
+    }
+    Ok(())
+}
+
+// -- tapscript signatures --
+
+/// BIP342 signature budget, the witness size plus this
+pub const VALIDATION_WEIGHT_OFFSET: i64 = 50;
+/// what every checked signature takes from the budget
+pub const VALIDATION_WEIGHT_PER_SIGOP_PASSED: i64 = 50;
+
+/// BIP340 signature with an optional sighash byte against a 32 bytes x-only key, the key path
+/// spend check and the tapscript signature check
+pub fn check_schnorr_sig(sig: &[u8], pub_key: &[u8], checker: &dyn SignatureChecker, exec_data: &ExecData) -> Result<(), ScriptError> {
+    let (sig, sighash_type) = match sig.len() {
+        64 => (sig, SIGHASH_DEFAULT),
+        // the default type has to be left out
+        65 if sig[64] != SIGHASH_DEFAULT => (&sig[..64], sig[64]),
+        65 => return Err(ScriptError::SchnorrSigHashType),
+        _ => return Err(ScriptError::SchnorrSigSize),
+    };
+    if !matches!(sighash_type & !SIGHASH_ANYONECANPAY, SIGHASH_DEFAULT..=SIGHASH_SINGLE) || sighash_type == SIGHASH_ANYONECANPAY {
+        return Err(ScriptError::SchnorrSigHashType);
+    }
+    let z = checker.tap_sig_hash(sighash_type, exec_data).ok_or(ScriptError::SchnorrSigHashType)?;
+
+    match SchnorrSignature::parse(sig) {
+        Some(signature) if signature.is_valid(&int_to_big_endian(&z, 32), pub_key) => Ok(()),
+        _ => Err(ScriptError::SchnorrSig),
+    }
+}
+
+/// the tapscript signature rules, an empty signature is false, any other must be valid, and keys
+/// other than 32 bytes are reserved for future versions
+fn check_tapscript_sig(
+    sig: &[u8],
+    pub_key: &[u8],
+    checker: &dyn SignatureChecker,
+    exec_data: &mut ExecData,
+    flags: VerifyFlags,
+) -> Result<bool, ScriptError> {
+    let success = !sig.is_empty();
+    if success {
+        exec_data.validation_weight_left -= VALIDATION_WEIGHT_PER_SIGOP_PASSED;
+        if exec_data.validation_weight_left < 0 {
+            return Err(ScriptError::TapscriptValidationWeight);
+        }
+    }
+
+    match pub_key.len() {
+        0 => return Err(ScriptError::PubKeyType),
+        32 if success => check_schnorr_sig(sig, pub_key, checker, exec_data)?,
+        32 => {},
+        _ if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_PUBKEYTYPE) => {
+            return Err(ScriptError::DiscourageUpgradablePubKeyType);
+        },
+        _ => {},
+    }
+
+    Ok(success)
+}
+
+pub fn op_checksig_tapscript(
+    stack: &mut Vec<Vec<u8>>,
+    checker: &dyn SignatureChecker,
+    exec_data: &mut ExecData,
+    flags: VerifyFlags,
+) -> Result<(), ScriptError> {
+    let pub_key = pop(stack)?;
+    let sig = pop(stack)?;
+    let success = check_tapscript_sig(&sig, &pub_key, checker, exec_data, flags)?;
+    stack.push(encode_bool(success));
+    Ok(())
+}
+
+pub fn op_checksigverify_tapscript(
+    stack: &mut Vec<Vec<u8>>,
+    checker: &dyn SignatureChecker,
+    exec_data: &mut ExecData,
+    flags: VerifyFlags,
+) -> Result<(), ScriptError> {
+    op_checksig_tapscript(stack, checker, exec_data, flags)?;
+    if !cast_to_bool(&pop(stack)?) {
+        return Err(ScriptError::CheckSigVerify);
+    }
+    Ok(())
+}
+
+/// sig n pubkey -> n + 1 if the signature is valid, n if it's empty, replaces CHECKMULTISIG
+pub fn op_checksigadd(
+    stack: &mut Vec<Vec<u8>>,
+    checker: &dyn SignatureChecker,
+    exec_data: &mut ExecData,
+    flags: VerifyFlags,
+) -> Result<(), ScriptError> {
+    require(stack, 3)?;
+    let pub_key = pop(stack)?;
+    let n = pop_num(stack, flags)?;
+    let sig = pop(stack)?;
+    let success = check_tapscript_sig(&sig, &pub_key, checker, exec_data, flags)?;
+    stack.push(encode_num(n + success as i64));
+    Ok(())
+}
+
+/// BIP342, opcodes that make a tapscript succeed without running it, so soft forks can give
+/// them any meaning
+pub fn is_op_success(op: u8) -> bool {
+    matches!(op, 80 | 98 | 126..=129 | 131..=134 | 137..=138 | 141..=142 | 149..=153 | 187..=254)
+}
+
+
+// -- locktime --
+
+/// locktime operands may be 5 bytes to reach past 2^31
+pub const MAX_LOCKTIME_SIZE: usize = 5;
+
+/// BIP65, fails unless the transaction locktime is at least the top element, leaves the stack as is
+pub fn op_checklocktimeverify(stack: &mut [Vec<u8>], checker: &dyn SignatureChecker, flags: VerifyFlags) -> Result<(), ScriptError> {
+    if !flags.contains(VerifyFlags::CHECKLOCKTIMEVERIFY) {
+        return op_nop(flags);
+    }
+
+    require(stack, 1)?;
+    let locktime = decode_num(&stack[stack.len() - 1], MAX_LOCKTIME_SIZE, flags.contains(VerifyFlags::MINIMALDATA))?;
+    if locktime < 0 {
+        return Err(ScriptError::NegativeLocktime);
+    }
+    if !checker.check_locktime(locktime) {
+        return Err(ScriptError::UnsatisfiedLocktime);
+    }
+    Ok(())
+}
+
+/// BIP112, fails unless the input's sequence is a relative locktime of at least the top element
+pub fn op_checksequenceverify(stack: &mut [Vec<u8>], checker: &dyn SignatureChecker, flags: VerifyFlags) -> Result<(), ScriptError> {
+    if !flags.contains(VerifyFlags::CHECKSEQUENCEVERIFY) {
+        return op_nop(flags);
+    }
+
+    require(stack, 1)?;
+    let sequence = decode_num(&stack[stack.len() - 1], MAX_LOCKTIME_SIZE, flags.contains(VerifyFlags::MINIMALDATA))?;
+    if sequence < 0 {
+        return Err(ScriptError::NegativeLocktime);
+    }
+    // with the disable flag the operand is reserved for future soft forks
+    if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG as i64 != 0 {
+        return Ok(());
+    }
+    if !checker.check_sequence(sequence) {
+        return Err(ScriptError::UnsatisfiedLocktime);
+    }
+    Ok(())
+}
+
+/// run an opcode that only touches the main stack, None if `op` needs more context than that
+pub fn execute_stack_op(op: u8, stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Option<Result<(), ScriptError>> {
+    let result = match op {
+        OP_0 => { stack.push(vec![]); Ok(()) },
+        OP_1NEGATE => op_push_num(stack, -1),
+        OP_1..=OP_16 => op_push_num(stack, (op - OP_1 + 1) as i64),
+        OP_NOP => Ok(()),
+        OP_NOP1 | OP_NOP4..=OP_NOP10 => op_nop(flags),
+        OP_VERIFY => op_verify(stack),
+        OP_RETURN => op_return(stack),
+        OP_2DROP => op_2drop(stack),
+        OP_2DUP => op_2dup(stack),
+        OP_3DUP => op_3dup(stack),
+        OP_2OVER => op_2over(stack),
+        OP_2ROT => op_2rot(stack),
+        OP_2SWAP => op_2swap(stack),
+        OP_IFDUP => op_ifdup(stack),
+        OP_DEPTH => op_depth(stack),
+        OP_DROP => op_drop(stack),
+        OP_DUP => op_dup(stack),
+        OP_NIP => op_nip(stack),
+        OP_OVER => op_over(stack),
+        OP_PICK => op_pick(stack, flags),
+        OP_ROLL => op_roll(stack, flags),
+        OP_ROT => op_rot(stack),
+        OP_SWAP => op_swap(stack),
+        OP_TUCK => op_tuck(stack),
+        OP_SIZE => op_size(stack),
+        OP_EQUAL => op_equal(stack),
+        OP_EQUALVERIFY => op_equalverify(stack),
+        OP_1ADD => op_1add(stack, flags),
+        OP_1SUB => op_1sub(stack, flags),
+        OP_NEGATE => op_negate(stack, flags),
+        OP_ABS => op_abs(stack, flags),
+        OP_NOT => op_not(stack, flags),
+        OP_0NOTEQUAL => op_0notequal(stack, flags),
+        OP_ADD => op_add(stack, flags),
+        OP_SUB => op_sub(stack, flags),
+        OP_BOOLAND => op_booland(stack, flags),
+        OP_BOOLOR => op_boolor(stack, flags),
+        OP_NUMEQUAL => op_numequal(stack, flags),
+        OP_NUMEQUALVERIFY => op_numequalverify(stack, flags),
+        OP_NUMNOTEQUAL => op_numnotequal(stack, flags),
+        OP_LESSTHAN => op_lessthan(stack, flags),
+        OP_GREATERTHAN => op_greaterthan(stack, flags),
+        OP_LESSTHANOREQUAL => op_lessthanorequal(stack, flags),
+        OP_GREATERTHANOREQUAL => op_greaterthanorequal(stack, flags),
+        OP_MIN => op_min(stack, flags),
+        OP_MAX => op_max(stack, flags),
+        OP_WITHIN => op_within(stack, flags),
+        OP_RIPEMD160 => op_ripemd160(stack),
+        OP_SHA1 => op_sha1(stack),
+        OP_SHA256 => op_sha256(stack),
+        OP_HASH160 => op_hash160(stack),
+        OP_HASH256 => op_hash256(stack),
+        _ => return None,
+    };
+
+    Some(result)
+}
+
+/// opcodes that fail a script even when they appear in an unexecuted branch (CVE-2010-5137)
+pub fn is_disabled(op: u8) -> bool {
+    matches!(
+        op,
+        OP_CAT | OP_SUBSTR | OP_LEFT | OP_RIGHT | OP_INVERT | OP_AND | OP_OR | OP_XOR | OP_2MUL | OP_2DIV |
+        OP_MUL | OP_DIV | OP_MOD | OP_LSHIFT | OP_RSHIFT
+    )
+}
+
+
+#[cfg(test)]
+mod tests_op {
+    use crate::interpreter::{ScriptError, VerifyFlags};
+
+    use super::{encode_num, decode_num, op_add, op_within, op_numequalverify, op_1sub, MAX_NUM_SIZE};
+
+    #[test]
+    fn test_num() {
+        let cases: [(i64, &str); 9] = [
+            (0, ""), (1, "01"), (-1, "81"), (127, "7f"), (128, "8000"), (-128, "8080"),
+            (255, "ff00"), (-255, "ff80"), (0x7fffffff, "ffffff7f"),
+        ];
+        for (num, encoded) in cases {
+            assert_eq!(encoded, hex::encode(encode_num(num)));
+            assert_eq!(Ok(num), decode_num(&encode_num(num), MAX_NUM_SIZE, true));
+        }
+
+        assert_eq!(Err(ScriptError::NumOverflow(5)), decode_num(&[0, 0, 0, 0x80, 0], MAX_NUM_SIZE, false));
+        assert_eq!(Ok(-0x7fffffff), decode_num(&[0xff, 0xff, 0xff, 0xff], MAX_NUM_SIZE, false));
+
+        // padded encodings decode to the same value but aren't minimal
+        assert_eq!(Ok(1), decode_num(&[1, 0], MAX_NUM_SIZE, false));
+        assert_eq!(Err(ScriptError::NonMinimalNum), decode_num(&[1, 0], MAX_NUM_SIZE, true));
+        assert_eq!(Err(ScriptError::NonMinimalNum), decode_num(&[0x80], MAX_NUM_SIZE, true));
+        assert_eq!(Err(ScriptError::NonMinimalNum), decode_num(&[0], MAX_NUM_SIZE, true));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        // results may exceed 4 bytes
+        let mut stack = vec![encode_num(0x7fffffff), encode_num(0x7fffffff)];
+        op_add(&mut stack, VerifyFlags::NONE).unwrap();
+        assert_eq!(vec![encode_num(0xfffffffe)], stack);
+
+        // but can't be used as operands again
+        stack.push(encode_num(1));
+        assert_eq!(Err(ScriptError::NumOverflow(5)), op_add(&mut stack, VerifyFlags::NONE));
+
+        let mut stack = vec![encode_num(0)];
+        op_1sub(&mut stack, VerifyFlags::NONE).unwrap();
+        assert_eq!(vec![encode_num(-1)], stack);
+
+        let mut stack = vec![encode_num(6), encode_num(2), encode_num(6)];
+        op_within(&mut stack, VerifyFlags::NONE).unwrap();
+        assert_eq!(vec![encode_num(0)], stack);
+
+        let mut stack = vec![encode_num(3), encode_num(4)];
+        assert_eq!(Err(ScriptError::NumEqualVerify), op_numequalverify(&mut stack, VerifyFlags::NONE));
+
+        let mut stack = vec![encode_num(3)];
+        assert_eq!(Err(ScriptError::InvalidStackOperation), op_add(&mut stack, VerifyFlags::NONE));
+    }
 }
@@ -5,11 +5,18 @@ use num_bigint::BigInt;
 use sha2::{Sha256, Digest};
 use hex::ToHex;
 
-use crate::{signature::Signature, s256point::S256Point, s256field::S256Field, utils::encode_base58_checksum};
+use crate::{
+    network::Network,
+    signature::{Signature, SchnorrSignature},
+    s256point::S256Point,
+    s256field::S256Field,
+    utils::{encode_base58_checksum, tagged_hash},
+};
 
 
 type HmacSha256 = Hmac<Sha256>;
 
+#[derive(Clone)]
 pub struct PrivateKey {
     pub secret: BigInt,
 }
@@ -161,7 +168,30 @@ impl PrivateKey {
         }
     }
 
-    pub fn wif(&self, compressed: bool, testnet: bool) -> Vec<u8> {
+    /// BIP340 signature of a 32 bytes message, `aux_rand` is mixed into the nonce
+    pub fn sign_schnorr(&self, msg: &[u8], aux_rand: &[u8]) -> SchnorrSignature {
+        let n = BigInt::parse_bytes(b"fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141", 16).unwrap();
+        let pub_key = self.get_pub_key();
+        // sign for the even y point with the x-only key
+        let d = if pub_key.has_even_y() { self.secret.clone() } else { &n - &self.secret };
+        let d_bytes = PrivateKey::new(d.clone()).secret_bytes();
+
+        let t: Vec<u8> = d_bytes.iter().zip(tagged_hash("BIP0340/aux", aux_rand)).map(|(a, b)| a ^ b).collect();
+        let mut nonce_data = t;
+        nonce_data.extend_from_slice(&pub_key.xonly());
+        nonce_data.extend_from_slice(msg);
+        let k0 = BigInt::from_bytes_be(num_bigint::Sign::Plus, &tagged_hash("BIP0340/nonce", &nonce_data)) % &n;
+
+        let r_point = PrivateKey::new(k0.clone()).get_pub_key();
+        let k = if r_point.has_even_y() { k0 } else { &n - k0 };
+        let r = r_point.xonly();
+        let e = SchnorrSignature::challenge(&r, &pub_key.xonly(), msg);
+
+        SchnorrSignature::new(BigInt::from_bytes_be(num_bigint::Sign::Plus, &r), (k + e * d) % n)
+    }
+
+    /// secret as 32 bytes big-endian
+    pub fn secret_bytes(&self) -> Vec<u8> {
         let mut secret_bytes = self.secret.to_bytes_be().1;
         if secret_bytes.len() < 32 {
             for _ in 0..(32 - secret_bytes.len()) {
@@ -169,14 +199,13 @@ impl PrivateKey {
             }
         }
 
-        let prefix;
-        if testnet {
-            prefix = b'\xef';
-        } else {
-            prefix = b'\x80';
-        }
+        secret_bytes
+    }
+
+    pub fn wif(&self, compressed: bool, network: Network) -> Vec<u8> {
+        let secret_bytes = self.secret_bytes();
 
-        let mut s: Vec<u8> = vec![prefix];
+        let mut s: Vec<u8> = vec![network.wif_prefix()];
         s.extend_from_slice(&secret_bytes);
         if compressed {
             s.push(b'\x01');
@@ -4,7 +4,7 @@ use num_bigint::BigInt;
 
 use hex::ToHex;
 
-use crate::{s256field::S256Field, utils::{hash160, encode_base58_checksum, u8_slice_to_string}};
+use crate::{network::Network, s256field::S256Field, utils::{hash160, tagged_hash, encode_base58_checksum, u8_slice_to_string}};
 
 
 #[derive(Clone)]
@@ -89,6 +89,30 @@ impl S256Point {
 }
 
 impl S256Point {
+    /// whether `sec_bin` is a SEC encoded point on the curve, `parse` panics on anything else
+    pub fn is_valid_sec(sec_bin: &[u8]) -> bool {
+        match (sec_bin.first(), sec_bin.len()) {
+            (Some(2), 33) | (Some(3), 33) | (Some(4), 65) => {},
+            _ => return false,
+        }
+
+        let t = BigInt::from(2i32);
+        let p = t.clone().pow(256u32) - t.pow(32u32) - BigInt::from(977i32);
+        let x = BigInt::from_bytes_be(num_bigint::Sign::Plus, &sec_bin[1..33]);
+        if x >= p {
+            return false;
+        }
+        let alpha = (x.modpow(&BigInt::from(3i32), &p) + BigInt::from(7i32)) % &p;
+
+        let y = if sec_bin[0] == 4 {
+            BigInt::from_bytes_be(num_bigint::Sign::Plus, &sec_bin[33..65])
+        } else {
+            alpha.modpow(&((p.clone() + BigInt::from(1i32)) / BigInt::from(4i32)), &p)
+        };
+
+        y < p && y.modpow(&BigInt::from(2i32), &p) == alpha
+    }
+
     pub fn multi(&self, coefficient: BigInt) -> Self {
         // n is specified for s256
         let n = BigInt::parse_bytes(b"fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141", 16).unwrap();
@@ -150,22 +174,51 @@ impl S256Point {
         h
     }
 
-    pub fn address(&self, compressed: bool, testnet: bool) -> Vec<u8> {
-        let h160 = self.hash160(compressed);
-        let prefix;
-        if testnet {
-            prefix = b'\x6f';
-        } else {
-            prefix = b'\x00';
+    /// 32 bytes x coordinate, the BIP340 x-only public key
+    pub fn xonly(&self) -> Vec<u8> {
+        self.sec(true)[1..].to_owned()
+    }
+
+    pub fn has_even_y(&self) -> bool {
+        self.y.as_ref().unwrap().num.clone() % BigInt::from(2i32) == BigInt::from(0i32)
+    }
+
+    /// the point with even y for a 32 bytes x-only key
+    pub fn lift_x(x: &[u8]) -> Self {
+        let mut sec_bin = vec![2u8];
+        sec_bin.extend_from_slice(x);
+        Self::parse(sec_bin)
+    }
+
+    /// BIP341 output key Q = P + tG where t = hashTapTweak(P || merkle_root) and P has even y
+    pub fn tap_tweak(&self, merkle_root: Option<&[u8]>) -> Self {
+        let internal = Self::lift_x(&self.xonly());
+        let mut msg = internal.xonly();
+        if let Some(root) = merkle_root {
+            msg.extend_from_slice(root);
         }
+        let t = BigInt::from_bytes_be(num_bigint::Sign::Plus, &tagged_hash("TapTweak", &msg));
 
-        let mut s: Vec<u8> = vec![prefix];
+        generator().multi(t) + internal
+    }
+
+    pub fn address(&self, compressed: bool, network: Network) -> Vec<u8> {
+        let h160 = self.hash160(compressed);
+
+        let mut s: Vec<u8> = vec![network.p2pkh_prefix()];
         s.extend_from_slice(&h160);
 
         encode_base58_checksum(&s)
     }
 }
 
+pub fn generator() -> S256Point {
+    let gx = BigInt::parse_bytes(b"79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798", 16).unwrap();
+    let gy = BigInt::parse_bytes(b"483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8", 16).unwrap();
+
+    S256Point::new(Some(Rc::new(S256Field::new(gx))), Some(Rc::new(S256Field::new(gy))))
+}
+
 impl PartialEq for S256Point {
     fn eq(&self, other: &Self) -> bool {
         self.x == other.x && self.y == other.y && self.a == other.a && self.b == other.b
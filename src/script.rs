@@ -1,17 +1,107 @@
-use std::fmt::Display;
+use std::{fmt::Display, ops::Add};
 
 use log::info;
 use num_bigint::BigInt;
 use num_traits::ToPrimitive;
 use hex::ToHex;
 
-use crate::utils::{read_varint, little_endian_to_int, int_to_little_endian, encode_varint};
+use crate::{
+    op::*,
+    interpreter::{Interpreter, ScriptError, SignatureChecker, VerifyFlags},
+    utils::{read_varint, little_endian_to_int, int_to_little_endian, encode_varint},
+};
 
 
 #[derive(Clone)]
 pub enum ScriptCmd {
     OpCode(u8),
+    /// data push, serialized with the shortest push opcode for its length
     Cmd(Vec<u8>),
+    /// data pushed with a longer OP_PUSHDATA1/2/4 than needed, kept as is so old scripts
+    /// reserialize byte for byte
+    PushData(u8, Vec<u8>),
+    /// a push running past the end of the script and every byte after it, kept as is so the
+    /// script reserializes byte for byte, running it fails with BadOpcode
+    Invalid(Vec<u8>),
+}
+
+impl ScriptCmd {
+    /// the pushed data, None for opcodes
+    pub fn data(&self) -> Option<&[u8]> {
+        match self {
+            ScriptCmd::Cmd(data) | ScriptCmd::PushData(_, data) => Some(data),
+            ScriptCmd::OpCode(_) | ScriptCmd::Invalid(_) => None,
+        }
+    }
+
+    /// MINIMALDATA: pushes must use OP_0, OP_1NEGATE and OP_1 to OP_16 where they can, and else
+    /// the shortest push opcode
+    pub fn is_minimal_push(&self) -> bool {
+        match self {
+            ScriptCmd::OpCode(_) => true,
+            ScriptCmd::Cmd(data) => !matches!(data[..], [1..=16] | [0x81]),
+            ScriptCmd::PushData(_, _) | ScriptCmd::Invalid(_) => false,
+        }
+    }
+
+    /// the command's bytes in a serialized script
+    pub fn serialize(&self) -> Vec<u8> {
+        let (op_code, data) = match self {
+            ScriptCmd::OpCode(op_code) => return vec![*op_code],
+            ScriptCmd::Invalid(bytes) => return bytes.clone(),
+            ScriptCmd::Cmd(c) => (push_opcode(c.len()), c),
+            ScriptCmd::PushData(op_code, c) => (*op_code, c),
+        };
+
+        let mut result = vec![op_code];
+        match op_code {
+            OP_PUSHDATA1 => result.extend_from_slice(&int_to_little_endian(&BigInt::from(data.len()), 1)),
+            OP_PUSHDATA2 => result.extend_from_slice(&int_to_little_endian(&BigInt::from(data.len()), 2)),
+            OP_PUSHDATA4 => result.extend_from_slice(&int_to_little_endian(&BigInt::from(data.len()), 4)),
+            _ => {},
+        }
+        result.extend_from_slice(data);
+
+        result
+    }
+}
+
+/// shortest push opcode for `len` bytes, the length itself up to 75
+fn push_opcode(len: usize) -> u8 {
+    if len <= 75 {
+        len as u8
+    } else if len <= 0xff {
+        OP_PUSHDATA1
+    } else if len <= 0xffff {
+        OP_PUSHDATA2
+    } else {
+        OP_PUSHDATA4
+    }
+}
+
+
+/// standard output script templates, with the key, hash or data they carry
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptType {
+    /// SEC public key
+    P2pk(Vec<u8>),
+    /// hash160 of the public key
+    P2pkh(Vec<u8>),
+    /// hash160 of the redeem script
+    P2sh(Vec<u8>),
+    /// hash160 of the public key
+    P2wpkh(Vec<u8>),
+    /// sha256 of the witness script
+    P2wsh(Vec<u8>),
+    /// x-only output key
+    P2tr(Vec<u8>),
+    /// bare m of n multisig with the SEC public keys
+    Multisig { m: usize, n: usize, keys: Vec<Vec<u8>> },
+    /// OP_RETURN followed by data pushes
+    NullData(Vec<Vec<u8>>),
+    /// witness program of a version without defined rules, version and program
+    WitnessUnknown(u8, Vec<u8>),
+    NonStandard,
 }
 
 
@@ -32,58 +122,84 @@ impl Script {
         }
     }
 
+    /// parse a script with its length prefix, what can't be decoded is kept as
+    /// ScriptCmd::Invalid
     pub fn parse(s: &[u8]) -> Self {
         info!("script serialization: {}", s.encode_hex::<String>());
 
         let (len, bytes_read) = read_varint(s);
-        let len = len.to_u32_digits().1[0] as usize;
+        let len = len.to_usize().unwrap();
         info!("script length: {}", len);
 
+        let end = s.len().min(bytes_read.saturating_add(len));
+        Self::parse_raw_lenient(&s[bytes_read.min(end)..end])
+    }
+
+    /// parse a script without the length prefix, None if a push runs past the end
+    pub fn parse_raw(s: &[u8]) -> Option<Self> {
+        match Self::decode(s) {
+            (cmds, None) => Some(Self { cmds }),
+            (_, Some(_)) => None,
+        }
+    }
+
+    /// parse a script without the length prefix, a push running past the end is kept with the
+    /// rest of the bytes as ScriptCmd::Invalid
+    pub fn parse_raw_lenient(s: &[u8]) -> Self {
+        let (mut cmds, failed_at) = Self::decode(s);
+        if let Some(pos) = failed_at {
+            cmds.push(ScriptCmd::Invalid(s[pos..].to_owned()));
+        }
+
+        Self {
+            cmds,
+        }
+    }
+
+    /// the commands up to the first push that runs past the end, and where that push starts
+    fn decode(s: &[u8]) -> (Vec<ScriptCmd>, Option<usize>) {
         let mut cmds: Vec<ScriptCmd> = vec![];
         let mut count = 0usize;
 
-        let s = &s[bytes_read..];
-
-        while count < len {
-            let current = s[count];
+        while count < s.len() {
+            let start = count;
+            let current_byte = s[count];
             count += 1;
-            let current_byte = current;
-
-            if current_byte >= 1 && current_byte <= 75 { // next n bytes are element
-                let n = current_byte as usize;
-                let mut op: Vec<u8> = vec![];
-                op.extend_from_slice(&s[count..(count + n)]);
-                cmds.push(ScriptCmd::Cmd(op));
-                count += n;
-            } else if current_byte == 76 { // OP_PUSHDATA1, next 1 byte implys how many bytes to
-                                           // read
-                let data_len = little_endian_to_int(&[s[count]]).to_u32_digits().1[0] as usize;
-                count += 1;
-                let mut op: Vec<u8> = vec![];
-                op.extend_from_slice(&s[count..(count + data_len)]);
-                cmds.push(ScriptCmd::Cmd(op));
-                count += data_len;
-            } else if current_byte == 77 { // OP_PUSHDATA2, next 2 bytes implys how many bytes to
-                                           // read
-                let data_len = little_endian_to_int(&s[count..(count + 2)]).to_u32_digits().1[0] as usize;
-                count += 2;
-                let mut op: Vec<u8> = vec![];
-                op.extend_from_slice(&s[count..(count + data_len)]);
-                cmds.push(ScriptCmd::Cmd(op));
-                count += data_len;
+
+            let data_len = if (1..=75).contains(&current_byte) { // next n bytes are element
+                current_byte as usize
+            } else if (OP_PUSHDATA1..=OP_PUSHDATA4).contains(&current_byte) { // next 1, 2 or 4
+                                                                                // bytes imply how
+                                                                                // many bytes to read
+                let size = match current_byte {
+                    OP_PUSHDATA1 => 1,
+                    OP_PUSHDATA2 => 2,
+                    _ => 4,
+                };
+                let data_len = match s.get(count..(count + size)) {
+                    Some(len) => little_endian_to_int(len).to_usize().unwrap(),
+                    None => return (cmds, Some(start)),
+                };
+                count += size;
+                data_len
             } else { // op_code
                 cmds.push(ScriptCmd::OpCode(current_byte));
+                continue;
+            };
+
+            let data = match s.get(count..).filter(|rest| rest.len() >= data_len) {
+                Some(rest) => rest[..data_len].to_owned(),
+                None => return (cmds, Some(start)),
+            };
+            count += data_len;
+            if current_byte == push_opcode(data_len) {
+                cmds.push(ScriptCmd::Cmd(data));
+            } else {
+                cmds.push(ScriptCmd::PushData(current_byte, data));
             }
         }
 
-        info!("count: {}, len: {}", count, len);
-        if count != len {
-            panic!("parsing script failed");
-        }
-
-        Self {
-            cmds,
-        }
+        (cmds, None)
     }
 }
 
@@ -100,36 +216,369 @@ impl Script {
     pub fn raw_serialize(&self) -> Vec<u8> {
         let mut result: Vec<u8> = vec![];
         for cmd in &self.cmds {
+            result.extend_from_slice(&cmd.serialize());
+        }
+
+        result
+    }
+
+    /// FindAndDelete, remove every command serialized exactly like the shortest push of `data`,
+    /// the same data pushed with a longer opcode stays, returns how many were removed
+    pub fn find_and_delete(&mut self, data: &[u8]) -> usize {
+        let pattern = ScriptCmd::Cmd(data.to_owned()).serialize();
+        let len = self.cmds.len();
+        self.cmds.retain(|cmd| cmd.serialize() != pattern);
+        len - self.cmds.len()
+    }
+}
+
+impl Script {
+    /// run the script under consensus rules only, `z` is the signature hash signatures are
+    /// checked against
+    pub fn evaluate(&self, z: &BigInt) -> Result<(), ScriptError> {
+        self.evaluate_with(z, VerifyFlags::NONE)
+    }
+
+    /// run the script with signatures checked against the hashes from `checker`
+    pub fn evaluate_with(&self, checker: &dyn SignatureChecker, flags: VerifyFlags) -> Result<(), ScriptError> {
+        Interpreter::new(self, checker, flags).run()
+    }
+}
+
+/// concatenate scripts, e.g. scriptSig + scriptPubKey for evaluation
+impl Add for Script {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        let mut cmds = self.cmds;
+        cmds.extend(rhs.cmds);
+
+        Self {
+            cmds,
+        }
+    }
+}
+
+impl Script {
+    /// parse Bitcoin Core style ASM, opcodes with or without the OP_ prefix, decimal numbers and
+    /// hex data pushes, None on an unknown token
+    pub fn from_asm(asm: &str) -> Option<Self> {
+        let mut cmds = vec![];
+        for token in asm.split_whitespace() {
+            // decimal first, ASM writes pushes of up to 4 bytes as numbers
+            if let Ok(n) = token.parse::<i64>() {
+                if (-0x7fffffff..=0x7fffffff).contains(&n) {
+                    cmds.push(match n {
+                        0 => ScriptCmd::OpCode(OP_0),
+                        -1 => ScriptCmd::OpCode(OP_1NEGATE),
+                        1..=16 => ScriptCmd::OpCode(OP_1 + n as u8 - 1),
+                        _ => ScriptCmd::Cmd(encode_num(n)),
+                    });
+                    continue;
+                }
+            }
+
+            if let Some(op) = op_code_from_name(token) {
+                cmds.push(ScriptCmd::OpCode(op));
+                continue;
+            }
+
+            cmds.push(ScriptCmd::Cmd(hex::decode(token).ok()?));
+        }
+
+        Some(Self {
+            cmds,
+        })
+    }
+}
+
+// -- ScriptBuilder --
+
+/// builds a script command by command, pushes use the smallest encoding
+#[derive(Clone, Default)]
+pub struct ScriptBuilder {
+    cmds: Vec<ScriptCmd>,
+}
+
+impl ScriptBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// OP_0, OP_1NEGATE and OP_1 to OP_16 where possible, else a push of the script number
+    pub fn push_int(mut self, n: i64) -> Self {
+        let cmd = match n {
+            0 => ScriptCmd::OpCode(OP_0),
+            -1 => ScriptCmd::OpCode(OP_1NEGATE),
+            1..=16 => ScriptCmd::OpCode(OP_1 + n as u8 - 1),
+            _ => ScriptCmd::Cmd(encode_num(n)),
+        };
+        self.cmds.push(cmd);
+        self
+    }
+
+    /// push data, the empty and single byte values with an opcode of their own use it
+    pub fn push_slice(mut self, data: &[u8]) -> Self {
+        let cmd = match data {
+            [] => ScriptCmd::OpCode(OP_0),
+            [n @ 1..=16] => ScriptCmd::OpCode(OP_1 + n - 1),
+            [0x81] => ScriptCmd::OpCode(OP_1NEGATE),
+            _ => ScriptCmd::Cmd(data.to_owned()),
+        };
+        self.cmds.push(cmd);
+        self
+    }
+
+    pub fn push_opcode(mut self, op: u8) -> Self {
+        self.cmds.push(ScriptCmd::OpCode(op));
+        self
+    }
+
+    pub fn into_script(self) -> Script {
+        Script {
+            cmds: self.cmds,
+        }
+    }
+}
+
+impl Script {
+    pub fn p2pk(pub_key: &[u8]) -> Self {
+        ScriptBuilder::new().push_slice(pub_key).push_opcode(OP_CHECKSIG).into_script()
+    }
+
+    pub fn p2pkh(h160: &[u8]) -> Self {
+        ScriptBuilder::new()
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_slice(h160)
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_CHECKSIG)
+            .into_script()
+    }
+
+    /// `h160` is the hash160 of the redeem script
+    pub fn p2sh(h160: &[u8]) -> Self {
+        ScriptBuilder::new().push_opcode(OP_HASH160).push_slice(h160).push_opcode(OP_EQUAL).into_script()
+    }
+
+    /// witness output of any version
+    pub fn segwit(version: u8, program: &[u8]) -> Self {
+        ScriptBuilder::new().push_int(version as i64).push_slice(program).into_script()
+    }
+
+    pub fn p2wpkh(h160: &[u8]) -> Self {
+        Self::segwit(0, h160)
+    }
+
+    /// `h256` is the sha256 of the witness script
+    pub fn p2wsh(h256: &[u8]) -> Self {
+        Self::segwit(0, h256)
+    }
+
+    /// `output_key` is the 32 bytes x-only tweaked key
+    pub fn p2tr(output_key: &[u8]) -> Self {
+        Self::segwit(1, output_key)
+    }
+
+    /// m of n CHECKMULTISIG over SEC keys, in the given order
+    pub fn multisig(m: usize, keys: &[Vec<u8>]) -> Self {
+        let mut builder = ScriptBuilder::new().push_int(m as i64);
+        for key in keys {
+            builder = builder.push_slice(key);
+        }
+        builder.push_int(keys.len() as i64).push_opcode(OP_CHECKMULTISIG).into_script()
+    }
+
+    /// provably unspendable output carrying `data`
+    pub fn op_return(data: &[u8]) -> Self {
+        ScriptBuilder::new().push_opcode(OP_RETURN).push_slice(data).into_script()
+    }
+}
+
+/// length of a SEC public key given its first byte, 0 if it isn't one
+fn sec_len(prefix: u8) -> usize {
+    match prefix {
+        2 | 3 => 33,
+        4 | 6 | 7 => 65,
+        _ => 0,
+    }
+}
+
+/// value of OP_1 to OP_16
+fn small_int(op: u8) -> Option<usize> {
+    if (OP_1..=OP_16).contains(&op) {
+        Some((op - OP_1 + 1) as usize)
+    } else {
+        None
+    }
+}
+
+impl Script {
+    /// witness version and program if this is a witness output: a version opcode and a single
+    /// direct push of 2 to 40 bytes
+    pub fn witness_program(&self) -> Option<(u8, Vec<u8>)> {
+        let b = self.raw_serialize();
+        if b.len() < 4 || b.len() > 42 || b[1] as usize != b.len() - 2 {
+            return None;
+        }
+
+        match b[0] {
+            OP_0 => Some((0, b[2..].to_owned())),
+            op => small_int(op).map(|v| (v as u8, b[2..].to_owned())),
+        }
+    }
+
+    /// only pushes and OP_RESERVED, which counts as one since it's below OP_16
+    pub fn is_push_only(&self) -> bool {
+        self.cmds.iter().all(|cmd| match cmd {
+            ScriptCmd::OpCode(op) => *op <= OP_16,
+            ScriptCmd::Invalid(_) => false,
+            _ => true,
+        })
+    }
+
+    /// match the standard templates on the serialized script, so only the usual push encodings
+    /// qualify
+    pub fn classify(&self) -> ScriptType {
+        let b = self.raw_serialize();
+
+        if b.len() == 23 && b[0] == OP_HASH160 && b[1] == 20 && b[22] == OP_EQUAL {
+            return ScriptType::P2sh(b[2..22].to_owned());
+        }
+
+        if let Some((version, program)) = self.witness_program() {
+            return match (version, program.len()) {
+                (0, 20) => ScriptType::P2wpkh(program),
+                (0, 32) => ScriptType::P2wsh(program),
+                (0, _) => ScriptType::NonStandard,
+                (1, 32) => ScriptType::P2tr(program),
+                _ => ScriptType::WitnessUnknown(version, program),
+            };
+        }
+
+        if b.first() == Some(&OP_RETURN) {
+            let data = Script::parse_raw(&b[1..]).and_then(|s| {
+                s.cmds.into_iter().map(|cmd| match cmd {
+                    ScriptCmd::Cmd(c) | ScriptCmd::PushData(_, c) => Some(c),
+                    // every opcode up to OP_16 counts as a push, OP_RESERVED pushing nothing
+                    ScriptCmd::OpCode(OP_0) | ScriptCmd::OpCode(OP_RESERVED) => Some(vec![]),
+                    ScriptCmd::OpCode(OP_1NEGATE) => Some(encode_num(-1)),
+                    ScriptCmd::OpCode(op) => small_int(op).map(|n| vec![n as u8]),
+                    ScriptCmd::Invalid(_) => None,
+                }).collect::<Option<Vec<_>>>()
+            });
+            return match data {
+                Some(data) => ScriptType::NullData(data),
+                None => ScriptType::NonStandard,
+            };
+        }
+
+        if b.len() >= 35 && b[0] as usize == sec_len(b[1]) && b.len() == b[0] as usize + 2 && b[b.len() - 1] == OP_CHECKSIG {
+            return ScriptType::P2pk(b[1..(b.len() - 1)].to_owned());
+        }
+
+        if b.len() == 25 && b[..3] == [OP_DUP, OP_HASH160, 20] && b[23..] == [OP_EQUALVERIFY, OP_CHECKSIG] {
+            return ScriptType::P2pkh(b[3..23].to_owned());
+        }
+
+        if let Some(multisig) = self.classify_multisig() {
+            return multisig;
+        }
+
+        ScriptType::NonStandard
+    }
+
+    /// OP_m <pubkey>... OP_n OP_CHECKMULTISIG
+    fn classify_multisig(&self) -> Option<ScriptType> {
+        let len = self.cmds.len();
+        if len < 4 {
+            return None;
+        }
+
+        let m = match self.cmds[0] { ScriptCmd::OpCode(op) => small_int(op)?, _ => return None };
+        let n = match self.cmds[len - 2] { ScriptCmd::OpCode(op) => small_int(op)?, _ => return None };
+        match self.cmds[len - 1] {
+            ScriptCmd::OpCode(OP_CHECKMULTISIG) => {},
+            _ => return None,
+        }
+
+        let mut keys = vec![];
+        for cmd in &self.cmds[1..(len - 2)] {
             match cmd {
-                ScriptCmd::OpCode(op_code) => {
-                    result.extend_from_slice(&int_to_little_endian(&BigInt::from(*op_code), 1));
+                ScriptCmd::Cmd(key) if !key.is_empty() && key.len() == sec_len(key[0]) => keys.push(key.clone()),
+                _ => return None,
+            }
+        }
+        if keys.len() != n || m > n {
+            return None;
+        }
+
+        Some(ScriptType::Multisig { m, n, keys })
+    }
+}
+
+impl Script {
+    /// legacy sigop count, CHECKSIG counts one and CHECKMULTISIG the most keys it can take, or,
+    /// when `accurate`, the key count if an OP_1 to OP_16 right before it gives it
+    pub fn sigop_count(&self, accurate: bool) -> usize {
+        let mut count = 0;
+        let mut last_op = None;
+        for cmd in &self.cmds {
+            let op = match cmd {
+                ScriptCmd::OpCode(op) => *op,
+                _ => {
+                    last_op = None;
+                    continue;
                 },
-                ScriptCmd::Cmd(c) => {
-                    let len = c.len();
-                    if len < 75 {
-                        result.extend_from_slice(&int_to_little_endian(&BigInt::from(len), 1));
-                    } else if len > 75 && len < 0x100 {
-                        result.extend_from_slice(&int_to_little_endian(&BigInt::from(76u8), 1));
-                        result.extend_from_slice(&int_to_little_endian(&BigInt::from(len), 1));
-                    } else if len >= 0x100 && len <= 520 {
-                        result.extend_from_slice(&int_to_little_endian(&BigInt::from(77u8), 1));
-                        result.extend_from_slice(&int_to_little_endian(&BigInt::from(len), 2));
-                    } else {
-                        panic!("too long a cmd");
-                    }
-                    result.extend_from_slice(c);
-                }
+            };
+            match op {
+                OP_CHECKSIG | OP_CHECKSIGVERIFY => count += 1,
+                OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                    count += match last_op.and_then(small_int) {
+                        Some(n) if accurate => n,
+                        _ => MAX_PUBKEYS_PER_MULTISIG as usize,
+                    };
+                },
+                _ => {},
             }
+            last_op = Some(op);
         }
 
-        result
+        count
+    }
+
+    /// sigops of the redeem script `script_sig` reveals for this P2SH output, counted accurately,
+    /// none if the scriptSig does anything but push
+    pub fn p2sh_sigop_count(&self, script_sig: &Script) -> usize {
+        if !matches!(self.classify(), ScriptType::P2sh(_)) {
+            return self.sigop_count(true);
+        }
+        if !script_sig.is_push_only() {
+            return 0;
+        }
+
+        // the last push is the redeem script, OP_0 to OP_16 push nothing that parses to a sigop, and
+        // like Core the count stops at a push running past the end
+        match script_sig.cmds.last().and_then(ScriptCmd::data) {
+            Some(redeem_script) => Script::parse_raw_lenient(redeem_script).sigop_count(true),
+            None => 0,
+        }
     }
 }
 
+/// Bitcoin Core style ASM, e.g. `OP_DUP OP_HASH160 <hex> OP_EQUALVERIFY OP_CHECKSIG`
 impl Display for Script {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // todo: unimplemented
-        write!(f, "")
+        let tokens: Vec<String> = self.cmds.iter().map(|cmd| match cmd {
+            ScriptCmd::OpCode(op) => op_code_name(*op).to_string(),
+            ScriptCmd::Cmd(c) | ScriptCmd::PushData(_, c) if c.len() <= MAX_NUM_SIZE => {
+                decode_num(c, MAX_NUM_SIZE, false).unwrap().to_string()
+            },
+            ScriptCmd::Cmd(c) | ScriptCmd::PushData(_, c) => c.encode_hex::<String>(),
+            ScriptCmd::Invalid(_) => String::from("[error]"),
+        }).collect();
+
+        write!(f, "{}", tokens.join(" "))
     }
 }
 
@@ -139,9 +588,15 @@ mod tests_script {
     use log::info;
     use hex::ToHex;
 
-    use crate::script::ScriptCmd;
+    use num_bigint::BigInt;
+
+    use crate::{
+        interpreter::ScriptError,
+        op::{OP_0, OP_ADD, OP_CHECKSIG, OP_ENDIF, OP_IF, OP_NOP, OP_PUSHDATA1},
+        script::ScriptCmd,
+    };
 
-    use super::Script;
+    use super::{Script, ScriptType, ScriptBuilder};
 
     #[test]
     fn test_parse() {
@@ -156,15 +611,195 @@ mod tests_script {
         info!("{}", script);
 
         match &script.cmds[0] {
-            ScriptCmd::Cmd(cmd) => {
+            ScriptCmd::Cmd(cmd) | ScriptCmd::PushData(_, cmd) => {
                 info!("cmd: {}", cmd.encode_hex::<String>());
             },
             ScriptCmd::OpCode(op) => {
                 info!("op: {:02x?}", op);
-            }
+            },
+            ScriptCmd::Invalid(bytes) => {
+                info!("invalid: {}", bytes.encode_hex::<String>());
+            },
         }
     }
 
+    #[test]
+    fn test_parse_invalid() {
+        // a push running past the end is kept with the rest of the bytes
+        let script = Script::parse(&hex::decode("03016a4c").unwrap());
+        assert_eq!(2, script.cmds.len());
+        assert!(matches!(&script.cmds[1], ScriptCmd::Invalid(bytes) if bytes[..] == [0x4c]));
+        assert_eq!("03016a4c", script.serialize().encode_hex::<String>());
+        assert_eq!("106 [error]", script.to_string());
+        assert!(!script.is_push_only());
+        assert!(Script::parse_raw(&[0x01, 0x6a, 0x4c]).is_none());
+
+        // and fails when run, even in an unexecuted branch
+        let script = Script::parse_raw_lenient(&[OP_0, OP_IF, OP_PUSHDATA1, OP_ENDIF]);
+        assert_eq!(Err(ScriptError::BadOpcode(OP_PUSHDATA1)), script.evaluate(&BigInt::from(0)));
+    }
+
+    #[test]
+    fn test_asm() {
+        let script_pubkey = Script::parse(&hex::decode("1976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac").unwrap());
+        let asm = "OP_DUP OP_HASH160 bc3b654dca7e56b04dca18f2566cdaf02e8d9ada OP_EQUALVERIFY OP_CHECKSIG";
+        assert_eq!(asm, script_pubkey.to_string());
+        assert_eq!(script_pubkey.serialize(), Script::from_asm(asm).unwrap().serialize());
+
+        // small pushes are numbers, names may leave out OP_
+        let script = Script::from_asm("0 -1 16 17 -1000 DUP OP_NOP2").unwrap();
+        assert_eq!("0 -1 16 17 -1000 OP_DUP OP_CHECKLOCKTIMEVERIFY", script.to_string());
+        assert_eq!("004f60011102e88376b1", script.raw_serialize().encode_hex::<String>());
+
+        assert!(Script::from_asm("OP_DUP OP_FOO").is_none());
+        assert!(Script::from_asm("abc").is_none());
+        // numbers past 4 bytes aren't pushed as numbers
+        assert!(Script::from_asm(&i64::MIN.to_string()).is_none());
+    }
+
+    #[test]
+    fn test_classify() {
+        let classify = |asm: &str| Script::from_asm(asm).unwrap().classify();
+        let h20 = "bc3b654dca7e56b04dca18f2566cdaf02e8d9ada";
+        let h32 = "a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c";
+        let key = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+        assert_eq!(ScriptType::P2pk(hex::decode(key).unwrap()), classify(&format!("{} OP_CHECKSIG", key)));
+        assert_eq!(
+            ScriptType::P2pkh(hex::decode(h20).unwrap()),
+            classify(&format!("OP_DUP OP_HASH160 {} OP_EQUALVERIFY OP_CHECKSIG", h20)),
+        );
+        assert_eq!(ScriptType::P2sh(hex::decode(h20).unwrap()), classify(&format!("OP_HASH160 {} OP_EQUAL", h20)));
+        assert_eq!(ScriptType::P2wpkh(hex::decode(h20).unwrap()), classify(&format!("0 {}", h20)));
+        assert_eq!(ScriptType::P2wsh(hex::decode(h32).unwrap()), classify(&format!("0 {}", h32)));
+        assert_eq!(ScriptType::P2tr(hex::decode(h32).unwrap()), classify(&format!("1 {}", h32)));
+        assert_eq!(ScriptType::WitnessUnknown(2, hex::decode(h20).unwrap()), classify(&format!("2 {}", h20)));
+        assert_eq!(
+            ScriptType::Multisig { m: 1, n: 2, keys: vec![hex::decode(key).unwrap(), hex::decode(key).unwrap()] },
+            classify(&format!("1 {} {} 2 OP_CHECKMULTISIG", key, key)),
+        );
+        assert_eq!(
+            ScriptType::NullData(vec![b"hello".to_vec()]),
+            classify(&format!("OP_RETURN {}", hex::encode(b"hello"))),
+        );
+        assert_eq!(ScriptType::NullData(vec![vec![0x81], vec![]]), classify("OP_RETURN -1 OP_RESERVED"));
+        assert_eq!(ScriptType::NullData(vec![vec![], vec![16]]), classify("OP_RETURN 0 16"));
+
+        // v0 programs must be 20 or 32 bytes, multisig counts must match
+        assert_eq!(ScriptType::NonStandard, classify(&format!("0 {}00", h20)));
+        assert_eq!(ScriptType::NonStandard, classify(&format!("2 {} 1 OP_CHECKMULTISIG", key)));
+        assert_eq!(ScriptType::NonStandard, classify("OP_RETURN OP_DUP"));
+        assert_eq!(ScriptType::NonStandard, classify("1 OP_ADD 2 OP_EQUAL"));
+    }
+
+    #[test]
+    fn test_builders() {
+        let h20 = hex::decode("bc3b654dca7e56b04dca18f2566cdaf02e8d9ada").unwrap();
+        let key = hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798").unwrap();
+
+        assert_eq!("76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac", Script::p2pkh(&h20).raw_serialize().encode_hex::<String>());
+        assert_eq!("a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada87", Script::p2sh(&h20).raw_serialize().encode_hex::<String>());
+        assert_eq!("0014bc3b654dca7e56b04dca18f2566cdaf02e8d9ada", Script::p2wpkh(&h20).raw_serialize().encode_hex::<String>());
+        assert_eq!(ScriptType::P2tr(vec![7; 32]), Script::p2tr(&[7; 32]).classify());
+        assert_eq!(ScriptType::P2wsh(vec![7; 32]), Script::p2wsh(&[7; 32]).classify());
+        assert_eq!(ScriptType::P2pk(key.clone()), Script::p2pk(&key).classify());
+        assert_eq!(
+            ScriptType::Multisig { m: 1, n: 1, keys: vec![key.clone()] },
+            Script::multisig(1, &[key]).classify(),
+        );
+        assert_eq!("6a0568656c6c6f", Script::op_return(b"hello").raw_serialize().encode_hex::<String>());
+
+        let script = ScriptBuilder::new()
+            .push_int(0)
+            .push_int(-1)
+            .push_int(16)
+            .push_int(17)
+            .push_slice(&[])
+            .push_slice(&[5])
+            .push_slice(&[0x81])
+            .push_slice(&[0x11])
+            .push_opcode(OP_ADD)
+            .into_script();
+        assert_eq!("004f60011100554f011193", script.raw_serialize().encode_hex::<String>());
+    }
+
+    #[test]
+    fn test_push_data() {
+        // 75 bytes is the longest direct push, then OP_PUSHDATA1, 2 and 4
+        for (len, prefix) in [(75, "4b"), (76, "4c4c"), (255, "4cff"), (256, "4d0001"), (0x10000, "4e00000100")] {
+            let script = Script::new(Some(&[ScriptCmd::Cmd(vec![1; len])]));
+            let serialized = script.raw_serialize();
+            assert_eq!(prefix, serialized[..(prefix.len() / 2)].to_vec().encode_hex::<String>());
+            assert_eq!(serialized, Script::parse_raw(&serialized).unwrap().raw_serialize());
+        }
+
+        // non-minimal pushes survive a round trip
+        for raw in ["4c0102", "4d0100ff", "4e0100000003", "4c00", "0105", "0181"] {
+            let script = Script::parse_raw(&hex::decode(raw).unwrap()).unwrap();
+            assert_eq!(raw, script.raw_serialize().encode_hex::<String>());
+            assert!(!script.cmds[0].is_minimal_push());
+        }
+        for raw in ["0111", "00", "55", "020500"] {
+            let script = Script::parse_raw(&hex::decode(raw).unwrap()).unwrap();
+            assert!(script.cmds[0].is_minimal_push());
+        }
+
+        assert!(Script::parse_raw(&hex::decode("4e01000000").unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_sigop_count() {
+        let sigops = |asm: &str| {
+            let script = Script::from_asm(asm).unwrap();
+            (script.sigop_count(false), script.sigop_count(true))
+        };
+
+        assert_eq!((0, 0), sigops(""));
+        assert_eq!((1, 1), sigops("OP_DUP OP_HASH160 0102030405060708090a0b0c0d0e0f1011121314 OP_EQUALVERIFY OP_CHECKSIG"));
+        assert_eq!((20, 2), sigops("OP_1 02aa 02bb OP_2 OP_CHECKMULTISIG"));
+        assert_eq!((21, 4), sigops("OP_CHECKSIGVERIFY OP_3 OP_CHECKMULTISIGVERIFY"));
+        // only OP_1 to OP_16 right before it give the key count
+        assert_eq!((20, 20), sigops("OP_0 OP_CHECKMULTISIG"));
+        assert_eq!((20, 20), sigops("0202 OP_CHECKMULTISIG"));
+        assert_eq!((40, 36), sigops("OP_16 OP_CHECKMULTISIG OP_CHECKMULTISIG"));
+
+        // P2SH counts the redeem script in the scriptSig
+        let redeem_script = Script::from_asm("OP_2 02aa 02bb 02cc OP_3 OP_CHECKMULTISIG").unwrap();
+        let script_pubkey = Script::p2sh(&[0; 20]);
+        assert_eq!(0, script_pubkey.sigop_count(true));
+        let script_sig = ScriptBuilder::new().push_int(0).push_slice(&[1; 71]).push_slice(&redeem_script.raw_serialize()).into_script();
+        assert_eq!(3, script_pubkey.p2sh_sigop_count(&script_sig));
+        let script_sig = ScriptBuilder::new().push_slice(&redeem_script.raw_serialize()).push_opcode(OP_NOP).into_script();
+        assert_eq!(0, script_pubkey.p2sh_sigop_count(&script_sig));
+        assert_eq!(0, script_pubkey.p2sh_sigop_count(&Script::new(None)));
+        // a truncated redeem script counts up to the push that runs past the end
+        let script_sig = ScriptBuilder::new().push_slice(&[OP_CHECKSIG, OP_PUSHDATA1]).into_script();
+        assert_eq!(1, script_pubkey.p2sh_sigop_count(&script_sig));
+    }
+
+    #[test]
+    fn test_find_and_delete() {
+        let find_and_delete = |raw: &str, data: &str| {
+            let mut script = Script::parse_raw(&hex::decode(raw).unwrap()).unwrap();
+            let found = script.find_and_delete(&hex::decode(data).unwrap());
+            (script.raw_serialize().encode_hex::<String>(), found)
+        };
+
+        assert_eq!(("".to_string(), 0), find_and_delete("", "02feed"));
+        assert_eq!(("".to_string(), 1), find_and_delete("02feed", "feed"));
+        assert_eq!(("ac".to_string(), 2), find_and_delete("02feed02feedac", "feed"));
+        assert_eq!(("02feef".to_string(), 0), find_and_delete("02feef", "feed"));
+        // only whole pushes match, not data that happens to contain the pattern
+        assert_eq!(("0302feed".to_string(), 0), find_and_delete("0302feed", "feed"));
+        assert_eq!(("03feed02".to_string(), 0), find_and_delete("03feed02", "feed"));
+        // non-minimal pushes of the same data are different bytes
+        assert_eq!(("4c02feed".to_string(), 1), find_and_delete("4c02feed02feed", "feed"));
+        assert_eq!(("51".to_string(), 0), find_and_delete("51", "01"));
+        assert_eq!(("51".to_string(), 1), find_and_delete("010151", "01"));
+        // an empty signature is OP_0
+        assert_eq!(("ab".to_string(), 2), find_and_delete("00ab00", ""));
+    }
+
     #[test]
     fn test_serialize() {
         env_logger::init();
@@ -2,7 +2,7 @@ use std::{fmt::Debug, rc::Rc};
 
 use num_bigint::BigInt;
 
-use crate::{s256point::S256Point, s256field::S256Field};
+use crate::{s256point::{S256Point, generator}, s256field::S256Field, utils::{tagged_hash, int_to_big_endian}};
 
 
 pub struct Signature {
@@ -35,14 +35,109 @@ impl Signature {
         let s = self.s.clone();
         let r = self.r.clone();
 
+        let zero = BigInt::from(0i32);
+        if r <= zero || r >= n || s <= zero || s >= n {
+            return false;
+        }
+
         let u = z * s.clone().modpow(&(n.clone() - BigInt::from(2i32)), &n) % n.clone();
         let v = r.clone() * s.modpow(&(n.clone() - BigInt::from(2i32)), &n) % n.clone();
 
         let k_g = g.multi(u) + pub_key.multi(v);
 
-        let rx = &k_g.x.as_ref().unwrap().num;
+        match &k_g.x {
+            Some(rx) => rx.num == r,
+            None => false,
+        }
+    }
+
+    /// parse a DER signature without the sighash byte, None if it isn't one
+    pub fn parse(der: &[u8]) -> Option<Self> {
+        if der.len() < 8 || der[0] != 0x30 || der[1] as usize != der.len() - 2 {
+            return None;
+        }
 
-        return *rx == r;
+        let mut values = vec![];
+        let mut pos = 2;
+        for _ in 0..2 {
+            if pos + 2 > der.len() || der[pos] != 2 {
+                return None;
+            }
+            let len = der[pos + 1] as usize;
+            pos += 2;
+            if len == 0 || pos + len > der.len() {
+                return None;
+            }
+            values.push(BigInt::from_bytes_be(num_bigint::Sign::Plus, &der[pos..(pos + len)]));
+            pos += len;
+        }
+        if pos != der.len() {
+            return None;
+        }
+
+        let s = values.pop().unwrap();
+        let r = values.pop().unwrap();
+        Some(Self::new(r, s))
+    }
+
+    /// parse a signature the way Bitcoin Core reads them whatever the flags, BER lengths, padded
+    /// integers and trailing bytes are fine, None if it can't be read or r or s is over 32 bytes
+    pub fn parse_lax(der: &[u8]) -> Option<Self> {
+        if der.first() != Some(&0x30) {
+            return None;
+        }
+        // the sequence length isn't checked, only skipped
+        let mut pos = 2;
+        let len_byte = *der.get(1)?;
+        if len_byte & 0x80 != 0 {
+            let size = (len_byte & 0x7f) as usize;
+            if size > der.len() - pos {
+                return None;
+            }
+            pos += size;
+        }
+
+        let mut values = vec![];
+        for _ in 0..2 {
+            if der.get(pos) != Some(&2) {
+                return None;
+            }
+            let len_byte = *der.get(pos + 1)?;
+            pos += 2;
+            let len = if len_byte & 0x80 != 0 {
+                let mut size = (len_byte & 0x7f) as usize;
+                if size > der.len() - pos {
+                    return None;
+                }
+                while size > 0 && der[pos] == 0 {
+                    pos += 1;
+                    size -= 1;
+                }
+                if size >= std::mem::size_of::<usize>() {
+                    return None;
+                }
+                let len = der[pos..(pos + size)].iter().fold(0usize, |len, byte| (len << 8) + *byte as usize);
+                pos += size;
+                len
+            } else {
+                len_byte as usize
+            };
+            if len > der.len() - pos {
+                return None;
+            }
+
+            let value = &der[pos..(pos + len)];
+            let padding = value.iter().take_while(|byte| **byte == 0).count();
+            if len - padding > 32 {
+                return None;
+            }
+            values.push(BigInt::from_bytes_be(num_bigint::Sign::Plus, value));
+            pos += len;
+        }
+
+        let s = values.pop().unwrap();
+        let r = values.pop().unwrap();
+        Some(Self::new(r, s))
     }
 
     /// DER (Distinguished Encoding Rules) format
@@ -99,3 +194,74 @@ impl Debug for Signature {
         write!(f, "{}", self_formatted)
     }
 }
+
+
+/// BIP340 signature over an x-only public key, r is the x coordinate of the nonce point
+pub struct SchnorrSignature {
+    pub r: BigInt,
+    pub s: BigInt,
+}
+
+impl SchnorrSignature {
+    pub fn new(r: BigInt, s: BigInt) -> Self {
+        Self {
+            r,
+            s,
+        }
+    }
+
+    /// parse 64 bytes r || s, None if r isn't below p or s isn't below n
+    pub fn parse(sig: &[u8]) -> Option<Self> {
+        let p = BigInt::parse_bytes(b"fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f", 16).unwrap();
+        let n = BigInt::parse_bytes(b"fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141", 16).unwrap();
+        if sig.len() != 64 {
+            return None;
+        }
+
+        let r = BigInt::from_bytes_be(num_bigint::Sign::Plus, &sig[..32]);
+        let s = BigInt::from_bytes_be(num_bigint::Sign::Plus, &sig[32..]);
+        if r >= p || s >= n {
+            return None;
+        }
+        Some(Self::new(r, s))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = int_to_big_endian(&self.r, 32);
+        result.extend_from_slice(&int_to_big_endian(&self.s, 32));
+        result
+    }
+
+    /// BIP340 challenge, hashTag(r || P || msg) mod n
+    pub fn challenge(r: &[u8], pub_key: &[u8], msg: &[u8]) -> BigInt {
+        let n = BigInt::parse_bytes(b"fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141", 16).unwrap();
+        let mut data = r.to_owned();
+        data.extend_from_slice(pub_key);
+        data.extend_from_slice(msg);
+        BigInt::from_bytes_be(num_bigint::Sign::Plus, &tagged_hash("BIP0340/challenge", &data)) % n
+    }
+
+    /// whether sG - eP is a point with even y and x equal to r, `pub_key` is a 32 bytes x-only key
+    pub fn is_valid(&self, msg: &[u8], pub_key: &[u8]) -> bool {
+        let n = BigInt::parse_bytes(b"fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141", 16).unwrap();
+        let mut sec = vec![2u8];
+        sec.extend_from_slice(pub_key);
+        if pub_key.len() != 32 || !S256Point::is_valid_sec(&sec) {
+            return false;
+        }
+        let point = S256Point::parse(sec);
+
+        let e = Self::challenge(&int_to_big_endian(&self.r, 32), pub_key, msg);
+        let r_point = generator().multi(self.s.clone()) + point.multi(n - e);
+        match &r_point.x {
+            Some(rx) => rx.num == self.r && r_point.has_even_y(),
+            None => false,
+        }
+    }
+}
+
+impl Debug for SchnorrSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SchnorrSignature {{ r: 0x{:0>64}, s: 0x{:0>64} }}", self.r.to_str_radix(16), self.s.to_str_radix(16))
+    }
+}
@@ -0,0 +1,190 @@
+use std::fmt::Display;
+
+use hex::ToHex;
+
+use crate::{
+    interpreter::{Interpreter, ScriptError, SigVersion, SignatureChecker, VerifyFlags},
+    script::{Script, ScriptCmd},
+};
+
+
+/// interpreter state right after a command ran
+#[derive(Clone)]
+pub struct TraceStep {
+    /// position of `cmd` in the script
+    pub pc: usize,
+    pub cmd: ScriptCmd,
+    pub stack: Vec<Vec<u8>>,
+    pub alt_stack: Vec<Vec<u8>>,
+    /// whether each enclosing IF branch executes, innermost last
+    pub exec_stack: Vec<bool>,
+    /// the error `cmd` failed with, tracing stops after it
+    pub error: Option<ScriptError>,
+}
+
+fn format_stack(stack: &[Vec<u8>]) -> String {
+    let elements: Vec<String> = stack.iter().rev().map(|element| {
+        if element.is_empty() { String::from("<empty>") } else { element.encode_hex::<String>() }
+    }).collect();
+    format!("[{}]", elements.join(", "))
+}
+
+impl Display for TraceStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "#{:04} {}", self.pc, Script::new(Some(std::slice::from_ref(&self.cmd))))?;
+        // tops of the stacks first
+        writeln!(f, "  stack:     {}", format_stack(&self.stack))?;
+        writeln!(f, "  alt stack: {}", format_stack(&self.alt_stack))?;
+        write!(f, "  exec:      {:?}", self.exec_stack)?;
+        if let Some(error) = &self.error {
+            write!(f, "\n  error:     {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// steps through a script, yielding the state after every command
+pub struct Tracer<'a> {
+    interpreter: Interpreter<'a>,
+    /// the error the last step failed with
+    error: Option<ScriptError>,
+}
+
+impl<'a> Tracer<'a> {
+    pub fn new(script: &Script, checker: &'a dyn SignatureChecker, flags: VerifyFlags) -> Self {
+        Self {
+            interpreter: Interpreter::new(script, checker, flags),
+            error: None,
+        }
+    }
+
+    /// trace a script starting on the stack a previous one left
+    pub fn with_stack(script: &Script, stack: Vec<Vec<u8>>, checker: &'a dyn SignatureChecker, flags: VerifyFlags) -> Self {
+        Self {
+            interpreter: Interpreter::with_stack(script, stack, checker, flags, SigVersion::Base),
+            error: None,
+        }
+    }
+
+    pub fn interpreter(&self) -> &Interpreter<'a> {
+        &self.interpreter
+    }
+
+    /// the command the next step runs, None once the script is done or failed
+    pub fn next_cmd(&self) -> Option<&ScriptCmd> {
+        if self.error.is_some() {
+            return None;
+        }
+        self.interpreter.cmds.get(self.interpreter.pc)
+    }
+
+    /// once all steps are taken, whether the script ran to the end with every IF closed, what
+    /// it left on the stack aside
+    pub fn executed(&self) -> Result<(), ScriptError> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        if !self.interpreter.exec_stack.is_empty() {
+            return Err(ScriptError::UnbalancedConditional);
+        }
+        Ok(())
+    }
+
+    /// once all steps are taken, whether the script succeeded
+    pub fn result(&self) -> Result<(), ScriptError> {
+        self.executed()?;
+        self.interpreter.finish()
+    }
+}
+
+impl<'a> Iterator for Tracer<'a> {
+    type Item = TraceStep;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cmd = self.next_cmd()?.clone();
+        let pc = self.interpreter.pc;
+        let error = self.interpreter.step().err();
+        self.error = error.clone();
+
+        Some(TraceStep {
+            pc,
+            cmd,
+            stack: self.interpreter.stack.clone(),
+            alt_stack: self.interpreter.alt_stack.clone(),
+            exec_stack: self.interpreter.exec_stack.clone(),
+            error,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests_tracer {
+    use num_bigint::BigInt;
+
+    use crate::{
+        interpreter::{ScriptError, VerifyFlags},
+        script::{Script, ScriptCmd},
+    };
+
+    use super::Tracer;
+
+    #[test]
+    fn test_trace() {
+        let z = BigInt::from(0);
+        let script = Script::from_asm("2 OP_TOALTSTACK 1 OP_IF 3 OP_ELSE 4 OP_ENDIF OP_FROMALTSTACK OP_ADD 5 OP_EQUAL").unwrap();
+        let mut tracer = Tracer::new(&script, &z, VerifyFlags::NONE);
+
+        assert!(matches!(tracer.next_cmd(), Some(ScriptCmd::OpCode(_))));
+        let steps: Vec<_> = tracer.by_ref().collect();
+        assert_eq!(script.cmds.len(), steps.len());
+        assert_eq!(Ok(()), tracer.result());
+        assert!(tracer.next_cmd().is_none());
+
+        // after OP_TOALTSTACK
+        assert!(steps[1].stack.is_empty());
+        assert_eq!(vec![vec![2u8]], steps[1].alt_stack);
+        // 4 is skipped in the ELSE branch
+        assert_eq!(vec![false], steps[5].exec_stack);
+        assert_eq!(vec![vec![3u8]], steps[5].stack);
+        assert_eq!(7, steps[7].pc);
+        assert!(steps[7].exec_stack.is_empty());
+        assert_eq!("#0001 OP_TOALTSTACK\n  stack:     []\n  alt stack: [02]\n  exec:      []", steps[1].to_string());
+    }
+
+    #[test]
+    fn test_trace_error() {
+        let z = BigInt::from(0);
+        let script = Script::from_asm("1 2 OP_EQUALVERIFY 3").unwrap();
+        let mut tracer = Tracer::new(&script, &z, VerifyFlags::NONE);
+
+        let steps: Vec<_> = tracer.by_ref().collect();
+        assert_eq!(3, steps.len());
+        assert_eq!(Some(ScriptError::EqualVerify), steps[2].error);
+        assert_eq!(Err(ScriptError::EqualVerify), tracer.result());
+    }
+
+    #[test]
+    fn test_trace_with_stack() {
+        let z = BigInt::from(0);
+        let script_sig = Script::from_asm("1 OP_IF 2").unwrap();
+        let mut tracer = Tracer::new(&script_sig, &z, VerifyFlags::NONE);
+        tracer.by_ref().for_each(drop);
+        assert_eq!(Err(ScriptError::UnbalancedConditional), tracer.executed());
+
+        // a scriptSig leaving false on the stack is fine, the scriptPubKey runs on it
+        let script_sig = Script::from_asm("0 2").unwrap();
+        let mut tracer = Tracer::new(&script_sig, &z, VerifyFlags::NONE);
+        tracer.by_ref().for_each(drop);
+        assert_eq!(Ok(()), tracer.executed());
+        let stack = tracer.interpreter().stack.clone();
+
+        let script_pubkey = Script::from_asm("2 OP_EQUALVERIFY OP_NOT").unwrap();
+        let mut tracer = Tracer::with_stack(&script_pubkey, stack, &z, VerifyFlags::NONE);
+        let steps: Vec<_> = tracer.by_ref().collect();
+        assert_eq!(3, steps.len());
+        assert_eq!(vec![vec![1u8]], steps[2].stack);
+        assert_eq!(Ok(()), tracer.result());
+    }
+}
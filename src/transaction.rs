@@ -1,9 +1,49 @@
-use std::fmt::Display;
+use std::{cell::RefCell, fmt::Display};
 
 use num_bigint::BigInt;
+use num_traits::ToPrimitive;
 use hex::ToHex;
 
-use crate::{utils::{hash256, int_to_little_endian, encode_varint, little_endian_to_int, read_varint}, script::Script, tx_fetcher::TxFetcher};
+use crate::{
+    utils::{hash256, int_to_little_endian, encode_varint, little_endian_to_int, read_varint},
+    op::OP_CODESEPARATOR,
+    script::{Script, ScriptCmd, ScriptType},
+    tx_fetcher::TxFetcher,
+    network::Network,
+    interpreter::{ExecData, SigVersion, SignatureChecker, VerifyFlags, witness_sigop_count},
+};
+
+
+/// locktimes below this are block heights, from it on unix timestamps
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+/// an input with this sequence opts out of the locktime
+pub const SEQUENCE_FINAL: u32 = 0xffffffff;
+/// BIP68, a sequence with this bit set is not a relative locktime
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+/// BIP68, relative locktimes with this bit set count 512 seconds units instead of blocks
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+/// BIP68, bits of the sequence holding the relative locktime
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000ffff;
+
+/// sighash types, the last byte of a signature tells which parts of the transaction it signs
+pub const SIGHASH_ALL: u8 = 1;
+/// taproot only, signs like SIGHASH_ALL without a sighash byte on the signature (BIP341)
+pub const SIGHASH_DEFAULT: u8 = 0;
+pub const SIGHASH_NONE: u8 = 2;
+pub const SIGHASH_SINGLE: u8 = 3;
+/// combined with one of the above, only the input being signed is committed to
+pub const SIGHASH_ANYONECANPAY: u8 = 0x80;
+
+/// BIP144 extended serialization, a zero byte where the input count goes and a flag
+pub const SEGWIT_MARKER: u8 = 0x00;
+pub const SEGWIT_FLAG: u8 = 0x01;
+
+/// legacy sigops and bytes outside the witness weigh this much more than witness ones
+pub const WITNESS_SCALE_FACTOR: usize = 4;
+/// consensus limit on the sigop cost of all transactions in a block
+pub const MAX_BLOCK_SIGOPS_COST: usize = 80_000;
+/// policy limit on the sigop cost of a single transaction
+pub const MAX_STANDARD_TX_SIGOPS_COST: usize = MAX_BLOCK_SIGOPS_COST / 5;
 
 
 #[derive(Clone)]
@@ -13,34 +53,56 @@ pub struct Tx {
     tx_ins: Vec<TxIn>,
     tx_outs: Vec<TxOut>,
     locktime: BigInt,
-    testnet: bool,
+    network: Network,
+    /// BIP143 hashes, computed by the first segwit v0 signature hash
+    segwit_hashes: RefCell<Option<SegwitHashes>>,
 }
 
 impl Tx {
-    pub fn new(version: u32, tx_ins: Vec<TxIn>, tx_outs: Vec<TxOut>, locktime: BigInt, testnet: bool) -> Self {
+    pub fn new(version: u32, tx_ins: Vec<TxIn>, tx_outs: Vec<TxOut>, locktime: BigInt, network: Network) -> Self {
         Self {
             version,
             tx_ins,
             tx_outs,
             locktime,
-            testnet,
+            network,
+            segwit_hashes: RefCell::new(None),
         }
     }
 
-    /// Parse transaction serialization into Tx struct
-    pub fn parse(serialization: &[u8], testnet: bool) -> Self {
+    /// Parse transaction serialization into Tx struct, legacy or with the BIP144 marker, flag and
+    /// witnesses
+    pub fn parse(serialization: &[u8], network: Network) -> Self {
         let mut bytes_read = 0;
         // version is encoded in 4 bytes little-endian
         let version = little_endian_to_int(&serialization[bytes_read..4]);
-        let version = version.to_u32_digits().1[0];
+        let version = version.to_u32().unwrap();
         bytes_read += 4;
 
+        // a zero input count can't be a valid legacy transaction, so it marks the segwit format,
+        // followed by a flag telling witnesses are present
+        let segwit = serialization[bytes_read] == SEGWIT_MARKER;
+        if segwit {
+            let flag = serialization[bytes_read + 1];
+            if flag != SEGWIT_FLAG {
+                panic!("unknown segwit flag: {}", flag);
+            }
+            bytes_read += 2;
+        }
+
         // inputs
-        let tx_ins = TxIn::parse(&serialization, &mut bytes_read);
+        let mut tx_ins = TxIn::parse(&serialization, &mut bytes_read);
 
         // outputs
         let tx_outs = TxOut::parse(&serialization, &mut bytes_read);
 
+        // one witness stack per input
+        if segwit {
+            for tx_in in &mut tx_ins {
+                tx_in.witness = TxIn::parse_witness(serialization, &mut bytes_read);
+            }
+        }
+
         // locktime, 4 bytes; if sequence is ffffffff, locktime will be ignored
         let locktime = BigInt::from_bytes_le(num_bigint::Sign::Plus, &serialization[bytes_read..(bytes_read + 4)]);
 
@@ -49,15 +111,16 @@ impl Tx {
             tx_ins,
             tx_outs,
             locktime,
-            testnet,
+            network,
+            segwit_hashes: RefCell::new(None),
         }
     }
 }
 
 impl Tx {
-    /// binary hash of the legacy serialization
+    /// binary hash of the legacy serialization, byte order reversed the way it's displayed
     pub fn hash(&self) -> Vec<u8> {
-        hash256(&self.serialize().into_iter().rev().collect::<Vec<u8>>())
+        hash256(&self.serialize_legacy()).into_iter().rev().collect()
     }
 
     /// human-readable hexadecimal of the transaction hash
@@ -65,9 +128,36 @@ impl Tx {
         self.hash().encode_hex::<String>()
     }
 
-    /// returns the byte serialization of the transaction
+    /// binary hash of the serialization with witnesses, the txid's for transactions without any
+    pub fn witness_hash(&self) -> Vec<u8> {
+        hash256(&self.serialize()).into_iter().rev().collect()
+    }
+
+    /// human-readable hexadecimal of the witness hash
+    pub fn wtxid(&self) -> String {
+        self.witness_hash().encode_hex::<String>()
+    }
+
+    /// whether any input has a witness, which decides the serialization format
+    pub fn has_witness(&self) -> bool {
+        self.tx_ins.iter().any(|tx_in| !tx_in.witness.is_empty())
+    }
+
+    /// returns the byte serialization of the transaction, with the witnesses if there are any
     pub fn serialize(&self) -> Vec<u8> {
+        self.serialize_with(self.has_witness())
+    }
+
+    /// the serialization without witnesses, which the txid commits to
+    pub fn serialize_legacy(&self) -> Vec<u8> {
+        self.serialize_with(false)
+    }
+
+    fn serialize_with(&self, witness: bool) -> Vec<u8> {
         let mut result = int_to_little_endian(&BigInt::from(self.version), 4);
+        if witness {
+            result.extend_from_slice(&[SEGWIT_MARKER, SEGWIT_FLAG]);
+        }
 
         result.extend_from_slice(&encode_varint(&BigInt::from(self.tx_ins.len())));
         for tx_in in &self.tx_ins {
@@ -79,6 +169,12 @@ impl Tx {
             result.extend_from_slice(&tx_out.serialize());
         }
 
+        if witness {
+            for tx_in in &self.tx_ins {
+                result.extend_from_slice(&tx_in.serialize_witness());
+            }
+        }
+
         result.extend_from_slice(&int_to_little_endian(&self.locktime, 4));
 
         result
@@ -89,7 +185,7 @@ impl Tx {
         let mut output_sum = BigInt::from(0i32);
 
         for tx_in in &self.tx_ins {
-            input_sum = input_sum + tx_in.value(self.testnet);
+            input_sum = input_sum + tx_in.value(self.network);
         }
         for tx_out in &self.tx_outs {
             output_sum = output_sum + tx_out.amount.clone();
@@ -116,6 +212,134 @@ impl Tx {
         // the one input must have a previous index of ffffffff
         return self.tx_ins[0].prev_index == 0xffffffff;
     }
+
+    /// sigops in the scriptSigs and output scripts, counted the inaccurate way blocks always have
+    pub fn legacy_sigop_count(&self) -> usize {
+        let inputs: usize = self.tx_ins.iter().map(|tx_in| tx_in.script_sig.sigop_count(false)).sum();
+        let outputs: usize = self.tx_outs.iter().map(|tx_out| tx_out.script_pub_key.sigop_count(false)).sum();
+        inputs + outputs
+    }
+
+    /// sigops in the redeem scripts of the inputs spending P2SH outputs, `prev_outs` are the
+    /// outputs the inputs spend, in input order
+    pub fn p2sh_sigop_count(&self, prev_outs: &[TxOut]) -> usize {
+        self.tx_ins.iter()
+            .zip(prev_outs)
+            .filter(|(_, prev_out)| matches!(prev_out.script_pub_key.classify(), ScriptType::P2sh(_)))
+            .map(|(tx_in, prev_out)| prev_out.script_pub_key.p2sh_sigop_count(&tx_in.script_sig))
+            .sum()
+    }
+
+    /// signature hash of input `input_index` with the original algorithm, `script_code` is the
+    /// script being executed, which replaces the input's scriptSig, the other scriptSigs are
+    /// emptied and the hash type picks which other inputs and outputs are committed to
+    pub fn sig_hash(&self, input_index: usize, script_code: &Script, sighash_type: u8) -> BigInt {
+        let base_type = sighash_type & 0x1f;
+        let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+
+        // the original implementation returned one as an error here and signed it, so signatures
+        // of the number one stay valid for these inputs
+        if input_index >= self.tx_ins.len() || (base_type == SIGHASH_SINGLE && input_index >= self.tx_outs.len()) {
+            return sig_hash_one();
+        }
+
+        let cmds: Vec<ScriptCmd> = script_code.cmds.iter()
+            .filter(|cmd| !matches!(cmd, ScriptCmd::OpCode(OP_CODESEPARATOR)))
+            .cloned()
+            .collect();
+        let script_code = Script::new(Some(&cmds));
+
+        let mut tx_ins = vec![];
+        for (i, tx_in) in self.tx_ins.iter().enumerate() {
+            if anyone_can_pay && i != input_index {
+                continue;
+            }
+
+            let mut tx_in = TxIn::new(tx_in.prev_tx.clone(), tx_in.prev_index, None, tx_in.sequence);
+            if i == input_index {
+                tx_in.script_sig = script_code.clone();
+            } else if base_type == SIGHASH_NONE || base_type == SIGHASH_SINGLE {
+                // the other inputs can be updated
+                tx_in.sequence = 0;
+            }
+            tx_ins.push(tx_in);
+        }
+
+        let tx_outs = match base_type {
+            SIGHASH_NONE => vec![],
+            // outputs before the matching one are blanked to an amount of -1 and an empty script
+            SIGHASH_SINGLE => {
+                let blank = TxOut::new(&BigInt::from(u64::MAX), &Script::new(None));
+                let mut tx_outs = vec![blank; input_index];
+                tx_outs.push(self.tx_outs[input_index].clone());
+                tx_outs
+            },
+            _ => self.tx_outs.clone(),
+        };
+
+        let tx = Tx::new(self.version, tx_ins, tx_outs, self.locktime.clone(), self.network);
+        let mut serialization = tx.serialize_legacy();
+        serialization.extend_from_slice(&int_to_little_endian(&BigInt::from(sighash_type), 4));
+
+        BigInt::from_bytes_be(num_bigint::Sign::Plus, &hash256(&serialization))
+    }
+
+    /// hashPrevouts, hashSequence and hashOutputs of the whole transaction, the same for every
+    /// input so they're only hashed once
+    pub fn segwit_hashes(&self) -> SegwitHashes {
+        self.segwit_hashes.borrow_mut().get_or_insert_with(|| SegwitHashes::new(self)).clone()
+    }
+
+    /// BIP143 signature hash of segwit v0 input `input_index` spending `amount` satoshis,
+    /// `script_code` is the witness script, or the P2PKH script of a P2WPKH key hash
+    pub fn sig_hash_segwit_v0(&self, input_index: usize, script_code: &Script, amount: &BigInt, sighash_type: u8) -> BigInt {
+        let base_type = sighash_type & 0x1f;
+        let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+        let tx_in = &self.tx_ins[input_index];
+        let hashes = self.segwit_hashes();
+        let zero = vec![0u8; 32];
+
+        let hash_prevouts = if anyone_can_pay { &zero } else { &hashes.hash_prevouts };
+        let sign_all = base_type != SIGHASH_SINGLE && base_type != SIGHASH_NONE;
+        let hash_sequence = if !anyone_can_pay && sign_all { &hashes.hash_sequence } else { &zero };
+        let hash_outputs = if sign_all {
+            hashes.hash_outputs.clone()
+        } else if base_type == SIGHASH_SINGLE && input_index < self.tx_outs.len() {
+            hash256(&self.tx_outs[input_index].serialize())
+        } else {
+            zero.clone()
+        };
+
+        let mut preimage = int_to_little_endian(&BigInt::from(self.version), 4);
+        preimage.extend_from_slice(hash_prevouts);
+        preimage.extend_from_slice(hash_sequence);
+        preimage.extend_from_slice(&tx_in.serialize_outpoint());
+        preimage.extend_from_slice(&script_code.serialize());
+        preimage.extend_from_slice(&int_to_little_endian(amount, 8));
+        preimage.extend_from_slice(&int_to_little_endian(&BigInt::from(tx_in.sequence), 4));
+        preimage.extend_from_slice(&hash_outputs);
+        preimage.extend_from_slice(&int_to_little_endian(&self.locktime, 4));
+        preimage.extend_from_slice(&int_to_little_endian(&BigInt::from(sighash_type), 4));
+
+        BigInt::from_bytes_be(num_bigint::Sign::Plus, &hash256(&preimage))
+    }
+
+    /// BIP141 sigop cost, legacy and P2SH sigops weigh WITNESS_SCALE_FACTOR and witness sigops one
+    pub fn sigop_cost(&self, prev_outs: &[TxOut], flags: VerifyFlags) -> usize {
+        let mut cost = self.legacy_sigop_count() * WITNESS_SCALE_FACTOR;
+        if self.is_coinbase() {
+            return cost;
+        }
+
+        if flags.contains(VerifyFlags::P2SH) {
+            cost += self.p2sh_sigop_count(prev_outs) * WITNESS_SCALE_FACTOR;
+        }
+        for (tx_in, prev_out) in self.tx_ins.iter().zip(prev_outs) {
+            cost += witness_sigop_count(&tx_in.script_sig, &prev_out.script_pub_key, &tx_in.witness, flags);
+        }
+
+        cost
+    }
 }
 
 impl Display for Tx {
@@ -142,6 +366,128 @@ impl Display for Tx {
 }
 
 
+/// BIP143 hashes of the parts of a transaction every input's signature can commit to
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegwitHashes {
+    /// hash256 of every input's outpoint
+    pub hash_prevouts: Vec<u8>,
+    /// hash256 of every input's sequence
+    pub hash_sequence: Vec<u8>,
+    /// hash256 of every output
+    pub hash_outputs: Vec<u8>,
+}
+
+impl SegwitHashes {
+    pub fn new(tx: &Tx) -> Self {
+        let mut prevouts = vec![];
+        let mut sequences = vec![];
+        for tx_in in &tx.tx_ins {
+            prevouts.extend_from_slice(&tx_in.serialize_outpoint());
+            sequences.extend_from_slice(&int_to_little_endian(&BigInt::from(tx_in.sequence), 4));
+        }
+        let mut outputs = vec![];
+        for tx_out in &tx.tx_outs {
+            outputs.extend_from_slice(&tx_out.serialize());
+        }
+
+        Self {
+            hash_prevouts: hash256(&prevouts),
+            hash_sequence: hash256(&sequences),
+            hash_outputs: hash256(&outputs),
+        }
+    }
+}
+
+/// the hash 1 as it's laid out in memory, little-endian, read as a big-endian message
+fn sig_hash_one() -> BigInt {
+    BigInt::from(1) << 248
+}
+
+
+// -- TransactionChecker --
+
+/// checks the signatures and timelocks of one input of a transaction
+pub struct TransactionChecker<'a> {
+    tx: &'a Tx,
+    input_index: usize,
+    /// BIP341 hash of the input's SIGHASH_DEFAULT taproot signatures on the path being spent,
+    /// with no OP_CODESEPARATOR executed
+    z: BigInt,
+    /// satoshis of the output being spent, segwit signatures commit to it
+    amount: BigInt,
+}
+
+impl<'a> TransactionChecker<'a> {
+    pub fn new(tx: &'a Tx, input_index: usize, z: BigInt) -> Self {
+        Self {
+            tx,
+            input_index,
+            z,
+            amount: BigInt::from(0),
+        }
+    }
+
+    /// the amount of the output the input spends, needed to check segwit v0 signatures
+    pub fn with_amount(mut self, amount: BigInt) -> Self {
+        self.amount = amount;
+        self
+    }
+}
+
+impl<'a> SignatureChecker for TransactionChecker<'a> {
+    fn sig_hash(&self, script_code: &Script, sighash_type: u8, sig_version: SigVersion) -> BigInt {
+        match sig_version {
+            SigVersion::Base => self.tx.sig_hash(self.input_index, script_code, sighash_type),
+            SigVersion::WitnessV0 => self.tx.sig_hash_segwit_v0(self.input_index, script_code, &self.amount, sighash_type),
+            SigVersion::Tapscript => unreachable!("tapscript signatures are hashed by tap_sig_hash"),
+        }
+    }
+
+    /// only `z`, other sighash types or an executed OP_CODESEPARATOR can't be checked
+    fn tap_sig_hash(&self, sighash_type: u8, exec_data: &ExecData) -> Option<BigInt> {
+        if sighash_type != SIGHASH_DEFAULT || exec_data.codesep_pos != 0xffffffff {
+            return None;
+        }
+        Some(self.z.clone())
+    }
+
+    fn check_locktime(&self, locktime: i64) -> bool {
+        let tx_locktime = self.tx.locktime.to_i64().unwrap();
+        // both heights or both timestamps
+        if (tx_locktime < LOCKTIME_THRESHOLD as i64) != (locktime < LOCKTIME_THRESHOLD as i64) {
+            return false;
+        }
+        if locktime > tx_locktime {
+            return false;
+        }
+
+        // the locktime isn't enforced if the input is final
+        self.tx.tx_ins[self.input_index].sequence != SEQUENCE_FINAL
+    }
+
+    fn check_sequence(&self, sequence: i64) -> bool {
+        let tx_sequence = self.tx.tx_ins[self.input_index].sequence as i64;
+
+        // relative locktimes only apply from version 2 on and to inputs that don't disable them
+        if self.tx.version < 2 {
+            return false;
+        }
+        if tx_sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG as i64 != 0 {
+            return false;
+        }
+
+        let mask = (SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK) as i64;
+        let (tx_sequence, sequence) = (tx_sequence & mask, sequence & mask);
+        let threshold = SEQUENCE_LOCKTIME_TYPE_FLAG as i64;
+        if (tx_sequence < threshold) != (sequence < threshold) {
+            return false;
+        }
+
+        sequence <= tx_sequence
+    }
+}
+
+
 // -- TxIn --
 
 #[derive(Clone)]
@@ -153,6 +499,8 @@ pub struct TxIn {
     pub script_sig: Script,
     /// sequence, 4 bytes, LE
     pub sequence: u32,
+    /// witness stack, empty for inputs without one
+    pub witness: Vec<Vec<u8>>,
 }
 
 impl TxIn {
@@ -167,6 +515,7 @@ impl TxIn {
             prev_index,
             script_sig,
             sequence,
+            witness: vec![],
         }
     }
 
@@ -174,7 +523,7 @@ impl TxIn {
         let (num, b_read) = read_varint(&serialization[*bytes_read..]);
         *bytes_read += b_read;
 
-        let num = num.to_u32_digits().1[0];
+        let num = num.to_u32().unwrap();
         let mut tx_ins: Vec<Self> = vec![];
 
         for _ in 0..num  {
@@ -185,27 +534,27 @@ impl TxIn {
             // previous transaction index, 4 bytes
             let prev_index = &serialization[*bytes_read..(*bytes_read + 4)];
             let prev_index = BigInt::from_bytes_le(num_bigint::Sign::Plus, prev_index);
-            let prev_index = prev_index.to_u32_digits().1[0];
+            let prev_index = prev_index.to_u32().unwrap();
             *bytes_read += 4;
 
             // script sig, variant length, preceded by a varint
             let (script_sig_len, b_read) = read_varint(&serialization[*bytes_read..]);
-            *bytes_read += b_read;
-            let script_sig_len = script_sig_len.to_u32_digits().1[0] as usize;
-            let script_sig = &serialization[*bytes_read..(*bytes_read + script_sig_len)];
-            *bytes_read += script_sig_len;
+            let script_sig_len = script_sig_len.to_usize().unwrap();
+            let script_sig = &serialization[*bytes_read..(*bytes_read + b_read + script_sig_len)];
+            *bytes_read += b_read + script_sig_len;
 
             // sequence, 4 bytes
             let sequence = &serialization[*bytes_read..(*bytes_read + 4)];
             let sequence = BigInt::from_bytes_le(num_bigint::Sign::Plus, sequence);
-            let sequence = sequence.to_u32_digits().1[0];
+            let sequence = sequence.to_u32().unwrap();
             *bytes_read += 4;
 
             let tx_in = Self {
-                prev_tx: prev_tx_id.to_owned(),
+                prev_tx: prev_tx_id.iter().rev().copied().collect(),
                 prev_index,
                 script_sig: Script::parse(script_sig),
                 sequence,
+                witness: vec![],
             };
 
             tx_ins.push(tx_in);
@@ -213,33 +562,68 @@ impl TxIn {
         
         tx_ins
     }
+
+    /// parse one input's witness, a varint count of varint length prefixed items
+    pub fn parse_witness(serialization: &[u8], bytes_read: &mut usize) -> Vec<Vec<u8>> {
+        let (num, b_read) = read_varint(&serialization[*bytes_read..]);
+        *bytes_read += b_read;
+
+        let mut witness = vec![];
+        for _ in 0..num.to_usize().unwrap() {
+            let (len, b_read) = read_varint(&serialization[*bytes_read..]);
+            *bytes_read += b_read;
+            let len = len.to_usize().unwrap();
+            witness.push(serialization[*bytes_read..(*bytes_read + len)].to_owned());
+            *bytes_read += len;
+        }
+
+        witness
+    }
 }
 
 impl TxIn {
     /// returns the byte serialization of the transaction input
     pub fn serialize(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = self.prev_tx.clone().into_iter().rev().collect();
-        result.extend_from_slice(&int_to_little_endian(&BigInt::from(self.prev_index), 4));
+        let mut result = self.serialize_outpoint();
         result.extend_from_slice(&self.script_sig.serialize());
         result.extend_from_slice(&int_to_little_endian(&BigInt::from(self.sequence), 4));
 
         result
     }
 
+    /// the output being spent, previous transaction id in LE and index
+    pub fn serialize_outpoint(&self) -> Vec<u8> {
+        let mut result: Vec<u8> = self.prev_tx.clone().into_iter().rev().collect();
+        result.extend_from_slice(&int_to_little_endian(&BigInt::from(self.prev_index), 4));
+
+        result
+    }
+
+    /// returns the byte serialization of the witness stack
+    pub fn serialize_witness(&self) -> Vec<u8> {
+        let mut result = encode_varint(&BigInt::from(self.witness.len()));
+        for item in &self.witness {
+            result.extend_from_slice(&encode_varint(&BigInt::from(item.len())));
+            result.extend_from_slice(item);
+        }
+
+        result
+    }
+
     /// fetch transaction from http request
-    pub fn fetch_tx(&self, testnet: bool) -> Tx {
-        TxFetcher::fetch(&self.prev_tx, testnet, true)
+    pub fn fetch_tx(&self, network: Network) -> Tx {
+        TxFetcher::fetch(&self.prev_tx, network, true)
     }
 
     // fetch transaction from http request, and get output amount
-    pub fn value(&self, testnet: bool) -> BigInt {
-        let tx = self.fetch_tx(testnet);
+    pub fn value(&self, network: Network) -> BigInt {
+        let tx = self.fetch_tx(network);
         tx.tx_outs[self.prev_index as usize].amount.clone()
     }
 
     /// fetch transaction from http request, and get script
-    pub fn script_pubkey(&self, testnet: bool) -> Script {
-        let tx = self.fetch_tx(testnet);
+    pub fn script_pubkey(&self, network: Network) -> Script {
+        let tx = self.fetch_tx(network);
         tx.tx_outs[self.prev_index as usize].script_pub_key.clone()
     }
 }
@@ -270,7 +654,7 @@ impl TxOut {
         let (num, b_read) = read_varint(&serialization[*bytes_read..]);
         *bytes_read += b_read;
 
-        let num = num.to_u32_digits().1[0];
+        let num = num.to_u32().unwrap();
         let mut tx_outs: Vec<Self> = vec![];
 
         for _ in 0..num {
@@ -281,11 +665,10 @@ impl TxOut {
 
             // script pub key, varint
             let (script_pub_key_len, b_read) = read_varint(&serialization[*bytes_read..]);
-            let script_pub_key_len = script_pub_key_len.to_u32_digits().1[0] as usize;
-            *bytes_read += b_read;
+            let script_pub_key_len = script_pub_key_len.to_usize().unwrap();
 
-            let script_pub_key = &serialization[*bytes_read..(*bytes_read + script_pub_key_len)];
-            *bytes_read += script_pub_key_len;
+            let script_pub_key = &serialization[*bytes_read..(*bytes_read + b_read + script_pub_key_len)];
+            *bytes_read += b_read + script_pub_key_len;
 
             let tx_out = Self {
                 amount,
@@ -318,12 +701,25 @@ impl Display for TxOut {
 #[cfg(test)]
 mod tests_tx {
     use log::info;
-
-    use super::Tx;
+    use num_bigint::BigInt;
+    use hex::ToHex;
+
+    use crate::{
+        network::Network,
+        interpreter::{ScriptError, SignatureChecker, VerifyFlags, verify_script},
+        op::OP_CODESEPARATOR,
+        script::{Script, ScriptCmd},
+        utils::{hash160, hash256, sha256},
+    };
+
+    use super::{
+        Tx, TxIn, TxOut, TransactionChecker, SEQUENCE_FINAL, SIGHASH_ALL, SIGHASH_NONE, SIGHASH_SINGLE,
+        SIGHASH_ANYONECANPAY, sig_hash_one,
+    };
 
     #[test]
     fn test_parse_tx() {
-        env_logger::init();
+        let _ = env_logger::builder().is_test(true).try_init();
 
         let serialization = hex::decode("010000000456919960ac691763688d3d3bcea9ad6ecaf875df5339e148a1fc61c6ed7a069e010\
         000006a47304402204585bcdef85e6b1c6af5c2669d4830ff86e42dd205c0e089bc2a821657e951\
@@ -344,7 +740,232 @@ mod tests_tx {
         a87988ac005a6202000000001976a9143c82d7df364eb6c75be8c80df2b3eda8db57397088ac46\
         430600".to_string()).unwrap();
 
-        let tx = Tx::parse(&serialization, true);
+        let tx = Tx::parse(&serialization, Network::Testnet);
         info!("{}", tx);
+
+        assert_eq!(serialization, tx.serialize());
+        assert_eq!("ee51510d7bbabe28052038d1deb10c03ec74f06a79e21913c6fcf48d56217c87", tx.id());
+        assert_eq!(tx.id(), tx.wtxid());
+        assert_eq!("9e067aedc661fca148e13953df75f8ca6eada9ce3b3d8d68631769ac60999156", tx.tx_ins[0].prev_tx.encode_hex::<String>());
+        assert_eq!(1, tx.tx_ins[0].prev_index);
+        assert_eq!(4, tx.tx_ins.len());
+        assert_eq!(2, tx.tx_outs.len());
+    }
+
+    #[test]
+    fn test_parse_undecodable_scripts() {
+        // a coinbase scriptSig and a scriptPubKey ending in a push that runs past the end
+        let serialization = hex::decode("01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff03016a4cffffffff0100f2052a01000000024d0100000000").unwrap();
+        let tx = Tx::parse(&serialization, Network::Mainnet);
+
+        assert_eq!(serialization, tx.serialize());
+        assert_eq!(hash256(&serialization).into_iter().rev().collect::<Vec<u8>>(), tx.hash());
+        assert!(tx.is_coinbase());
+        assert_eq!("106 [error]", tx.tx_ins[0].script_sig.to_string());
+
+        // running them fails on the first undecodable byte
+        let empty = Script::new(None);
+        let z = BigInt::from(0);
+        assert_eq!(Err(ScriptError::BadOpcode(0x4c)), verify_script(&tx.tx_ins[0].script_sig, &empty, &[], &z, VerifyFlags::NONE));
+        assert_eq!(Err(ScriptError::BadOpcode(0x4d)), verify_script(&empty, &tx.tx_outs[0].script_pub_key, &[], &z, VerifyFlags::NONE));
+    }
+
+    #[test]
+    fn test_parse_segwit() {
+        // BIP143 native P2WPKH example, a P2PK input and a P2WPKH input
+        let serialization = hex::decode("01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000").unwrap();
+        let tx = Tx::parse(&serialization, Network::Mainnet);
+
+        assert!(tx.has_witness());
+        assert!(tx.tx_ins[0].witness.is_empty());
+        assert_eq!(2, tx.tx_ins[1].witness.len());
+        assert_eq!("025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee6357", tx.tx_ins[1].witness[1].encode_hex::<String>());
+        assert_eq!(serialization, tx.serialize());
+
+        // the legacy serialization leaves out the marker, flag and witnesses
+        let legacy = tx.serialize_legacy();
+        let witness_start = serialization.len() - 4 - (1 + 1 + 0x47 + 1 + 0x21) - 1;
+        assert_eq!(&serialization[..4], &legacy[..4]);
+        assert_eq!(&serialization[6..witness_start], &legacy[4..(legacy.len() - 4)]);
+        assert_eq!(&serialization[(serialization.len() - 4)..], &legacy[(legacy.len() - 4)..]);
+        assert_eq!(legacy, Tx::parse(&legacy, Network::Mainnet).serialize());
+
+        assert_eq!(hash256(&legacy).into_iter().rev().collect::<Vec<u8>>(), tx.hash());
+        assert_eq!(hash256(&serialization).into_iter().rev().collect::<Vec<u8>>(), tx.witness_hash());
+        assert_ne!(tx.id(), tx.wtxid());
+    }
+
+    #[test]
+    fn test_check_timelocks() {
+        let tx = |version: u32, locktime: u32, sequence: u32| {
+            let tx_in = TxIn::new(vec![0u8; 32], 0, None, sequence);
+            Tx::new(version, vec![tx_in], vec![], BigInt::from(locktime), Network::Mainnet)
+        };
+
+        let t = tx(2, 700_000, 0xfffffffe);
+        let checker = TransactionChecker::new(&t, 0, BigInt::from(0));
+        assert!(checker.check_locktime(700_000));
+        assert!(!checker.check_locktime(700_001));
+        // a timestamp doesn't compare against a height
+        assert!(!checker.check_locktime(500_000_000));
+
+        let t = tx(2, 700_000, SEQUENCE_FINAL);
+        assert!(!TransactionChecker::new(&t, 0, BigInt::from(0)).check_locktime(700_000));
+
+        let t = tx(2, 0, 144);
+        let checker = TransactionChecker::new(&t, 0, BigInt::from(0));
+        assert!(checker.check_sequence(144));
+        assert!(!checker.check_sequence(145));
+        assert!(!checker.check_sequence((1 << 22) | 1));
+
+        // relative locktimes need version 2
+        let t = tx(1, 0, 144);
+        assert!(!TransactionChecker::new(&t, 0, BigInt::from(0)).check_sequence(144));
+    }
+
+    #[test]
+    fn test_sigop_cost() {
+        let multisig = Script::from_asm("OP_1 02aa 02bb OP_2 OP_CHECKMULTISIG").unwrap();
+        let p2pkh = Script::p2pkh(&[0; 20]);
+        let p2sh = Script::p2sh(&hash160(&multisig.raw_serialize()));
+        let p2wsh = Script::p2wsh(&sha256(&multisig.raw_serialize()));
+        let flags = VerifyFlags::P2SH | VerifyFlags::WITNESS;
+
+        let spend = |script_sig: Script| TxIn::new(vec![1; 32], 0, Some(script_sig), SEQUENCE_FINAL);
+        let mut tx_ins = vec![
+            spend(Script::new(None)),
+            spend(Script::new(Some(&[ScriptCmd::Cmd(vec![]), ScriptCmd::Cmd(multisig.raw_serialize())]))),
+            spend(Script::new(None)),
+        ];
+        tx_ins[2].witness = vec![vec![], multisig.raw_serialize()];
+        let tx_outs = vec![TxOut::new(&BigInt::from(1000), &p2pkh), TxOut::new(&BigInt::from(1000), &multisig)];
+        let tx = Tx::new(2, tx_ins.clone(), tx_outs.clone(), BigInt::from(0), Network::Mainnet);
+        let prev_outs = vec![
+            TxOut::new(&BigInt::from(5000), &p2pkh),
+            TxOut::new(&BigInt::from(5000), &p2sh),
+            TxOut::new(&BigInt::from(5000), &p2wsh),
+        ];
+
+        // the bare multisig output counts 20 the inaccurate way, the P2SH redeem script 2
+        assert_eq!(21, tx.legacy_sigop_count());
+        assert_eq!(2, tx.p2sh_sigop_count(&prev_outs));
+        assert_eq!((21 + 2) * 4 + 2, tx.sigop_cost(&prev_outs, flags));
+        assert_eq!(21 * 4, tx.sigop_cost(&prev_outs, VerifyFlags::NONE));
+
+        tx_ins[2].witness.clear();
+        let tx = Tx::new(2, tx_ins, tx_outs, BigInt::from(0), Network::Mainnet);
+        assert_eq!(21 * 4 + 2 * 4, tx.sigop_cost(&prev_outs, flags));
+    }
+
+    #[test]
+    fn test_sig_hash() {
+        let serialization = hex::decode("0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600").unwrap();
+        let tx = Tx::parse(&serialization, Network::Mainnet);
+        assert_eq!("452c629d67e41baec3ac6f04fe744b4b9617f8f859c63b3002f8684e7a4fee03", tx.id());
+
+        let script_pubkey = Script::parse_raw(&hex::decode("76a914a802fc56c704ce87c42d7c92eb75e7896bdc41ae88ac").unwrap()).unwrap();
+        let z = BigInt::parse_bytes(b"27e0c5994dec7824e56dec6b2fcb342eb7cdb0d0957c2fce9882f715e85d81a6", 16).unwrap();
+        assert_eq!(z, tx.sig_hash(0, &script_pubkey, SIGHASH_ALL));
+
+        // the input's signature checks out against the hash
+        let checker = TransactionChecker::new(&tx, 0, BigInt::from(0));
+        assert_eq!(Ok(()), verify_script(&tx.tx_ins[0].script_sig, &script_pubkey, &[], &checker, VerifyFlags::STANDARD));
+        // the same key in another script is another hash
+        let sig = Script::new(Some(&tx.tx_ins[0].script_sig.cmds[..1]));
+        let p2pk = Script::p2pk(tx.tx_ins[0].script_sig.cmds[1].data().unwrap());
+        assert_eq!(Err(ScriptError::EvalFalse), verify_script(&sig, &p2pk, &[], &checker, VerifyFlags::NONE));
+
+        // OP_CODESEPARATOR never makes it into the hash
+        let with_separator = Script::new(Some(&[ScriptCmd::OpCode(OP_CODESEPARATOR)])) + script_pubkey.clone();
+        assert_eq!(z, tx.sig_hash(0, &with_separator, SIGHASH_ALL));
+    }
+
+    #[test]
+    fn test_sig_hash_types() {
+        let script_code = Script::p2pkh(&[0; 20]);
+        let tx_in = |n: u8, sequence: u32| TxIn::new(vec![n; 32], 0, Some(Script::new(Some(&[ScriptCmd::Cmd(vec![n])]))), sequence);
+        let tx_out = |amount: i64| TxOut::new(&BigInt::from(amount), &Script::p2pkh(&[1; 20]));
+        let tx = |tx_ins: Vec<TxIn>, tx_outs: Vec<TxOut>| Tx::new(1, tx_ins, tx_outs, BigInt::from(0), Network::Mainnet);
+
+        let base = tx(vec![tx_in(1, 0), tx_in(2, 0)], vec![tx_out(1000), tx_out(2000)]);
+        let other_outputs = tx(vec![tx_in(1, 0), tx_in(2, 0)], vec![tx_out(1000), tx_out(3000), tx_out(4000)]);
+        let other_input = tx(vec![tx_in(1, 0), tx_in(3, 5)], vec![tx_out(1000), tx_out(2000)]);
+        let other_sequence = tx(vec![tx_in(1, 0), tx_in(2, 5)], vec![tx_out(1000), tx_out(2000)]);
+        let same = |a: &Tx, b: &Tx, sighash_type: u8| a.sig_hash(0, &script_code, sighash_type) == b.sig_hash(0, &script_code, sighash_type);
+
+        // scriptSigs are never signed
+        let other_script_sig = tx(vec![tx_in(1, 0), TxIn::new(vec![2; 32], 0, None, 0)], vec![tx_out(1000), tx_out(2000)]);
+        assert!(same(&base, &other_script_sig, SIGHASH_ALL));
+
+        assert!(!same(&base, &other_outputs, SIGHASH_ALL));
+        assert!(!same(&base, &other_input, SIGHASH_ALL));
+        assert!(!same(&base, &other_sequence, SIGHASH_ALL));
+
+        assert!(same(&base, &other_outputs, SIGHASH_NONE));
+        assert!(same(&base, &other_sequence, SIGHASH_NONE));
+        assert!(!same(&base, &other_input, SIGHASH_NONE));
+
+        // only the output at the input's index
+        assert!(same(&base, &other_outputs, SIGHASH_SINGLE));
+        assert!(same(&base, &other_sequence, SIGHASH_SINGLE));
+        assert!(!same(&base, &tx(vec![tx_in(1, 0), tx_in(2, 0)], vec![tx_out(1500)]), SIGHASH_SINGLE));
+
+        assert!(same(&base, &other_input, SIGHASH_ALL | SIGHASH_ANYONECANPAY));
+        assert!(!same(&base, &other_outputs, SIGHASH_ALL | SIGHASH_ANYONECANPAY));
+        assert!(same(&base, &other_outputs, SIGHASH_NONE | SIGHASH_ANYONECANPAY));
+        // undefined types sign like SIGHASH_ALL, with the type byte still in the hash
+        assert!(!same(&base, &other_outputs, 0x04));
+        assert_ne!(base.sig_hash(0, &script_code, 0x04), base.sig_hash(0, &script_code, SIGHASH_ALL));
+
+        // SIGHASH_SINGLE without a matching output signs the number one
+        let one_output = tx(vec![tx_in(1, 0), tx_in(2, 0)], vec![tx_out(1000)]);
+        let one = BigInt::parse_bytes(b"0100000000000000000000000000000000000000000000000000000000000000", 16).unwrap();
+        assert_eq!(one, one_output.sig_hash(1, &script_code, SIGHASH_SINGLE));
+        assert_eq!(one, one_output.sig_hash(1, &script_code, SIGHASH_SINGLE | SIGHASH_ANYONECANPAY));
+        assert_ne!(one, one_output.sig_hash(0, &script_code, SIGHASH_SINGLE));
+        assert_eq!(one, one_output.sig_hash(2, &script_code, SIGHASH_ALL));
+    }
+
+    #[test]
+    fn test_sig_hash_segwit_v0() {
+        // BIP143 native P2WPKH example, the second input spends 6 BTC
+        let serialization = hex::decode("01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000").unwrap();
+        let tx = Tx::parse(&serialization, Network::Mainnet);
+        let amount = BigInt::from(600_000_000);
+
+        let hashes = tx.segwit_hashes();
+        assert_eq!("96b827c8483d4e9b96712b6713a7b68d6e8003a781feba36c31143470b4efd37", hashes.hash_prevouts.encode_hex::<String>());
+        assert_eq!("52b0a642eea2fb7ae638c36f6252b6750293dbe574a806984b8e4d8548339a3b", hashes.hash_sequence.encode_hex::<String>());
+        assert_eq!("863ef3e1a92afbfdb97f31ad0fc7683ee943e9abcf2501590ff8f6551f47e5e5", hashes.hash_outputs.encode_hex::<String>());
+
+        let key_hash = hex::decode("1d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap();
+        let z = BigInt::parse_bytes(b"c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670", 16).unwrap();
+        assert_eq!(z, tx.sig_hash_segwit_v0(1, &Script::p2pkh(&key_hash), &amount, SIGHASH_ALL));
+
+        // the witness signature checks out against the hash, and commits to the amount
+        let script_pubkey = Script::p2wpkh(&key_hash);
+        let flags = VerifyFlags::STANDARD;
+        let checker = TransactionChecker::new(&tx, 1, BigInt::from(0)).with_amount(amount.clone());
+        assert_eq!(Ok(()), verify_script(&tx.tx_ins[1].script_sig, &script_pubkey, &tx.tx_ins[1].witness, &checker, flags));
+        let checker = TransactionChecker::new(&tx, 1, BigInt::from(0)).with_amount(amount.clone() - 1);
+        assert_eq!(Err(ScriptError::NullFail), verify_script(&tx.tx_ins[1].script_sig, &script_pubkey, &tx.tx_ins[1].witness, &checker, flags));
+
+        // the hash types leave out the same parts as legacy signatures
+        let mut tx_ins = tx.tx_ins.clone();
+        tx_ins[0].prev_index = 5;
+        tx_ins[0].sequence = 0;
+        let other_input = Tx::new(tx.version, tx_ins, tx.tx_outs.clone(), tx.locktime.clone(), tx.network);
+        let other_outputs = Tx::new(tx.version, tx.tx_ins.clone(), tx.tx_outs[..1].to_vec(), tx.locktime.clone(), tx.network);
+        let script_code = Script::p2pkh(&key_hash);
+        let same = |a: &Tx, b: &Tx, sighash_type: u8| {
+            a.sig_hash_segwit_v0(1, &script_code, &amount, sighash_type) == b.sig_hash_segwit_v0(1, &script_code, &amount, sighash_type)
+        };
+        assert!(!same(&tx, &other_input, SIGHASH_ALL));
+        assert!(!same(&tx, &other_outputs, SIGHASH_ALL));
+        assert!(same(&tx, &other_input, SIGHASH_ALL | SIGHASH_ANYONECANPAY));
+        assert!(same(&tx, &other_outputs, SIGHASH_NONE));
+        // the output at index 1 is gone, SINGLE signs no outputs, unlike the legacy bug
+        assert!(!same(&tx, &other_outputs, SIGHASH_SINGLE));
+        assert_ne!(sig_hash_one(), other_outputs.sig_hash_segwit_v0(1, &script_code, &amount, SIGHASH_SINGLE));
     }
 }
@@ -5,7 +5,7 @@ use log::info;
 use num_bigint::BigInt;
 use serde::{Serialize, Deserialize};
 
-use crate::{transaction::{Tx, TxIn, TxOut}, vec_with_init_val, script::Script};
+use crate::{network::Network, transaction::{Tx, TxIn, TxOut}, vec_with_init_val, script::Script};
 
 
 /*
@@ -20,16 +20,19 @@ pub struct TxFetcher {
 
 impl TxFetcher {
 
-    pub fn get_url(testnet: bool) -> String {
-        if testnet {
-            return "https://blockstream.info/testnet/api/tx/".to_string();
-        } else {
-            return "https://blockstream.info/api/tx/".to_string();
+    pub fn get_url(network: Network) -> String {
+        match network {
+            Network::Mainnet => "https://blockstream.info/api/tx/".to_string(),
+            Network::Testnet => "https://blockstream.info/testnet/api/tx/".to_string(),
+            Network::Testnet4 => "https://mempool.space/testnet4/api/tx/".to_string(),
+            Network::Signet => "https://mempool.space/signet/api/tx/".to_string(),
+            // regtest has no public explorer, expect a local esplora instance
+            Network::Regtest => "http://localhost:3002/tx/".to_string(),
         }
     }
 
-    pub fn fetch(tx_id: &[u8], testnet: bool, fresh: bool) -> Tx {
-        let url = format!("{}{}", TxFetcher::get_url(testnet), tx_id.encode_hex::<String>());
+    pub fn fetch(tx_id: &[u8], network: Network, fresh: bool) -> Tx {
+        let url = format!("{}{}", TxFetcher::get_url(network), tx_id.encode_hex::<String>());
         let resp = reqwest::blocking::get(url).unwrap();
         let resp = resp.json::<TxResponse>().unwrap();
 
@@ -52,7 +55,7 @@ impl TxFetcher {
             );
         }
 
-        Tx::new(resp.version, tx_ins, tx_outs, BigInt::from(resp.locktime), testnet)
+        Tx::new(resp.version, tx_ins, tx_outs, BigInt::from(resp.locktime), network)
     }
 }
 
@@ -92,6 +95,8 @@ struct TxOutResponse {
 
 #[cfg(test)]
 mod tests_tx_fetcher {
+    use crate::network::Network;
+
     use super::TxFetcher;
 
     fn init() {
@@ -103,6 +108,6 @@ mod tests_tx_fetcher {
         init();
 
         let tx_id = "3cac64f933aaa5d2a80ec6d50a774309284b7c68d7d026cb68c7ccc4cd56a07f".to_string();
-        TxFetcher::fetch(&hex::decode(tx_id).unwrap(), false, true);
+        TxFetcher::fetch(&hex::decode(tx_id).unwrap(), Network::Mainnet, true);
     }
 }
@@ -1,6 +1,7 @@
 use num_bigint::BigInt;
 use num_traits::ToPrimitive;
 use ripemd::Ripemd160;
+use sha1::Sha1;
 use sha2::{Sha256, Digest};
 use hex::ToHex;
 
@@ -59,6 +60,48 @@ pub fn encode_base58_checksum(b: &[u8]) -> Vec<u8> {
     encode_base58(&b)
 }
 
+/// decode base58 characters to bytes, returns None on a character outside the alphabet
+pub fn decode_base58(s: &[u8]) -> Option<Vec<u8>> {
+    let base58_alphabet = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+    let mut count = 0;
+    for c in s {
+        if *c == b'1' {
+            count += 1;
+        } else {
+            break;
+        }
+    }
+
+    let mut num = BigInt::from(0i32);
+    for c in s {
+        let digit = base58_alphabet.iter().position(|a| a == c)?;
+        num = num * BigInt::from(58i32) + BigInt::from(digit);
+    }
+
+    let mut result: Vec<u8> = vec![0u8; count];
+    if num > BigInt::from(0i32) {
+        result.extend_from_slice(&num.to_bytes_be().1);
+    }
+
+    Some(result)
+}
+
+/// decode base58 with a 4 bytes checksum suffix, returns the payload if the checksum matches
+pub fn decode_base58_checksum(s: &[u8]) -> Option<Vec<u8>> {
+    let b = decode_base58(s)?;
+    if b.len() < 4 {
+        return None;
+    }
+
+    let (payload, checksum) = b.split_at(b.len() - 4);
+    if hash256(payload)[..4] != *checksum {
+        return None;
+    }
+
+    Some(payload.to_owned())
+}
+
 /// two rounds of sha256
 pub fn hash256(s: &[u8]) -> Vec<u8> {
     let mut hasher = Sha256::new();
@@ -83,6 +126,34 @@ pub fn hash160(s: &[u8]) -> Vec<u8> {
     s1.as_slice().to_owned()
 }
 
+pub fn sha256(s: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(s);
+    hasher.finalize().as_slice().to_owned()
+}
+
+pub fn ripemd160(s: &[u8]) -> Vec<u8> {
+    let mut hasher = Ripemd160::new();
+    hasher.update(s);
+    hasher.finalize().as_slice().to_owned()
+}
+
+pub fn sha1(s: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha1::new();
+    hasher.update(s);
+    hasher.finalize().as_slice().to_owned()
+}
+
+/// BIP340 tagged hash, sha256(sha256(tag) || sha256(tag) || msg)
+pub fn tagged_hash(tag: &str, msg: &[u8]) -> Vec<u8> {
+    let tag_hash = sha256(tag.as_bytes());
+    let mut hasher = Sha256::new();
+    hasher.update(&tag_hash);
+    hasher.update(&tag_hash);
+    hasher.update(msg);
+    hasher.finalize().as_slice().to_owned()
+}
+
 /// convert BigInt to little-endian bytes
 pub fn int_to_little_endian(n: &BigInt, len: usize) -> Vec<u8> {
     let n = n.clone();
@@ -96,6 +167,14 @@ pub fn int_to_little_endian(n: &BigInt, len: usize) -> Vec<u8> {
     n_bytes
 }
 
+/// convert BigInt to big-endian bytes, zero padded in front
+pub fn int_to_big_endian(n: &BigInt, len: usize) -> Vec<u8> {
+    let mut n_bytes = int_to_little_endian(n, len);
+    n_bytes.reverse();
+
+    n_bytes
+}
+
 /// convert little-endian bytes to BigInt
 pub fn little_endian_to_int(b: &[u8]) -> BigInt {
     BigInt::from_bytes_le(num_bigint::Sign::Plus, b)